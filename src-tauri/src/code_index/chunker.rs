@@ -0,0 +1,156 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// 单个代码片段的最大行数，超出时按窗口切分
+const MAX_CHUNK_LINES: usize = 120;
+/// 按窗口切分时的窗口大小及重叠行数
+const WINDOW_LINES: usize = 80;
+const WINDOW_OVERLAP: usize = 10;
+/// 少于该行数的片段会合并到前一个片段中
+const MIN_CHUNK_LINES: usize = 5;
+
+/// 顶层声明（类、接口、函数等），缩进不超过一级
+static DECLARATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^\s{0,4}(?:(?:public|private|protected|internal|static|final|abstract|export|default|async|pub(?:\([^)]*\))?|open|override|data|sealed|suspend|unsafe)\s+)*(?:class|interface|enum|record|struct|trait|impl|fn|func|def|function|object|type|mod)\s+(?:<[^>]*>\s*)?(?:\([^)]*\)\s*)?([A-Za-z_][A-Za-z0-9_]*)",
+    )
+    .unwrap()
+});
+
+/// 带访问修饰符的方法声明（Java/Kotlin/C#等）
+static METHOD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s{2,8}(?:public|private|protected)\s[^=;]*?([A-Za-z_][A-Za-z0-9_]*)\s*\(")
+        .unwrap()
+});
+
+#[derive(Debug, Clone)]
+pub struct CodeChunk {
+    pub symbol: Option<String>,
+    /// 起止行号，从1开始
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+}
+
+/// 将源码按顶层声明切分为代码片段，过长的片段再按固定窗口切分
+pub fn chunk_source(content: &str) -> Vec<CodeChunk> {
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    // 每个片段的起始行及其符号
+    let mut boundaries: Vec<(usize, Option<String>)> = vec![(0, None)];
+    for (index, line) in lines.iter().enumerate() {
+        let symbol = DECLARATION
+            .captures(line)
+            .or_else(|| METHOD.captures(line))
+            .map(|caps| caps[1].to_string());
+        if let Some(symbol) = symbol {
+            if index == 0 {
+                boundaries[0].1 = Some(symbol);
+            } else {
+                boundaries.push((index, Some(symbol)));
+            }
+        }
+    }
+
+    let mut segments: Vec<(usize, usize, Option<String>)> = Vec::new();
+    for (i, (start, symbol)) in boundaries.iter().enumerate() {
+        let end = boundaries.get(i + 1).map_or(lines.len(), |b| b.0);
+        match segments.last_mut() {
+            Some(last) if end - start < MIN_CHUNK_LINES => {
+                last.1 = end;
+                if last.2.is_none() {
+                    last.2 = symbol.clone();
+                }
+            }
+            _ => segments.push((*start, end, symbol.clone())),
+        }
+    }
+
+    let mut chunks = Vec::new();
+    for (start, end, symbol) in segments {
+        if end - start <= MAX_CHUNK_LINES {
+            chunks.push(make_chunk(&lines, start, end, symbol));
+            continue;
+        }
+        let mut window_start = start;
+        while window_start < end {
+            let window_end = (window_start + WINDOW_LINES).min(end);
+            chunks.push(make_chunk(&lines, window_start, window_end, symbol.clone()));
+            if window_end == end {
+                break;
+            }
+            window_start = window_end - WINDOW_OVERLAP;
+        }
+    }
+    chunks.retain(|c| !c.content.trim().is_empty());
+    chunks
+}
+
+fn make_chunk(lines: &[&str], start: usize, end: usize, symbol: Option<String>) -> CodeChunk {
+    CodeChunk {
+        symbol,
+        start_line: start + 1,
+        end_line: end,
+        content: lines[start..end].join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count)
+            .map(|i| format!("    x{} = {};", i, i))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn splits_at_top_level_declarations() {
+        let source = format!(
+            "package demo;\n\npublic class UserService {{\n{}\n    public User find(long id) {{\n{}\n    }}\n}}\n",
+            numbered_lines(6),
+            numbered_lines(6)
+        );
+        let chunks = chunk_source(&source);
+        let symbols: Vec<Option<&str>> = chunks.iter().map(|c| c.symbol.as_deref()).collect();
+        // 第一个声明之前的内容单独作为一个片段
+        assert_eq!(symbols, vec![None, Some("UserService"), Some("find")]);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 2));
+        assert_eq!(chunks[2].start_line, chunks[1].end_line + 1);
+        assert_eq!(chunks[2].end_line, source.lines().count());
+    }
+
+    #[test]
+    fn merges_short_segments_into_previous() {
+        let source = format!(
+            "fn first() {{\n{}\n}}\nfn tiny() {{}}\nfn last() {{\n{}\n}}\n",
+            numbered_lines(5),
+            numbered_lines(5)
+        );
+        let chunks = chunk_source(&source);
+        let symbols: Vec<Option<&str>> = chunks.iter().map(|c| c.symbol.as_deref()).collect();
+        assert_eq!(symbols, vec![Some("first"), Some("last")]);
+        assert!(chunks[0].content.contains("fn tiny()"));
+    }
+
+    #[test]
+    fn splits_long_segments_into_overlapping_windows() {
+        let source = format!("fn long() {{\n{}\n}}", numbered_lines(200));
+        let chunks = chunk_source(&source);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.symbol.as_deref() == Some("long")));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 80));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (71, 150));
+        assert_eq!((chunks[2].start_line, chunks[2].end_line), (141, 202));
+    }
+
+    #[test]
+    fn skips_blank_content() {
+        assert!(chunk_source("").is_empty());
+        assert!(chunk_source("\n\n   \n").is_empty());
+    }
+}
@@ -0,0 +1,473 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    function::file::{ignore_aware_walker, load_ignore_patterns},
+    llm::embedding::{build_embedder, AIEmbedder},
+    storage::code_index::{
+        count_code_chunks, delete_indexed_path, get_indexed_files, load_code_chunks,
+        save_file_chunks, IndexedFile, NewCodeChunk, StoredCodeChunk,
+    },
+};
+
+pub mod chunker;
+
+use chunker::chunk_source;
+
+/// 参与索引的源码文件扩展名
+const INDEXABLE_EXTENSIONS: &[&str] = &[
+    "java",
+    "kt",
+    "kts",
+    "scala",
+    "groovy",
+    "ts",
+    "tsx",
+    "js",
+    "jsx",
+    "vue",
+    "go",
+    "rs",
+    "py",
+    "rb",
+    "php",
+    "cs",
+    "c",
+    "h",
+    "cpp",
+    "hpp",
+    "swift",
+    "sql",
+    "xml",
+    "yml",
+    "yaml",
+    "properties",
+    "toml",
+    "gradle",
+];
+/// 超过该大小的文件不参与索引（多为生成代码或数据文件）
+const MAX_FILE_SIZE: u64 = 256 * 1024;
+/// 每次请求向量模型的片段数量
+const EMBED_BATCH_SIZE: usize = 16;
+/// 提交给向量模型的单个片段最大字符数
+const MAX_EMBED_CHARS: usize = 4000;
+/// 自动检索时默认返回的片段数量
+pub const DEFAULT_TOP_K: usize = 8;
+/// 连续多个文件索引失败时多为向量模型不可用，不再继续
+const MAX_CONSECUTIVE_FAILURES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum IndexState {
+    Idle,
+    Indexing,
+    Ready,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStatus {
+    pub root: Option<String>,
+    pub state: IndexState,
+    #[serde(rename = "processedFiles")]
+    pub processed_files: usize,
+    #[serde(rename = "totalFiles")]
+    pub total_files: usize,
+    /// 索引失败而跳过的文件数，error为最近一次的错误
+    #[serde(rename = "failedFiles")]
+    pub failed_files: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeSearchHit {
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    pub symbol: Option<String>,
+    #[serde(rename = "startLine")]
+    pub start_line: i64,
+    #[serde(rename = "endLine")]
+    pub end_line: i64,
+    pub content: String,
+    pub score: f32,
+}
+
+static INDEX_STATUS: Lazy<Mutex<IndexStatus>> = Lazy::new(|| {
+    Mutex::new(IndexStatus {
+        root: None,
+        state: IndexState::Idle,
+        processed_files: 0,
+        total_files: 0,
+        failed_files: 0,
+        error: None,
+    })
+});
+
+/// 已加载的项目及其全部代码片段
+type CachedChunks = (String, Arc<Vec<StoredCodeChunk>>);
+
+/// 检索时使用的代码片段及向量，索引变化时清除后重新加载
+static CHUNK_CACHE: Lazy<Mutex<Option<CachedChunks>>> = Lazy::new(|| Mutex::new(None));
+/// 索引每次变化时加一，加载期间索引发生变化时不缓存加载的结果
+static INDEX_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// 保证同一时间只有一个索引任务在写入
+static INDEX_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+pub fn get_index_status() -> IndexStatus {
+    INDEX_STATUS.lock().unwrap().clone()
+}
+
+fn update_status(f: impl FnOnce(&mut IndexStatus)) {
+    f(&mut INDEX_STATUS.lock().unwrap());
+}
+
+fn record_failure(path: &Path, e: &anyhow::Error) {
+    warn!("索引文件失败，已跳过: {}, 错误: {}", path.display(), e);
+    update_status(|s| {
+        s.failed_files += 1;
+        s.error = Some(format!("{}: {}", path.display(), e));
+    });
+}
+
+fn invalidate_chunk_cache() {
+    INDEX_GENERATION.fetch_add(1, Ordering::SeqCst);
+    *CHUNK_CACHE.lock().unwrap() = None;
+}
+
+/// 在后台为项目建立（或增量更新）代码索引
+pub fn spawn_index_build(root: PathBuf) {
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = build_index(&root).await {
+            error!("构建代码索引失败: {}", e);
+            update_status(|s| {
+                s.state = IndexState::Failed;
+                s.error = Some(e.to_string());
+            });
+        }
+    });
+}
+
+/// 打开项目时若数据库中已有该项目的索引，则在后台增量同步一次，使索引恢复为就绪状态
+pub fn resume_index(root: PathBuf) {
+    tauri::async_runtime::spawn(async move {
+        match count_code_chunks(&root.to_string_lossy()).await {
+            Ok(0) => {}
+            Ok(_) => spawn_index_build(root),
+            Err(e) => warn!("读取已有代码索引失败: {}", e),
+        }
+    });
+}
+
+/// 项目文件发生变化时增量更新索引，仅在该项目索引已就绪或正在同步时生效
+pub fn notify_changed(paths: Vec<PathBuf>) {
+    if !matches!(
+        get_index_status().state,
+        IndexState::Ready | IndexState::Indexing
+    ) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = update_paths(paths).await {
+            warn!("增量更新代码索引失败: {}", e);
+        }
+    });
+}
+
+/// 同步项目下的全部文件：新增或内容变化的文件重新切分并生成向量，已删除的文件移除索引
+pub async fn build_index(root: &Path) -> Result<()> {
+    let _guard = INDEX_LOCK.lock().await;
+    let root_str = root.to_string_lossy().to_string();
+    update_status(|s| {
+        s.root = Some(root_str.clone());
+        s.state = IndexState::Indexing;
+        s.processed_files = 0;
+        s.total_files = 0;
+        s.failed_files = 0;
+        s.error = None;
+    });
+    let embedder = build_embedder().await?;
+    let excludes = load_ignore_patterns().await?;
+    let files = collect_indexable_files(root, &excludes)?;
+    let indexed = get_indexed_files(&root_str).await?;
+    update_status(|s| s.total_files = files.len());
+
+    let current: HashSet<String> = files
+        .iter()
+        .map(|f| f.to_string_lossy().to_string())
+        .collect();
+    for stale in indexed.keys().filter(|p| !current.contains(*p)) {
+        delete_indexed_path(stale).await?;
+        invalidate_chunk_cache();
+    }
+    // 单个文件失败（如向量接口偶发错误）时跳过该文件，下次同步时重试
+    let mut consecutive_failures = 0;
+    for file in &files {
+        match index_file(embedder.as_ref(), &root_str, file, &indexed).await {
+            Ok(_) => consecutive_failures = 0,
+            Err(e) => {
+                record_failure(file, &e);
+                consecutive_failures += 1;
+                if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                    return Err(anyhow!("连续{}个文件索引失败: {}", consecutive_failures, e));
+                }
+            }
+        }
+        update_status(|s| s.processed_files += 1);
+    }
+    update_status(|s| s.state = IndexState::Ready);
+    let failed = get_index_status().failed_files;
+    info!(
+        "代码索引构建完成: {}, 共{}个文件, 失败{}个",
+        root_str,
+        files.len(),
+        failed
+    );
+    Ok(())
+}
+
+async fn update_paths(paths: Vec<PathBuf>) -> Result<()> {
+    let _guard = INDEX_LOCK.lock().await;
+    let Some(root) = get_index_status().root else {
+        return Ok(());
+    };
+    let root_path = PathBuf::from(&root);
+    let embedder = build_embedder().await?;
+    let indexed = get_indexed_files(&root).await?;
+    for path in paths.iter().filter(|p| p.starts_with(&root_path)) {
+        if path.is_file() {
+            if is_indexable(path) {
+                if let Err(e) = index_file(embedder.as_ref(), &root, path, &indexed).await {
+                    record_failure(path, &e);
+                }
+            }
+        } else if !path.exists() {
+            delete_indexed_path(&path.to_string_lossy()).await?;
+            invalidate_chunk_cache();
+        }
+    }
+    Ok(())
+}
+
+/// 为单个文件建立索引，内容与向量模型均未变化时跳过；无法读取的文件记录警告后跳过
+async fn index_file(
+    embedder: &dyn AIEmbedder,
+    root: &str,
+    path: &Path,
+    indexed: &HashMap<String, IndexedFile>,
+) -> Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("读取文件失败，跳过索引: {}, 错误: {}", path.display(), e);
+            return Ok(());
+        }
+    };
+    let path_str = path.to_string_lossy().to_string();
+    let content_hash = hash_content(&content);
+    if let Some(record) = indexed.get(&path_str) {
+        if record.content_hash == content_hash && record.model == embedder.model_id() {
+            return Ok(());
+        }
+    }
+    let relative_path = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let chunks = chunk_source(&content);
+    let mut new_chunks = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(EMBED_BATCH_SIZE) {
+        let texts: Vec<String> = batch
+            .iter()
+            .map(|c| {
+                let text = format!(
+                    "{}\n{}\n{}",
+                    relative_path,
+                    c.symbol.as_deref().unwrap_or(""),
+                    c.content
+                );
+                text.chars().take(MAX_EMBED_CHARS).collect()
+            })
+            .collect();
+        let embeddings = embedder.embed(texts).await?;
+        if embeddings.len() != batch.len() {
+            return Err(anyhow!("向量模型返回的结果数量与输入不一致"));
+        }
+        for (chunk, embedding) in batch.iter().zip(embeddings) {
+            new_chunks.push(NewCodeChunk {
+                symbol: chunk.symbol.clone(),
+                start_line: chunk.start_line as i64,
+                end_line: chunk.end_line as i64,
+                content: chunk.content.clone(),
+                embedding,
+            });
+        }
+    }
+    save_file_chunks(
+        root,
+        &path_str,
+        &content_hash,
+        embedder.model_id(),
+        &new_chunks,
+    )
+    .await?;
+    invalidate_chunk_cache();
+    Ok(())
+}
+
+/// 检索与问题最相关的代码片段
+pub async fn search(root: &str, query: &str, top_k: usize) -> Result<Vec<CodeSearchHit>> {
+    let chunks = cached_chunks(root).await?;
+    if chunks.is_empty() {
+        return Err(anyhow!("当前项目尚未建立代码索引，请先构建索引"));
+    }
+    let embedder = build_embedder().await?;
+    let query_vec = embedder
+        .embed(vec![query.to_string()])
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("向量模型未返回结果"))?;
+    Ok(rank_chunks(&query_vec, &chunks, top_k))
+}
+
+/// 项目的全部代码片段及向量，首次检索时从数据库加载，之后复用直到索引变化
+async fn cached_chunks(root: &str) -> Result<Arc<Vec<StoredCodeChunk>>> {
+    if let Some((cached_root, chunks)) = CHUNK_CACHE.lock().unwrap().as_ref() {
+        if cached_root == root {
+            return Ok(chunks.clone());
+        }
+    }
+    let generation = INDEX_GENERATION.load(Ordering::SeqCst);
+    let chunks = Arc::new(load_code_chunks(root).await?);
+    let mut cache = CHUNK_CACHE.lock().unwrap();
+    if INDEX_GENERATION.load(Ordering::SeqCst) == generation {
+        *cache = Some((root.to_string(), chunks.clone()));
+    }
+    Ok(chunks)
+}
+
+/// 按与问题向量的余弦相似度排序，只复制最相关的片段
+fn rank_chunks(query: &[f32], chunks: &[StoredCodeChunk], top_k: usize) -> Vec<CodeSearchHit> {
+    let mut scored: Vec<(f32, &StoredCodeChunk)> = chunks
+        .iter()
+        .map(|chunk| (cosine_similarity(query, &chunk.embedding), chunk))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored
+        .into_iter()
+        .take(top_k)
+        .map(|(score, chunk)| CodeSearchHit {
+            score,
+            file_path: chunk.file_path.clone(),
+            symbol: chunk.symbol.clone(),
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            content: chunk.content.clone(),
+        })
+        .collect()
+}
+
+fn collect_indexable_files(root: &Path, excludes: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in ignore_aware_walker(root, excludes)?.build() {
+        match entry {
+            Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => {
+                if is_indexable(entry.path()) {
+                    files.push(entry.into_path());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("遍历目录时跳过无法读取的条目: {}", e),
+        }
+    }
+    Ok(files)
+}
+
+/// 是否为可索引的源码文件：扩展名受支持且文件大小不超过上限
+pub fn is_indexable(path: &Path) -> bool {
+    let supported = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| INDEXABLE_EXTENSIONS.contains(&ext.as_str()));
+    supported && fs::metadata(path).is_ok_and(|m| m.len() <= MAX_FILE_SIZE)
+}
+
+/// 内容摘要保存在数据库中，使用与Rust版本无关的SHA-256
+fn hash_content(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(path: &str, embedding: Vec<f32>) -> StoredCodeChunk {
+        StoredCodeChunk {
+            file_path: path.to_string(),
+            symbol: None,
+            start_line: 1,
+            end_line: 1,
+            content: String::new(),
+            embedding,
+        }
+    }
+
+    #[test]
+    fn cosine_similarity_handles_degenerate_vectors() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn ranks_chunks_by_similarity() {
+        let chunks = vec![
+            chunk("far.rs", vec![0.0, 1.0]),
+            chunk("near.rs", vec![1.0, 0.1]),
+            chunk("exact.rs", vec![2.0, 0.0]),
+            chunk("bad.rs", vec![1.0]),
+        ];
+        let hits = rank_chunks(&[1.0, 0.0], &chunks, 2);
+        let paths: Vec<&str> = hits.iter().map(|h| h.file_path.as_str()).collect();
+        assert_eq!(paths, vec!["exact.rs", "near.rs"]);
+        assert!(hits[0].score >= hits[1].score);
+    }
+
+    #[test]
+    fn content_hash_is_stable() {
+        assert_eq!(
+            hash_content("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
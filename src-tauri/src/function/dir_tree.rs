@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::Deserialize;
+
+use super::file::ignore_aware_walker;
+use crate::storage::sys_config::get_config;
+
+/// 包路径所在的源码根目录名称，其下的单链目录使用"."合并（com/example/foo → com.example.foo）
+const PACKAGE_ROOT_NAMES: &[&str] = &["java", "kotlin", "scala", "groovy"];
+
+/// 目录结构生成选项，对应sys_config中的dir_structure_options（JSON）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DirTreeOptions {
+    /// 最大遍历深度（相对于根目录）
+    #[serde(rename = "maxDepth")]
+    pub max_depth: usize,
+    /// 输出的最大条目数，超出部分会被省略
+    #[serde(rename = "maxEntries")]
+    pub max_entries: usize,
+    /// 是否为最相关的目录列出文件名
+    #[serde(rename = "includeFiles")]
+    pub include_files: bool,
+    /// 列出文件名的相关目录数量上限
+    #[serde(rename = "maxRelevantDirs")]
+    pub max_relevant_dirs: usize,
+    /// 每个相关目录列出的文件数量上限
+    #[serde(rename = "maxFilesPerDir")]
+    pub max_files_per_dir: usize,
+}
+
+impl Default for DirTreeOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 12,
+            max_entries: 300,
+            include_files: true,
+            max_relevant_dirs: 5,
+            max_files_per_dir: 30,
+        }
+    }
+}
+
+/// 用于判断目录相关性的线索：被引用文件的路径及问题中的关键词
+#[derive(Debug, Default)]
+pub struct DirTreeFocus {
+    pub paths: Vec<PathBuf>,
+    pub keywords: Vec<String>,
+}
+
+impl DirTreeFocus {
+    /// 从文本中提取关键词（按非字母数字字符切分，忽略过短的词）
+    pub fn add_keywords_from(&mut self, text: &str) {
+        for word in text.split(|c: char| !c.is_ascii_alphanumeric()) {
+            let word = word.to_lowercase();
+            if word.len() >= 3 && !self.keywords.contains(&word) {
+                self.keywords.push(word);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct DirNode {
+    children: BTreeMap<String, DirNode>,
+    files: Vec<String>,
+}
+
+impl DirNode {
+    fn get_or_create(&mut self, rel_path: &Path) -> &mut DirNode {
+        rel_path.components().fold(self, |node, comp| {
+            let name = comp.as_os_str().to_string_lossy().to_string();
+            node.children.entry(name).or_default()
+        })
+    }
+}
+
+pub async fn load_dir_tree_options() -> Result<DirTreeOptions> {
+    match get_config("dir_structure_options".to_string()).await? {
+        Some(conf) => {
+            serde_json::from_str(&conf).map_err(|e| anyhow!("目录结构选项配置格式错误: {}", e))
+        }
+        None => Ok(DirTreeOptions::default()),
+    }
+}
+
+/// 基于指定的根目录生成目录结构树状图文本，遵循忽略规则并限制深度与条目数量
+pub fn render_directory_tree(
+    root_dir: &Path,
+    excludes: &[String],
+    options: &DirTreeOptions,
+    focus: &DirTreeFocus,
+) -> Result<String> {
+    let mut root = DirNode::default();
+    let walker = ignore_aware_walker(root_dir, excludes)?
+        .max_depth(Some(options.max_depth))
+        .build();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("遍历目录时跳过无法读取的条目: {}", e);
+                continue;
+            }
+        };
+        let Ok(relative_path) = entry.path().strip_prefix(root_dir) else {
+            continue;
+        };
+        if relative_path.as_os_str().is_empty() {
+            continue;
+        }
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        if is_dir {
+            root.get_or_create(relative_path);
+        } else if options.include_files {
+            let parent = relative_path.parent().unwrap_or(Path::new(""));
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            root.get_or_create(parent).files.push(file_name);
+        }
+    }
+
+    let relevant = if options.include_files {
+        find_relevant_dirs(&root, root_dir, options, focus)
+    } else {
+        Vec::new()
+    };
+    let mut renderer = TreeRenderer {
+        options,
+        relevant: &relevant,
+        output: String::new(),
+        entries: 0,
+        omitted: 0,
+    };
+    renderer.render_children(&root, &PathBuf::new(), 0);
+    if renderer.output.is_empty() {
+        return Ok(String::new());
+    }
+    let mut result = String::from("```\n");
+    result.push_str(&renderer.output);
+    if renderer.omitted > 0 {
+        result.push_str(&format!("...（已省略{}项）\n", renderer.omitted));
+    }
+    result.push_str("```\n");
+    Ok(result)
+}
+
+/// 根据被引用文件所在目录及关键词匹配情况，为目录打分，返回得分最高的若干目录
+fn find_relevant_dirs(
+    root: &DirNode,
+    root_dir: &Path,
+    options: &DirTreeOptions,
+    focus: &DirTreeFocus,
+) -> Vec<PathBuf> {
+    let focus_dirs: Vec<PathBuf> = focus
+        .paths
+        .iter()
+        .filter_map(|p| p.parent()?.strip_prefix(root_dir).ok())
+        .map(Path::to_path_buf)
+        .collect();
+    let mut scores: HashMap<PathBuf, usize> = HashMap::new();
+    let mut stack: Vec<(PathBuf, &DirNode)> = vec![(PathBuf::new(), root)];
+    while let Some((path, node)) = stack.pop() {
+        let mut score = 0;
+        if !path.as_os_str().is_empty() && focus_dirs.contains(&path) {
+            score += 10;
+        }
+        let dir_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        for keyword in &focus.keywords {
+            if dir_name.contains(keyword.as_str()) {
+                score += 2;
+            }
+            score += node
+                .files
+                .iter()
+                .filter(|f| f.to_lowercase().contains(keyword.as_str()))
+                .count();
+        }
+        if score > 0 && !node.files.is_empty() {
+            scores.insert(path.clone(), score);
+        }
+        for (name, child) in &node.children {
+            stack.push((path.join(name), child));
+        }
+    }
+    let mut ranked: Vec<(PathBuf, usize)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+        .into_iter()
+        .take(options.max_relevant_dirs)
+        .map(|(path, _)| path)
+        .collect()
+}
+
+struct TreeRenderer<'a> {
+    options: &'a DirTreeOptions,
+    relevant: &'a [PathBuf],
+    output: String,
+    entries: usize,
+    omitted: usize,
+}
+
+impl TreeRenderer<'_> {
+    fn render_children(&mut self, node: &DirNode, path: &Path, indent: usize) {
+        for (name, child) in &node.children {
+            let (label, current, current_path) = self.collapse(name, child, path);
+            if self.entries >= self.options.max_entries {
+                self.omitted += 1 + self.count_lines(current, &current_path);
+                continue;
+            }
+            self.push_line(indent, &format!("{}/", label));
+            self.render_children(current, &current_path, indent + 1);
+            if self.shows_files(&current_path, current) {
+                self.render_files(current, indent + 1);
+            }
+        }
+    }
+
+    /// 合并只有一个子目录且不需要展示文件的目录链，包路径根目录之下使用"."连接。
+    /// 返回合并后的名称、链末端的目录及其路径
+    fn collapse<'n>(
+        &self,
+        name: &str,
+        child: &'n DirNode,
+        path: &Path,
+    ) -> (String, &'n DirNode, PathBuf) {
+        let mut label = name.to_string();
+        let mut current = child;
+        let mut current_path = path.join(name);
+        while current.children.len() == 1
+            && !self.shows_files(&current_path, current)
+            && !is_package_root(&current_path)
+        {
+            let (child_name, next) = current.children.iter().next().unwrap();
+            let separator = if in_package(&current_path) { '.' } else { '/' };
+            label.push(separator);
+            label.push_str(child_name);
+            current_path.push(child_name);
+            current = next;
+        }
+        (label, current, current_path)
+    }
+
+    /// 目录下的内容完整展示时的行数（合并的目录链只占一行），用于统计省略的条目
+    fn count_lines(&self, node: &DirNode, path: &Path) -> usize {
+        let files = match self.shows_files(path, node) {
+            true => node.files.len().min(self.options.max_files_per_dir),
+            false => 0,
+        };
+        let dirs: usize = node
+            .children
+            .iter()
+            .map(|(name, child)| {
+                let (_, current, current_path) = self.collapse(name, child, path);
+                1 + self.count_lines(current, &current_path)
+            })
+            .sum();
+        dirs + files
+    }
+
+    fn render_files(&mut self, node: &DirNode, indent: usize) {
+        let mut files = node.files.clone();
+        files.sort();
+        let limit = self.options.max_files_per_dir;
+        for file in files.iter().take(limit) {
+            if self.entries >= self.options.max_entries {
+                self.omitted += 1;
+                continue;
+            }
+            self.push_line(indent, file);
+        }
+        if files.len() > limit {
+            self.push_line(indent, &format!("...（另有{}个文件）", files.len() - limit));
+        }
+    }
+
+    fn shows_files(&self, path: &Path, node: &DirNode) -> bool {
+        !node.files.is_empty() && self.relevant.iter().any(|p| p == path)
+    }
+
+    fn push_line(&mut self, indent: usize, text: &str) {
+        self.output.push_str(&"  ".repeat(indent));
+        self.output.push_str(text);
+        self.output.push('\n');
+        self.entries += 1;
+    }
+}
+
+fn is_package_root(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| PACKAGE_ROOT_NAMES.contains(&name.to_string_lossy().as_ref()))
+}
+
+fn in_package(path: &Path) -> bool {
+    path.components().any(|comp| {
+        matches!(comp, Component::Normal(name) if PACKAGE_ROOT_NAMES.contains(&name.to_string_lossy().as_ref()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn project(dirs: &[&str], files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in dirs {
+            fs::create_dir_all(dir.path().join(sub)).unwrap();
+        }
+        for file in files {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        dir
+    }
+
+    fn render(root: &Path, options: &DirTreeOptions, focus: &DirTreeFocus) -> String {
+        render_directory_tree(root, &[], options, focus).unwrap()
+    }
+
+    fn dirs_only() -> DirTreeOptions {
+        DirTreeOptions {
+            include_files: false,
+            ..DirTreeOptions::default()
+        }
+    }
+
+    #[test]
+    fn collapses_package_chains() {
+        let dir = project(&["src/main/java/com/example/user", "docs/api/v1"], &[]);
+        assert_eq!(
+            render(dir.path(), &dirs_only(), &DirTreeFocus::default()),
+            "```\ndocs/api/v1/\nsrc/main/java/\n  com.example.user/\n```\n"
+        );
+    }
+
+    #[test]
+    fn lists_files_of_relevant_dirs() {
+        let dir = project(
+            &["src/user", "src/order"],
+            &["src/user/UserService.java", "src/order/OrderService.java"],
+        );
+        let mut focus = DirTreeFocus::default();
+        focus.add_keywords_from("Add paging to the user list");
+        assert_eq!(
+            render(dir.path(), &DirTreeOptions::default(), &focus),
+            "```\nsrc/\n  order/\n  user/\n    UserService.java\n```\n"
+        );
+    }
+
+    #[test]
+    fn limits_depth() {
+        let dir = project(&["a/b/c/d", "a/x"], &[]);
+        let options = DirTreeOptions {
+            max_depth: 2,
+            ..dirs_only()
+        };
+        assert_eq!(
+            render(dir.path(), &options, &DirTreeFocus::default()),
+            "```\na/\n  b/\n  x/\n```\n"
+        );
+    }
+
+    #[test]
+    fn counts_collapsed_chains_once_when_omitting() {
+        let dir = project(
+            &[
+                "a",
+                "src/main/java/com/example/user",
+                "src/main/java/com/example/order",
+            ],
+            &[],
+        );
+        let options = DirTreeOptions {
+            max_entries: 1,
+            ..dirs_only()
+        };
+        // 省略的内容完整展示时为src/main/java/、com.example/、order/、user/四行
+        assert_eq!(
+            render(dir.path(), &options, &DirTreeFocus::default()),
+            "```\na/\n...（已省略4项）\n```\n"
+        );
+    }
+}
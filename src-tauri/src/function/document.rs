@@ -0,0 +1,525 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+/// 支持作为需求文档引用的文件格式
+pub const DOCUMENT_EXTENSIONS: &[&str] = &["pdf", "docx", "md", "markdown"];
+
+/// 附加到上下文中的文档内容字节数上限，超出部分截断
+const MAX_DOCUMENT_CHARS: usize = 80_000;
+/// 章节不存在时，错误信息中最多列出的标题数量
+const MAX_LISTED_HEADINGS: usize = 30;
+
+pub fn is_document_file(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| DOCUMENT_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// 读取需求文档并转换为Markdown，标题及表格保留为Markdown格式。
+/// selection为空时引用全文；PDF按页码选择，如"1-3,5"；DOCX及Markdown按章节标题选择，
+/// 多个标题以逗号分隔，选中标题中包含该文本的章节（含其下级章节）
+pub fn read_document(path: &Path, selection: &str) -> Result<String> {
+    let selection = selection.trim();
+    let content = match extension(path).as_deref() {
+        Some("pdf") => read_pdf(path, selection)?,
+        Some("docx") => select_sections(read_docx(path)?, selection)?,
+        Some("md") | Some("markdown") => {
+            let content = fs::read_to_string(path)
+                .map_err(|e| anyhow!("读取文档失败: {}, 错误: {}", path.display(), e))?;
+            select_sections(parse_markdown(&content), selection)?
+        }
+        _ => {
+            return Err(anyhow!(
+                "不支持的文档格式，仅支持PDF/DOCX/Markdown: {}",
+                path.display()
+            ))
+        }
+    };
+    Ok(truncate(content))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+fn truncate(content: String) -> String {
+    if content.len() <= MAX_DOCUMENT_CHARS {
+        return content;
+    }
+    let mut end = MAX_DOCUMENT_CHARS;
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = content[..end].rfind('\n').unwrap_or(end);
+    format!(
+        "{}\n（文档内容过长，其余部分已省略，可按页码或章节选择需要引用的部分）",
+        &content[..end]
+    )
+}
+
+/// PDF只能按页提取文字，无法还原标题及表格结构
+fn read_pdf(path: &Path, selection: &str) -> Result<String> {
+    let bytes =
+        fs::read(path).map_err(|e| anyhow!("读取文档失败: {}, 错误: {}", path.display(), e))?;
+    // pdf-extract遇到不规范的PDF时可能panic，不能让其中断整个任务
+    let pages = panic::catch_unwind(AssertUnwindSafe(|| {
+        pdf_extract::extract_text_from_mem_by_pages(&bytes)
+    }))
+    .map_err(|_| anyhow!("解析PDF失败: {}", path.display()))?
+    .map_err(|e| anyhow!("解析PDF失败: {}, 错误: {}", path.display(), e))?;
+    if pages.iter().all(|p| p.trim().is_empty()) {
+        return Err(anyhow!(
+            "PDF中没有可提取的文字，可能是扫描件，请将页面截图后作为图片引用: {}",
+            path.display()
+        ));
+    }
+    let selected = parse_page_ranges(selection, pages.len())?;
+    let mut content = String::new();
+    for number in selected {
+        content.push_str(&format!("[第{}页]\n", number));
+        content.push_str(&normalize_pdf_text(&pages[number - 1]));
+        content.push_str("\n\n");
+    }
+    Ok(content.trim_end().to_string())
+}
+
+/// 解析"1-3,5"形式的页码范围，页码从1开始；为空时选中所有页
+fn parse_page_ranges(selection: &str, page_count: usize) -> Result<Vec<usize>> {
+    if selection.is_empty() {
+        return Ok((1..=page_count).collect());
+    }
+    let invalid = || anyhow!("PDF文档只支持按页码选择，如\"1-3,5\"：{}", selection);
+    let mut pages = Vec::new();
+    for part in selection.split([',', '，']).map(str::trim) {
+        if part.is_empty() {
+            continue;
+        }
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (part, part),
+        };
+        let start: usize = start.parse().map_err(|_| invalid())?;
+        let end: usize = end.parse().map_err(|_| invalid())?;
+        if start == 0 || start > end || end > page_count {
+            return Err(anyhow!("页码范围{}无效，文档共{}页", part, page_count));
+        }
+        for page in start..=end {
+            if !pages.contains(&page) {
+                pages.push(page);
+            }
+        }
+    }
+    Ok(pages)
+}
+
+/// 去掉行尾空白，并合并连续的空行
+fn normalize_pdf_text(text: &str) -> String {
+    let mut result = String::new();
+    let mut blank = 0;
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank += 1;
+            if blank > 1 {
+                continue;
+            }
+        } else {
+            blank = 0;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result.trim().to_string()
+}
+
+/// 文档中的一个段落、表格或Markdown中的一行
+struct Block {
+    /// 标题的级别及文本
+    heading: Option<(usize, String)>,
+    markdown: String,
+}
+
+impl Block {
+    fn text(markdown: String) -> Self {
+        Self {
+            heading: None,
+            markdown,
+        }
+    }
+
+    fn heading(level: usize, title: String) -> Self {
+        Self {
+            markdown: format!("{} {}", "#".repeat(level.min(6)), title),
+            heading: Some((level, title)),
+        }
+    }
+}
+
+fn parse_markdown(content: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut in_fence = false;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let heading = if in_fence {
+            None
+        } else {
+            markdown_heading(line)
+        };
+        blocks.push(Block {
+            heading,
+            markdown: line.to_string(),
+        });
+    }
+    blocks
+}
+
+fn markdown_heading(line: &str) -> Option<(usize, String)> {
+    if line.len() - line.trim_start().len() > 3 {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim().to_string()))
+}
+
+/// 按章节标题选择文档内容，selection为空时返回全文
+fn select_sections(blocks: Vec<Block>, selection: &str) -> Result<String> {
+    let render = |blocks: &[&Block]| {
+        blocks
+            .iter()
+            .map(|b| b.markdown.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let selectors: Vec<String> = selection
+        .split([',', '，', ';', '；'])
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if selectors.is_empty() {
+        return Ok(render(&blocks.iter().collect::<Vec<_>>())
+            .trim()
+            .to_string());
+    }
+    // 与标题完全一致（忽略编号）的选择只匹配该标题，避免"功能需求"同时选中"非功能需求"
+    let exact: Vec<bool> = selectors
+        .iter()
+        .map(|selector| {
+            blocks.iter().any(|b| {
+                b.heading
+                    .as_ref()
+                    .is_some_and(|(_, title)| strip_numbering(title) == *selector)
+            })
+        })
+        .collect();
+    let mut matched = vec![false; selectors.len()];
+    let mut selected = Vec::new();
+    // 当前选中章节的标题级别
+    let mut current: Option<usize> = None;
+    for block in &blocks {
+        if let Some((level, title)) = &block.heading {
+            if current.is_some_and(|l| *level <= l) {
+                current = None;
+            }
+            if current.is_none() {
+                let title = title.to_lowercase();
+                for (i, selector) in selectors.iter().enumerate() {
+                    let hit = if exact[i] {
+                        strip_numbering(&title) == *selector
+                    } else {
+                        title.contains(selector.as_str())
+                    };
+                    if hit {
+                        matched[i] = true;
+                        current = Some(*level);
+                    }
+                }
+            }
+        }
+        if current.is_some() {
+            selected.push(block);
+        }
+    }
+    let missing: Vec<&str> = selectors
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(s, _)| s.as_str())
+        .collect();
+    if !missing.is_empty() {
+        let headings: Vec<&str> = blocks
+            .iter()
+            .filter_map(|b| b.heading.as_ref().map(|(_, title)| title.as_str()))
+            .take(MAX_LISTED_HEADINGS)
+            .collect();
+        return Err(anyhow!(
+            "文档中没有找到章节：{}。文档中的章节有：{}",
+            missing.join(", "),
+            if headings.is_empty() {
+                "（无）".to_string()
+            } else {
+                headings.join(", ")
+            }
+        ));
+    }
+    Ok(render(&selected).trim().to_string())
+}
+
+/// 去掉标题前的章节编号，如"2.1 "、"三、"
+fn strip_numbering(title: &str) -> String {
+    title
+        .trim_start_matches(|c: char| {
+            c.is_ascii_digit() || "一二三四五六七八九十.、 \t".contains(c)
+        })
+        .to_lowercase()
+}
+
+fn read_docx(path: &Path) -> Result<Vec<Block>> {
+    let file = fs::File::open(path)
+        .map_err(|e| anyhow!("读取文档失败: {}, 错误: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| anyhow!("不是有效的DOCX文档: {}, 错误: {}", path.display(), e))?;
+    // 部分文档没有样式定义，此时只能依据段落自身的大纲级别识别标题
+    let styles = match read_zip_entry(&mut archive, "word/styles.xml") {
+        Ok(xml) => parse_heading_styles(&xml)?,
+        Err(_) => HashMap::new(),
+    };
+    let document = read_zip_entry(&mut archive, "word/document.xml")
+        .map_err(|e| anyhow!("不是有效的DOCX文档: {}, 错误: {}", path.display(), e))?;
+    DocxParser::new(&styles).parse(&document)
+}
+
+fn read_zip_entry(archive: &mut zip::ZipArchive<fs::File>, name: &str) -> Result<String> {
+    let mut entry = archive.by_name(name)?;
+    let mut xml = String::new();
+    entry.read_to_string(&mut xml)?;
+    Ok(xml)
+}
+
+/// 按本地名称读取属性，忽略w:等命名空间前缀
+fn attr(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.to_string()))
+}
+
+/// 样式ID到标题级别的映射，依据样式的大纲级别或"heading N"一类的样式名称
+fn parse_heading_styles(xml: &str) -> Result<HashMap<String, usize>> {
+    let mut styles = HashMap::new();
+    let mut reader = Reader::from_str(xml);
+    let mut style_id: Option<String> = None;
+    let mut level: Option<usize> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"style" => {
+                style_id = attr(&e, b"styleId");
+                level = None;
+            }
+            Event::Start(e) | Event::Empty(e) if style_id.is_some() => {
+                match e.local_name().as_ref() {
+                    b"name" => {
+                        let name = attr(&e, b"val").unwrap_or_default().to_lowercase();
+                        if name == "title" {
+                            level = level.or(Some(1));
+                        } else if let Some(n) = name
+                            .strip_prefix("heading ")
+                            .and_then(|n| n.trim().parse().ok())
+                        {
+                            level = Some(n);
+                        }
+                    }
+                    b"outlineLvl" => {
+                        if let Some(n) = attr(&e, b"val").and_then(|v| v.parse::<usize>().ok()) {
+                            // 9表示正文
+                            if n < 9 {
+                                level = Some(n + 1);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"style" => {
+                if let (Some(id), Some(level)) = (style_id.take(), level.take()) {
+                    styles.insert(id, level);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(styles)
+}
+
+/// 将word/document.xml转换为Markdown：按样式识别标题，列表项转换为"-"，表格转换为Markdown表格。
+/// 嵌套表格的内容合并到外层单元格中
+struct DocxParser<'a> {
+    styles: &'a HashMap<String, usize>,
+    blocks: Vec<Block>,
+    table_depth: usize,
+    rows: Vec<Vec<String>>,
+    row: Vec<String>,
+    cell: String,
+    paragraph: String,
+    style_level: Option<usize>,
+    outline_level: Option<usize>,
+    list_level: Option<usize>,
+    in_run: bool,
+    in_text: bool,
+}
+
+impl<'a> DocxParser<'a> {
+    fn new(styles: &'a HashMap<String, usize>) -> Self {
+        Self {
+            styles,
+            blocks: Vec::new(),
+            table_depth: 0,
+            rows: Vec::new(),
+            row: Vec::new(),
+            cell: String::new(),
+            paragraph: String::new(),
+            style_level: None,
+            outline_level: None,
+            list_level: None,
+            in_run: false,
+            in_text: false,
+        }
+    }
+
+    fn parse(mut self, xml: &str) -> Result<Vec<Block>> {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) => self.open(&e, false),
+                Event::Empty(e) => self.open(&e, true),
+                Event::End(e) => self.close(e.local_name().as_ref()),
+                Event::Text(e) if self.in_text => self.paragraph.push_str(&e.unescape()?),
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(self.blocks)
+    }
+
+    fn open(&mut self, e: &BytesStart, empty: bool) {
+        match e.local_name().as_ref() {
+            b"p" if !empty => {
+                self.paragraph.clear();
+                self.style_level = None;
+                self.outline_level = None;
+                self.list_level = None;
+            }
+            b"pStyle" => {
+                self.style_level = attr(e, b"val").and_then(|id| self.styles.get(&id).copied());
+            }
+            b"outlineLvl" => {
+                self.outline_level = attr(e, b"val")
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .filter(|n| *n < 9)
+                    .map(|n| n + 1);
+            }
+            b"numPr" => self.list_level = Some(0),
+            b"ilvl" => self.list_level = attr(e, b"val").and_then(|v| v.parse().ok()),
+            b"r" if !empty => self.in_run = true,
+            b"t" if !empty => self.in_text = true,
+            // 段落属性中的制表位定义也叫tab，只处理文字中的
+            b"tab" if self.in_run => self.paragraph.push('\t'),
+            b"br" | b"cr" if self.in_run => self.paragraph.push('\n'),
+            b"tbl" if !empty => {
+                self.table_depth += 1;
+                if self.table_depth == 1 {
+                    self.rows.clear();
+                }
+            }
+            b"tr" if !empty && self.table_depth == 1 => self.row.clear(),
+            b"tc" if !empty && self.table_depth == 1 => self.cell.clear(),
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &[u8]) {
+        match name {
+            b"p" => self.finish_paragraph(),
+            b"r" => self.in_run = false,
+            b"t" => self.in_text = false,
+            b"tc" if self.table_depth == 1 => self.row.push(self.cell.trim().to_string()),
+            b"tr" if self.table_depth == 1 => self.rows.push(std::mem::take(&mut self.row)),
+            b"tbl" => {
+                self.table_depth = self.table_depth.saturating_sub(1);
+                if self.table_depth == 0 && !self.rows.is_empty() {
+                    let table = render_table(&std::mem::take(&mut self.rows));
+                    self.blocks.push(Block::text(format!("{}\n", table)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish_paragraph(&mut self) {
+        let text = self.paragraph.trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+        if self.table_depth > 0 {
+            // Markdown表格的单元格中不能换行
+            if !self.cell.is_empty() {
+                self.cell.push_str("<br>");
+            }
+            self.cell
+                .push_str(&text.replace('|', "\\|").replace('\n', "<br>"));
+            return;
+        }
+        let block = match (self.outline_level.or(self.style_level), self.list_level) {
+            (Some(level), _) => Block::heading(level, text.replace('\n', " ")),
+            (None, Some(indent)) => Block::text(format!(
+                "{}- {}",
+                "  ".repeat(indent),
+                text.replace('\n', " ")
+            )),
+            (None, None) => Block::text(format!("{}\n", text)),
+        };
+        // 标题与上一段之间空一行，列表项之间不空行
+        if block.heading.is_some() && self.blocks.last().is_some_and(|b| b.heading.is_none()) {
+            if let Some(last) = self.blocks.last_mut() {
+                if !last.markdown.ends_with('\n') {
+                    last.markdown.push('\n');
+                }
+            }
+        }
+        self.blocks.push(block);
+    }
+}
+
+fn render_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0).max(1);
+    let render_row = |row: &Vec<String>| {
+        let cells: Vec<&str> = (0..columns)
+            .map(|i| row.get(i).map_or("", String::as_str))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![
+        render_row(&rows[0]),
+        format!("|{}", " --- |".repeat(columns)),
+    ];
+    lines.extend(rows[1..].iter().map(render_row));
+    lines.join("\n")
+}
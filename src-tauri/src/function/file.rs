@@ -1,7 +1,26 @@
 use anyhow::{anyhow, Result};
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::storage::sys_config::get_config;
+
+/// 无论是否存在.gitignore，始终排除的目录。build、dist、out也常用作源码包名（如com/example/build），
+/// 只排除项目根目录下的；子模块中的构建输出一般已在.gitignore中排除
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+    ".git",
+    ".svn",
+    ".idea",
+    ".vscode",
+    ".gradle",
+    "node_modules",
+    "target",
+    "/build",
+    "/dist",
+    "/out",
+    "__pycache__",
+];
+
 pub fn file_existed(path: &str) -> bool {
     Path::new(path).exists()
 }
@@ -65,3 +84,103 @@ fn find_max_common_suffix(root_comps: &[String], sub_comps: &[String]) -> usize
     }
     0
 }
+
+/// 读取用户配置的排除规则（sys_config中的ignore_patterns，JSON字符串数组），并附加默认排除规则
+pub async fn load_ignore_patterns() -> Result<Vec<String>> {
+    let mut patterns: Vec<String> = DEFAULT_IGNORE_PATTERNS
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+    if let Some(conf) = get_config("ignore_patterns".to_string()).await? {
+        let user_patterns: Vec<String> =
+            serde_json::from_str(&conf).map_err(|e| anyhow!("排除规则配置格式错误: {}", e))?;
+        patterns.extend(user_patterns.into_iter().filter(|p| !p.trim().is_empty()));
+    }
+    Ok(patterns)
+}
+
+/// 构建遵循.gitignore/.ignore文件及额外排除规则的目录遍历器
+pub fn ignore_aware_walker(root: &Path, excludes: &[String]) -> Result<WalkBuilder> {
+    ignore_aware_walker_from(root, root, excludes)
+}
+
+/// 从项目中的某个目录开始遍历，以/开头的排除规则仍相对于项目根目录
+pub fn ignore_aware_walker_from(
+    root: &Path,
+    dir: &Path,
+    excludes: &[String],
+) -> Result<WalkBuilder> {
+    let mut overrides = OverrideBuilder::new(root);
+    for pattern in excludes {
+        // override中的规则默认为白名单，以!开头表示排除
+        overrides
+            .add(&format!("!{}", pattern.trim()))
+            .map_err(|e| anyhow!("无效的排除规则: {}, 错误: {}", pattern, e))?;
+    }
+    let mut builder = WalkBuilder::new(dir);
+    builder
+        .hidden(false)
+        .parents(true)
+        .require_git(false)
+        .overrides(overrides.build()?);
+    Ok(builder)
+}
+
+/// 路径是否被忽略规则排除，结果与目录遍历一致：从根目录开始逐级检查，
+/// 任一级未出现在上级目录的遍历结果中即视为被排除
+pub fn is_ignored_path(root: &Path, path: &Path, excludes: &[String]) -> Result<bool> {
+    let relative = path
+        .strip_prefix(root)
+        .map_err(|_| anyhow!("路径不在目录{}中: {}", root.display(), path.display()))?;
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        let child = current.join(component);
+        let visible = ignore_aware_walker_from(root, &current, excludes)?
+            .max_depth(Some(1))
+            .build()
+            .flatten()
+            .any(|entry| entry.depth() == 1 && entry.path() == child);
+        if !visible {
+            return Ok(true);
+        }
+        current = child;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_excludes() -> Vec<String> {
+        DEFAULT_IGNORE_PATTERNS
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn build_dirs_are_only_excluded_at_project_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in [
+            "build",
+            "src/main/java/com/example/build",
+            "web/node_modules",
+        ] {
+            fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        let excludes = default_excludes();
+        assert!(is_ignored_path(root, &root.join("build"), &excludes).unwrap());
+        assert!(is_ignored_path(root, &root.join("web/node_modules"), &excludes).unwrap());
+        let package = root.join("src/main/java/com/example/build");
+        assert!(!is_ignored_path(root, &package, &excludes).unwrap());
+        let walked: Vec<PathBuf> = ignore_aware_walker_from(root, &root.join("src"), &excludes)
+            .unwrap()
+            .build()
+            .flatten()
+            .map(|entry| entry.into_path())
+            .collect();
+        assert!(walked.contains(&package));
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::Serialize;
+
+use super::file::ignore_aware_walker_from;
+
+/// 资源树中的一个节点（文件或目录），使用绝对路径作为ID
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTreeNode {
+    pub id: String,
+    pub label: String,
+    #[serde(rename = "parentId")]
+    pub parent_id: String,
+    #[serde(rename = "isFolder")]
+    pub is_folder: bool,
+    #[serde(rename = "isLeaf")]
+    pub is_leaf: bool,
+    /// 文件大小（字节），目录或无法读取元数据时为空
+    pub size: Option<u64>,
+    /// 文件类型：目录为"directory"，文件为小写扩展名，无扩展名时为"file"
+    #[serde(rename = "fileType")]
+    pub file_type: String,
+}
+
+/// 按需加载的目录子节点分页结果
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTreePage {
+    pub items: Vec<FileTreeNode>,
+    pub total: usize,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+}
+
+/// 列出指定目录的直接子节点（遵循忽略规则），目录在前、文件在后，按名称排序后分页返回。
+/// root为目录所在的项目根目录，以/开头的排除规则相对于它匹配。
+/// 无法读取的条目会被跳过并记录警告，不会导致整个请求失败。
+pub fn list_dir_children(
+    root: &Path,
+    dir: &Path,
+    excludes: &[String],
+    offset: usize,
+    limit: usize,
+) -> Result<FileTreePage> {
+    if !dir.exists() {
+        return Err(anyhow!("Path does not exist: {}", dir.display()));
+    }
+    if !dir.is_dir() {
+        return Err(anyhow!("Path is not a directory: {}", dir.display()));
+    }
+    let parent_id = dir.to_string_lossy().to_string();
+    let walker = ignore_aware_walker_from(root, dir, excludes)?
+        .max_depth(Some(1))
+        .build();
+    let mut nodes = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("读取目录条目失败，已跳过: {}", e);
+                continue;
+            }
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        let path = entry.path();
+        let is_folder = entry.file_type().is_some_and(|t| t.is_dir())
+            || (entry.path_is_symlink() && path.is_dir());
+        let size = if is_folder {
+            None
+        } else {
+            match entry.metadata() {
+                Ok(meta) => Some(meta.len()),
+                Err(e) => {
+                    warn!("读取文件元数据失败: {}, 错误: {}", path.display(), e);
+                    None
+                }
+            }
+        };
+        let file_type = if is_folder {
+            "directory".to_string()
+        } else {
+            path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_else(|| "file".to_string())
+        };
+        nodes.push(FileTreeNode {
+            id: path.to_string_lossy().to_string(),
+            label: entry.file_name().to_string_lossy().to_string(),
+            parent_id: parent_id.clone(),
+            is_folder,
+            is_leaf: !is_folder,
+            size,
+            file_type,
+        });
+    }
+    nodes.sort_by(|a, b| {
+        b.is_folder
+            .cmp(&a.is_folder)
+            .then_with(|| a.label.to_lowercase().cmp(&b.label.to_lowercase()))
+    });
+    let total = nodes.len();
+    let items: Vec<FileTreeNode> = nodes.into_iter().skip(offset).take(limit).collect();
+    let has_more = offset + items.len() < total;
+    Ok(FileTreePage {
+        items,
+        total,
+        has_more,
+    })
+}
@@ -0,0 +1,310 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use git2::{Delta, Diff, DiffOptions, Patch, Repository, RevparseMode, Tree};
+use serde::Serialize;
+
+/// 附加到上下文中的变更内容总字符数上限，超出后其余文件只列出变更统计
+const MAX_DIFF_CHARS: usize = 60_000;
+/// 单个文件最多保留的变更行数
+const MAX_FILE_PATCH_LINES: usize = 300;
+/// 区间变更中最多列出的提交数量
+const MAX_LISTED_COMMITS: usize = 20;
+/// 变更内容对代码生成没有意义的文件，只列出统计
+const SKIPPED_PATCH_FILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "go.sum",
+    "poetry.lock",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffFileStat {
+    pub path: String,
+    /// added、modified、deleted、renamed、untracked等
+    pub status: String,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Debug)]
+pub struct GitDiff {
+    /// 变更的说明：未提交的变更、某个提交或提交区间
+    pub description: String,
+    pub commits: Vec<String>,
+    /// 超出MAX_LISTED_COMMITS而未列出的提交数量
+    pub omitted_commits: usize,
+    pub files: Vec<DiffFileStat>,
+    /// 截断后的unified diff文本
+    pub patch: String,
+    pub truncated: bool,
+}
+
+/// 读取repo_dir所在仓库的变更。spec为空时读取未提交的变更（含暂存区及未跟踪的文件），
+/// 否则按git的revision语法解析：单个提交（如HEAD~1）、区间（main..feature）或基于合并基点的区间（main...feature）
+pub fn read_git_diff(repo_dir: &Path, spec: &str) -> Result<GitDiff> {
+    let repo = Repository::discover(repo_dir)
+        .map_err(|e| anyhow!("{}不在Git仓库中: {}", repo_dir.display(), e.message()))?;
+    let spec = spec.trim();
+    let mut commits = Vec::new();
+    let mut omitted_commits = 0;
+    let (description, diff) = if spec.is_empty() {
+        let head_tree = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+        let mut options = DiffOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        let diff = repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut options))?;
+        ("未提交的变更".to_string(), diff)
+    } else {
+        let revspec = repo
+            .revparse(spec)
+            .map_err(|e| anyhow!("无法解析提交或分支\"{}\": {}", spec, e.message()))?;
+        let from = revspec
+            .from()
+            .ok_or_else(|| anyhow!("无法解析提交或分支\"{}\"", spec))?
+            .peel_to_commit()?;
+        if revspec.mode().contains(RevparseMode::SINGLE) {
+            let parent_tree = match from.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let diff = diff_trees(&repo, parent_tree.as_ref(), &from.tree()?)?;
+            commits.push(commit_line(&from));
+            (format!("提交 {}", short_id(&from)), diff)
+        } else {
+            let to = revspec
+                .to()
+                .ok_or_else(|| anyhow!("无法解析提交区间\"{}\"", spec))?
+                .peel_to_commit()?;
+            let base = if revspec.mode().contains(RevparseMode::MERGE_BASE) {
+                repo.find_commit(repo.merge_base(from.id(), to.id())?)?
+            } else {
+                from
+            };
+            let mut walk = repo.revwalk()?;
+            walk.push(to.id())?;
+            walk.hide(base.id())?;
+            for oid in walk {
+                let oid = oid?;
+                if commits.len() < MAX_LISTED_COMMITS {
+                    commits.push(commit_line(&repo.find_commit(oid)?));
+                } else {
+                    omitted_commits += 1;
+                }
+            }
+            let diff = diff_trees(&repo, Some(&base.tree()?), &to.tree()?)?;
+            (format!("提交区间 {}", spec), diff)
+        }
+    };
+    let (files, patch, truncated) = render_patches(&diff)?;
+    Ok(GitDiff {
+        description,
+        commits,
+        omitted_commits,
+        files,
+        patch,
+        truncated,
+    })
+}
+
+impl GitDiff {
+    /// 转换为附加到LLM上下文中的文本：变更文件列表及（截断后的）diff
+    pub fn to_context(&self) -> String {
+        let mut context = format!("##引用Git变更：{}\n", self.description);
+        if !self.commits.is_empty() {
+            context.push_str("包含的提交：\n");
+            for commit in &self.commits {
+                context.push_str(&format!("- {}\n", commit));
+            }
+            if self.omitted_commits > 0 {
+                context.push_str(&format!(
+                    "- ...（另有{}个更早的提交未列出）\n",
+                    self.omitted_commits
+                ));
+            }
+        }
+        context.push_str("变更的文件：\n");
+        for file in &self.files {
+            context.push_str(&format!(
+                "- [{}] {} (+{} -{})\n",
+                file.status, file.path, file.additions, file.deletions
+            ));
+        }
+        context.push_str(&format!("```diff\n{}\n```\n", self.patch.trim_end()));
+        if self.truncated {
+            context.push_str("（变更内容过多，部分内容已省略）\n");
+        }
+        context
+    }
+}
+
+fn diff_trees<'r>(repo: &'r Repository, old: Option<&Tree>, new: &Tree) -> Result<Diff<'r>> {
+    let mut diff = repo.diff_tree_to_tree(old, Some(new), None)?;
+    diff.find_similar(None)?;
+    Ok(diff)
+}
+
+/// 逐个文件生成patch，并按单文件行数及总字符数截断
+fn render_patches(diff: &Diff) -> Result<(Vec<DiffFileStat>, String, bool)> {
+    let mut files = Vec::new();
+    let mut output = String::new();
+    // 按字符而不是字节计数，中文注释较多的变更不会被提前截断
+    let mut chars = 0;
+    let mut truncated = false;
+    for idx in 0..diff.deltas().len() {
+        let Some(mut patch) = Patch::from_diff(diff, idx)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let status = status_name(delta.status());
+        let is_binary = delta.flags().is_binary();
+        let (_, additions, deletions) = patch.line_stats()?;
+        files.push(DiffFileStat {
+            path: path.clone(),
+            status: status.to_string(),
+            additions,
+            deletions,
+        });
+
+        let skipped = SKIPPED_PATCH_FILES
+            .iter()
+            .any(|name| path.rsplit('/').next() == Some(*name));
+        if is_binary || skipped {
+            let note = format!("# {}: 变更内容已省略\n", path);
+            chars += note.chars().count();
+            output.push_str(&note);
+            continue;
+        }
+        if chars >= MAX_DIFF_CHARS {
+            truncated = true;
+            continue;
+        }
+        let buf = patch.to_buf()?;
+        let text = String::from_utf8_lossy(&buf);
+        let lines: Vec<&str> = text.lines().collect();
+        let mut written = 0;
+        for line in lines.iter().take(MAX_FILE_PATCH_LINES) {
+            let line_chars = line.chars().count() + 1;
+            if chars + line_chars > MAX_DIFF_CHARS {
+                break;
+            }
+            output.push_str(line);
+            output.push('\n');
+            chars += line_chars;
+            written += 1;
+        }
+        if written < lines.len() {
+            truncated = true;
+            output.push_str(&format!(
+                "# ...（{}还有{}行变更已省略）\n",
+                path,
+                lines.len() - written
+            ));
+        }
+    }
+    Ok((files, output, truncated))
+}
+
+fn status_name(status: Delta) -> &'static str {
+    match status {
+        Delta::Added => "added",
+        Delta::Deleted => "deleted",
+        Delta::Modified => "modified",
+        Delta::Renamed => "renamed",
+        Delta::Copied => "copied",
+        Delta::Untracked => "untracked",
+        Delta::Typechange => "typechange",
+        _ => "changed",
+    }
+}
+
+fn short_id(commit: &git2::Commit) -> String {
+    commit.id().to_string().chars().take(8).collect()
+}
+
+fn commit_line(commit: &git2::Commit) -> String {
+    format!(
+        "{} {}",
+        short_id(commit),
+        commit.summary().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_file(repo: &Repository, name: &str, content: &str, message: &str) {
+        let root = repo.workdir().unwrap();
+        std::fs::write(root.join(name), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("tester", "tester@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn notes_commits_left_out_of_the_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        commit_file(&repo, "a.txt", "0\n", "初始提交");
+        for i in 1..=MAX_LISTED_COMMITS + 5 {
+            commit_file(
+                &repo,
+                "a.txt",
+                &format!("{}\n", i),
+                &format!("第{}次修改", i),
+            );
+        }
+
+        let diff = read_git_diff(dir.path(), "HEAD~25..HEAD").unwrap();
+        assert_eq!(diff.commits.len(), MAX_LISTED_COMMITS);
+        assert_eq!(diff.omitted_commits, 5);
+        assert!(diff.commits[0].ends_with("第25次修改"));
+        assert!(diff.to_context().contains("另有5个更早的提交未列出"));
+
+        let diff = read_git_diff(dir.path(), "HEAD~3..HEAD").unwrap();
+        assert_eq!(diff.commits.len(), 3);
+        assert_eq!(diff.omitted_commits, 0);
+        assert!(!diff.to_context().contains("未列出"));
+    }
+
+    #[test]
+    fn diff_limit_counts_chars_not_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        commit_file(&repo, "readme.md", "说明\n", "初始提交");
+        // 约2.5万个字符，按UTF-8字节计算则超过上限
+        let line = "// 中文注释".repeat(25);
+        let content = vec![line.as_str(); 250].join("\n");
+        assert!(content.len() > MAX_DIFF_CHARS);
+        std::fs::write(dir.path().join("service.rs"), &content).unwrap();
+
+        let diff = read_git_diff(dir.path(), "").unwrap();
+        assert!(!diff.truncated);
+        assert_eq!(diff.files.len(), 1);
+        assert_eq!(diff.patch.matches("// 中文注释").count(), 25 * 250);
+    }
+}
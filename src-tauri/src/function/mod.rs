@@ -1 +1,7 @@
+pub mod dir_tree;
+pub mod document;
 pub mod file;
+pub mod file_tree;
+pub mod git_diff;
+pub mod outline;
+pub mod watcher;
@@ -0,0 +1,734 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use tree_sitter::{Language, Node, Parser};
+
+/// 各语言语法树中需要关注的节点类型
+struct LanguageSpec {
+    language: fn() -> Language,
+    /// 原样保留的节点：package、import、字段、类型别名等
+    keep: &'static [&'static str],
+    /// 只保留首行的节点，避免带出较长的初始化表达式
+    first_line: &'static [&'static str],
+    /// 保留声明并继续展开其成员的节点：类、接口、impl等
+    containers: &'static [&'static str],
+    /// 只保留签名、省略实现的节点：函数、方法、构造器等
+    callables: &'static [&'static str],
+    /// 包装了实际声明的节点（如export、装饰器），声明文本从包装节点开始计算
+    wrappers: &'static [&'static str],
+    /// 本身不输出、直接展开其子节点的节点
+    transparent: &'static [&'static str],
+    /// 无法通过body字段获取声明体时，按节点类型查找
+    body_kinds: &'static [&'static str],
+    /// 省略实现后追加在签名后的内容
+    signature_suffix: &'static str,
+    /// 是否以缩进而不是花括号表示代码块（Python）
+    indent_block: bool,
+}
+
+static JAVA: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_java::LANGUAGE.into(),
+    keep: &[
+        "package_declaration",
+        "import_declaration",
+        "field_declaration",
+        "constant_declaration",
+        "enum_constant",
+        "annotation_type_element_declaration",
+    ],
+    first_line: &[],
+    containers: &[
+        "class_declaration",
+        "interface_declaration",
+        "enum_declaration",
+        "record_declaration",
+        "annotation_type_declaration",
+    ],
+    callables: &[
+        "method_declaration",
+        "constructor_declaration",
+        "compact_constructor_declaration",
+    ],
+    wrappers: &[],
+    transparent: &["enum_body_declarations"],
+    body_kinds: &[],
+    signature_suffix: ";",
+    indent_block: false,
+};
+
+static KOTLIN: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_kotlin_ng::LANGUAGE.into(),
+    keep: &["package_header", "import", "type_alias", "enum_entry"],
+    first_line: &["property_declaration"],
+    containers: &[
+        "class_declaration",
+        "object_declaration",
+        "companion_object",
+    ],
+    callables: &["function_declaration", "secondary_constructor"],
+    wrappers: &[],
+    transparent: &[],
+    body_kinds: &["class_body", "enum_class_body", "function_body", "block"],
+    signature_suffix: "",
+    indent_block: false,
+};
+
+static TYPESCRIPT: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+    ..TSX
+};
+
+static TSX: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_typescript::LANGUAGE_TSX.into(),
+    keep: &[
+        "import_statement",
+        "interface_declaration",
+        "type_alias_declaration",
+        "enum_declaration",
+        "public_field_definition",
+        "abstract_method_signature",
+        "method_signature",
+        "index_signature",
+        "ambient_declaration",
+    ],
+    first_line: &["lexical_declaration", "variable_declaration"],
+    containers: &[
+        "class_declaration",
+        "abstract_class_declaration",
+        "internal_module",
+    ],
+    callables: &[
+        "function_declaration",
+        "generator_function_declaration",
+        "method_definition",
+    ],
+    wrappers: &["export_statement"],
+    transparent: &[],
+    body_kinds: &[],
+    signature_suffix: ";",
+    indent_block: false,
+};
+
+static GO: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_go::LANGUAGE.into(),
+    keep: &[
+        "package_clause",
+        "import_declaration",
+        "type_declaration",
+        "const_declaration",
+        "var_declaration",
+    ],
+    first_line: &[],
+    containers: &[],
+    callables: &["function_declaration", "method_declaration"],
+    wrappers: &[],
+    transparent: &[],
+    body_kinds: &[],
+    signature_suffix: "",
+    indent_block: false,
+};
+
+static RUST: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_rust::LANGUAGE.into(),
+    keep: &[
+        "use_declaration",
+        "extern_crate_declaration",
+        "attribute_item",
+        "inner_attribute_item",
+        "struct_item",
+        "enum_item",
+        "union_item",
+        "type_item",
+        "const_item",
+        "static_item",
+        "associated_type",
+        "function_signature_item",
+    ],
+    first_line: &[],
+    containers: &["impl_item", "trait_item", "mod_item"],
+    callables: &["function_item"],
+    wrappers: &[],
+    transparent: &[],
+    body_kinds: &[],
+    signature_suffix: ";",
+    indent_block: false,
+};
+
+static PYTHON: LanguageSpec = LanguageSpec {
+    language: || tree_sitter_python::LANGUAGE.into(),
+    keep: &[
+        "import_statement",
+        "import_from_statement",
+        "future_import_statement",
+    ],
+    first_line: &["assignment"],
+    containers: &["class_definition"],
+    callables: &["function_definition"],
+    wrappers: &["decorated_definition"],
+    transparent: &["expression_statement"],
+    body_kinds: &[],
+    signature_suffix: " ...",
+    indent_block: true,
+};
+
+fn spec_for(path: &Path) -> Option<&'static LanguageSpec> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    match ext.as_str() {
+        "java" => Some(&JAVA),
+        "kt" | "kts" => Some(&KOTLIN),
+        "ts" | "mts" | "cts" => Some(&TYPESCRIPT),
+        "tsx" | "js" | "jsx" | "mjs" | "cjs" => Some(&TSX),
+        "go" => Some(&GO),
+        "rs" => Some(&RUST),
+        "py" => Some(&PYTHON),
+        _ => None,
+    }
+}
+
+/// 是否支持对该文件提取大纲或符号
+pub fn is_supported(path: &Path) -> bool {
+    spec_for(path).is_some()
+}
+
+/// 提取文件大纲：保留package、import、类型声明、字段及方法签名，省略方法实现
+pub fn extract_outline(path: &Path, content: &str) -> Result<String> {
+    let (spec, parser_tree) = parse(path, content)?;
+    let mut walker = OutlineWalker {
+        spec,
+        source: content,
+        lines: Vec::new(),
+    };
+    walker.walk_children(parser_tree.root_node(), 0);
+    Ok(walker.lines.join("\n"))
+}
+
+/// 提取指定符号（类型或方法）的完整代码，支持"类名.方法名"形式的（部分）限定名；
+/// 同时保留package与import，便于理解符号中引用的类型
+pub fn extract_symbols(path: &Path, content: &str, symbols: &[String]) -> Result<String> {
+    let (spec, tree) = parse(path, content)?;
+    let mut finder = SymbolFinder {
+        spec,
+        source: content,
+        symbols,
+        preamble: Vec::new(),
+        found: Vec::new(),
+        matched: vec![false; symbols.len()],
+    };
+    finder.walk_children(tree.root_node(), &mut Vec::new());
+    let missing: Vec<&str> = symbols
+        .iter()
+        .zip(&finder.matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(symbol, _)| symbol.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "在文件{}中未找到符号: {}",
+            path.display(),
+            missing.join(", ")
+        ));
+    }
+    let mut sections = Vec::new();
+    if !finder.preamble.is_empty() {
+        sections.push(finder.preamble.join("\n"));
+    }
+    sections.extend(finder.found);
+    Ok(sections.join("\n\n"))
+}
+
+fn parse(path: &Path, content: &str) -> Result<(&'static LanguageSpec, tree_sitter::Tree)> {
+    let spec =
+        spec_for(path).ok_or_else(|| anyhow!("不支持解析该类型的文件: {}", path.display()))?;
+    let mut parser = Parser::new();
+    parser.set_language(&(spec.language)())?;
+    let tree = parser
+        .parse(content, None)
+        .ok_or_else(|| anyhow!("解析文件失败: {}", path.display()))?;
+    Ok((spec, tree))
+}
+
+enum NodeRole {
+    Keep,
+    FirstLine,
+    Container,
+    Callable,
+    Transparent,
+    Skip,
+}
+
+impl LanguageSpec {
+    /// 返回节点的角色及实际的声明节点（包装节点会被展开）
+    fn classify<'a>(&self, node: Node<'a>) -> (NodeRole, Node<'a>) {
+        let declaration = if self.wrappers.contains(&node.kind()) {
+            match node
+                .child_by_field_name("declaration")
+                .or_else(|| node.child_by_field_name("definition"))
+            {
+                Some(inner) => inner,
+                None => return (NodeRole::Keep, node),
+            }
+        } else {
+            node
+        };
+        let kind = declaration.kind();
+        let role = if self.keep.contains(&kind) {
+            NodeRole::Keep
+        } else if self.first_line.contains(&kind) {
+            NodeRole::FirstLine
+        } else if self.containers.contains(&kind) {
+            NodeRole::Container
+        } else if self.callables.contains(&kind) {
+            NodeRole::Callable
+        } else if self.transparent.contains(&kind) {
+            NodeRole::Transparent
+        } else {
+            NodeRole::Skip
+        };
+        (role, declaration)
+    }
+
+    fn body_of<'a>(&self, declaration: Node<'a>) -> Option<Node<'a>> {
+        declaration.child_by_field_name("body").or_else(|| {
+            let mut cursor = declaration.walk();
+            let body = declaration
+                .named_children(&mut cursor)
+                .find(|c| self.body_kinds.contains(&c.kind()));
+            body
+        })
+    }
+
+    fn is_preamble(&self, node: Node) -> bool {
+        let kind = node.kind();
+        kind.contains("import") || kind.starts_with("package") || kind == "use_declaration"
+    }
+}
+
+struct OutlineWalker<'a> {
+    spec: &'static LanguageSpec,
+    source: &'a str,
+    lines: Vec<String>,
+}
+
+impl OutlineWalker<'_> {
+    fn walk_children(&mut self, parent: Node, depth: usize) {
+        let mut cursor = parent.walk();
+        for child in parent.named_children(&mut cursor) {
+            self.visit(child, depth);
+        }
+    }
+
+    fn visit(&mut self, node: Node, depth: usize) {
+        let (role, declaration) = self.spec.classify(node);
+        match role {
+            NodeRole::Keep => self.push(depth, node_text(self.source, node, depth)),
+            NodeRole::FirstLine => {
+                let text = &self.source[node.byte_range()];
+                match text.lines().next() {
+                    Some(first) if first.len() < text.len() => {
+                        self.push(depth, format!("{} ...", first.trim_end()))
+                    }
+                    _ => self.push(depth, text.to_string()),
+                }
+            }
+            NodeRole::Container => match self.spec.body_of(declaration) {
+                Some(body) => {
+                    let header = header_text(self.source, node, body, depth);
+                    if self.spec.indent_block {
+                        self.push(depth, header);
+                        self.walk_children(body, depth + 1);
+                    } else {
+                        self.push(depth, format!("{} {{", header));
+                        self.walk_children(body, depth + 1);
+                        self.push(depth, "}".to_string());
+                    }
+                }
+                None => self.push(depth, node_text(self.source, node, depth)),
+            },
+            NodeRole::Callable => match self.spec.body_of(declaration) {
+                Some(body) => {
+                    let header = header_text(self.source, node, body, depth);
+                    self.push(depth, format!("{}{}", header, self.spec.signature_suffix));
+                }
+                // 抽象方法或接口方法本身就只有签名
+                None => self.push(depth, node_text(self.source, node, depth)),
+            },
+            NodeRole::Transparent => self.walk_children(declaration, depth),
+            NodeRole::Skip => {}
+        }
+    }
+
+    fn push(&mut self, depth: usize, text: String) {
+        self.lines.push(format!("{}{}", indent(depth), text));
+    }
+}
+
+struct SymbolFinder<'a> {
+    spec: &'static LanguageSpec,
+    source: &'a str,
+    symbols: &'a [String],
+    preamble: Vec<String>,
+    found: Vec<String>,
+    matched: Vec<bool>,
+}
+
+impl SymbolFinder<'_> {
+    fn walk_children(&mut self, parent: Node, scope: &mut Vec<String>) {
+        let mut cursor = parent.walk();
+        for child in parent.named_children(&mut cursor) {
+            self.visit(child, scope);
+        }
+    }
+
+    fn visit(&mut self, node: Node, scope: &mut Vec<String>) {
+        let (role, declaration) = self.spec.classify(node);
+        if matches!(role, NodeRole::Skip) {
+            return;
+        }
+        if matches!(role, NodeRole::Transparent) {
+            self.walk_children(declaration, scope);
+            return;
+        }
+        if scope.is_empty() && self.spec.is_preamble(declaration) {
+            self.preamble.push(node_text(self.source, node, 0));
+            return;
+        }
+        let Some(name) = symbol_name(self.source, declaration) else {
+            return;
+        };
+        let qualified = scope
+            .iter()
+            .chain(std::iter::once(&name))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(".");
+        let mut hit = false;
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            if qualified == *symbol || qualified.ends_with(&format!(".{}", symbol)) {
+                self.matched[i] = true;
+                hit = true;
+            }
+        }
+        if hit {
+            self.found.push(node_text(self.source, node, 0));
+            return;
+        }
+        if matches!(role, NodeRole::Container) {
+            if let Some(body) = self.spec.body_of(declaration) {
+                scope.push(name);
+                self.walk_children(body, scope);
+                scope.pop();
+            }
+        }
+    }
+}
+
+/// 获取声明的名称；Rust的impl块以其实现的类型作为名称
+fn symbol_name(source: &str, declaration: Node) -> Option<String> {
+    let name_node = if declaration.kind() == "impl_item" {
+        declaration.child_by_field_name("type")
+    } else {
+        declaration.child_by_field_name("name").or_else(|| {
+            // 如Go的type_declaration、TS的lexical_declaration，名称位于首个子节点上
+            let mut cursor = declaration.walk();
+            let first = declaration.named_children(&mut cursor).next();
+            first.and_then(|c| c.child_by_field_name("name"))
+        })
+    }?;
+    Some(source[name_node.byte_range()].to_string())
+}
+
+/// 声明从起始位置到声明体之前的部分，即类型声明头或方法签名
+fn header_text(source: &str, node: Node, body: Node, depth: usize) -> String {
+    let header = &source[node.start_byte()..body.start_byte()];
+    reindent(header.trim_end(), node.start_position().column, depth)
+}
+
+fn node_text(source: &str, node: Node, depth: usize) -> String {
+    reindent(
+        &source[node.byte_range()],
+        node.start_position().column,
+        depth,
+    )
+}
+
+/// 节点文本的首行不含原有缩进，后续行去掉原有缩进后按新的层级重新缩进
+fn reindent(text: &str, column: usize, depth: usize) -> String {
+    let mut lines = text.lines();
+    let mut result = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        let strip = line
+            .char_indices()
+            .take_while(|(i, c)| *i < column && c.is_whitespace())
+            .last()
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        result.push('\n');
+        result.push_str(&indent(depth));
+        result.push_str(&line[strip..]);
+    }
+    result
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JAVA_SOURCE: &str = r#"package com.example;
+
+import java.util.List;
+
+public class UserService {
+    private final UserRepository repository;
+
+    public UserService(UserRepository repository) {
+        this.repository = repository;
+    }
+
+    public List<User> findAll() {
+        return repository.findAll();
+    }
+
+    enum Status {
+        ACTIVE,
+        DISABLED;
+
+        boolean enabled() {
+            return this == ACTIVE;
+        }
+    }
+}
+"#;
+
+    const KOTLIN_SOURCE: &str = r#"package com.example
+
+import kotlinx.coroutines.flow.Flow
+
+class UserService(private val repository: UserRepository) {
+    val cache = mutableMapOf<Long, User>(
+        1L to User(1L)
+    )
+
+    fun find(id: Long): User? {
+        return cache[id] ?: repository.find(id)
+    }
+}
+"#;
+
+    const TS_SOURCE: &str = r#"import { User } from './user';
+
+export interface Repository {
+    find(id: number): User;
+}
+
+export class UserService {
+    private cache = new Map<number, User>();
+
+    constructor(private repository: Repository) {}
+
+    find(id: number): User {
+        return this.cache.get(id) ?? this.repository.find(id);
+    }
+}
+
+export function createService(repository: Repository): UserService {
+    return new UserService(repository);
+}
+"#;
+
+    const GO_SOURCE: &str = r#"package users
+
+import "fmt"
+
+type UserService struct {
+	repo Repository
+}
+
+func (s *UserService) Find(id int64) (*User, error) {
+	fmt.Println("find", id)
+	return s.repo.Find(id)
+}
+
+func NewUserService(repo Repository) *UserService {
+	return &UserService{repo: repo}
+}
+"#;
+
+    const RUST_SOURCE: &str = r#"use std::collections::HashMap;
+
+pub struct UserService {
+    cache: HashMap<u64, User>,
+}
+
+impl UserService {
+    pub fn find(&self, id: u64) -> Option<&User> {
+        self.cache.get(&id)
+    }
+}
+
+pub fn create() -> UserService {
+    UserService { cache: HashMap::new() }
+}
+"#;
+
+    const PYTHON_SOURCE: &str = r#"import os
+
+class UserService:
+    def __init__(self, repository):
+        self.repository = repository
+
+    def find(self, user_id):
+        return self.repository.find(user_id)
+
+def create_service():
+    return UserService(os.environ["REPO"])
+"#;
+
+    fn outline(file: &str, source: &str) -> String {
+        extract_outline(Path::new(file), source).unwrap()
+    }
+
+    fn symbols(file: &str, source: &str, names: &[&str]) -> Result<String> {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        extract_symbols(Path::new(file), source, &names)
+    }
+
+    #[test]
+    fn java_outline_and_symbols() {
+        assert_eq!(
+            outline("UserService.java", JAVA_SOURCE),
+            "package com.example;
+import java.util.List;
+public class UserService {
+    private final UserRepository repository;
+    public UserService(UserRepository repository);
+    public List<User> findAll();
+    enum Status {
+        ACTIVE
+        DISABLED
+        boolean enabled();
+    }
+}"
+        );
+        let found = symbols("UserService.java", JAVA_SOURCE, &["UserService.findAll"]).unwrap();
+        assert_eq!(
+            found,
+            "package com.example;
+import java.util.List;
+
+public List<User> findAll() {
+    return repository.findAll();
+}"
+        );
+        let nested = symbols("UserService.java", JAVA_SOURCE, &["Status.enabled"]).unwrap();
+        assert!(nested.ends_with("boolean enabled() {\n    return this == ACTIVE;\n}"));
+    }
+
+    #[test]
+    fn kotlin_outline_and_symbols() {
+        assert_eq!(
+            outline("UserService.kt", KOTLIN_SOURCE),
+            "package com.example
+import kotlinx.coroutines.flow.Flow
+class UserService(private val repository: UserRepository) {
+    val cache = mutableMapOf<Long, User>( ...
+    fun find(id: Long): User?
+}"
+        );
+        let found = symbols("UserService.kt", KOTLIN_SOURCE, &["find"]).unwrap();
+        assert!(found.ends_with(
+            "fun find(id: Long): User? {\n    return cache[id] ?: repository.find(id)\n}"
+        ));
+    }
+
+    #[test]
+    fn typescript_outline_and_symbols() {
+        assert_eq!(
+            outline("service.ts", TS_SOURCE),
+            "import { User } from './user';
+export interface Repository {
+    find(id: number): User;
+}
+export class UserService {
+    private cache = new Map<number, User>()
+    constructor(private repository: Repository);
+    find(id: number): User;
+}
+export function createService(repository: Repository): UserService;"
+        );
+        let found = symbols("service.ts", TS_SOURCE, &["createService"]).unwrap();
+        assert!(found.starts_with("import { User } from './user';\n\n"));
+        assert!(found.contains("return new UserService(repository);"));
+    }
+
+    #[test]
+    fn go_outline_and_symbols() {
+        assert_eq!(
+            outline("service.go", GO_SOURCE),
+            "package users
+import \"fmt\"
+type UserService struct {
+	repo Repository
+}
+func (s *UserService) Find(id int64) (*User, error)
+func NewUserService(repo Repository) *UserService"
+        );
+        let found = symbols("service.go", GO_SOURCE, &["NewUserService"]).unwrap();
+        assert!(found.ends_with("return &UserService{repo: repo}\n}"));
+    }
+
+    #[test]
+    fn rust_outline_and_symbols() {
+        assert_eq!(
+            outline("service.rs", RUST_SOURCE),
+            "use std::collections::HashMap;
+pub struct UserService {
+    cache: HashMap<u64, User>,
+}
+impl UserService {
+    pub fn find(&self, id: u64) -> Option<&User>;
+}
+pub fn create() -> UserService;"
+        );
+        // impl块以实现的类型作为名称
+        let found = symbols("service.rs", RUST_SOURCE, &["UserService.find"]).unwrap();
+        assert!(found.ends_with(
+            "pub fn find(&self, id: u64) -> Option<&User> {\n    self.cache.get(&id)\n}"
+        ));
+    }
+
+    #[test]
+    fn python_outline_and_symbols() {
+        assert_eq!(
+            outline("service.py", PYTHON_SOURCE),
+            "import os
+class UserService:
+    def __init__(self, repository): ...
+    def find(self, user_id): ...
+def create_service(): ..."
+        );
+        let found = symbols("service.py", PYTHON_SOURCE, &["UserService.find"]).unwrap();
+        assert!(
+            found.ends_with("def find(self, user_id):\n    return self.repository.find(user_id)")
+        );
+    }
+
+    #[test]
+    fn reports_missing_symbols_and_unsupported_files() {
+        let err = symbols(
+            "UserService.java",
+            JAVA_SOURCE,
+            &["findAll", "delete", "Other.findAll"],
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().ends_with("delete, Other.findAll"),
+            "{}",
+            err
+        );
+        assert!(!is_supported(Path::new("README.md")));
+        assert!(extract_outline(Path::new("README.md"), "# Demo").is_err());
+    }
+}
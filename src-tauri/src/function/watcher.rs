@@ -0,0 +1,374 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use log::{error, info, warn};
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{self, event::ModifyKind, EventKind, RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::code_index;
+
+/// 项目目录发生变化时推送给前端的事件
+pub const FS_CHANGE_EVENT: &str = "fs-change";
+/// 已选中的文件资源内容发生变化时推送给前端的事件
+pub const RESOURCE_CHANGE_EVENT: &str = "resource-changed";
+
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsChangeKind {
+    Created,
+    Removed,
+    Renamed,
+    Modified,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FsChange {
+    pub kind: FsChangeKind,
+    /// 重命名时依次为原路径和新路径
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceChange {
+    pub path: String,
+    /// 内容与添加为资源时是否不同
+    pub changed: bool,
+    pub removed: bool,
+}
+
+type ProjectDebouncer = Debouncer<RecommendedWatcher, RecommendedCache>;
+
+struct ProjectWatcher {
+    root: PathBuf,
+    debouncer: ProjectDebouncer,
+}
+
+static PROJECT_WATCHER: Lazy<Mutex<Option<ProjectWatcher>>> = Lazy::new(|| Mutex::new(None));
+
+/// 已添加为资源的文件及其添加时的内容摘要
+static TRACKED_RESOURCES: Lazy<Mutex<HashMap<PathBuf, u64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 监听项目根目录，替换之前的监听（同一时间只监听一个项目）。
+/// 每个未被忽略的目录单独监听，node_modules、target等被忽略的目录不占用系统的监听数量；之后新建的目录在事件中补充监听。
+/// 变化事件经过防抖合并后推送，被.gitignore/.ignore文件（包括子目录中的）或排除规则忽略的路径不会推送。
+pub fn watch_project(app: AppHandle, root: &Path, excludes: &[String]) -> Result<()> {
+    if !root.is_dir() {
+        return Err(anyhow!("Path is not a directory: {}", root.display()));
+    }
+    let rules = Arc::new(Mutex::new(IgnoreRules::new(root, excludes)));
+    let root_dir = root.to_path_buf();
+    let handler_rules = rules.clone();
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, None, move |result| {
+        handle_events(&app, &root_dir, &handler_rules, result)
+    })
+    .map_err(|e| anyhow!("创建目录监听失败: {}", e))?;
+    match watch_tree(&mut debouncer, &mut rules.lock().unwrap(), root) {
+        Ok(count) => info!("开始监听项目目录: {}, 共{}个目录", root.display(), count),
+        Err(e) if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) => warn!(
+            "监听的目录数量超过系统上限，部分目录的变化不会更新: {}",
+            root.display()
+        ),
+        Err(e) => return Err(anyhow!("监听目录失败: {}, 错误: {}", root.display(), e)),
+    }
+    *PROJECT_WATCHER.lock().unwrap() = Some(ProjectWatcher {
+        root: root.to_path_buf(),
+        debouncer,
+    });
+    Ok(())
+}
+
+/// 记录文件资源当前的内容摘要，之后内容变化时推送resource-changed事件
+pub fn track_resource(path: &Path) -> Result<()> {
+    let digest =
+        digest_file(path).map_err(|e| anyhow!("读取文件失败: {}, 错误: {}", path.display(), e))?;
+    TRACKED_RESOURCES
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), digest);
+    Ok(())
+}
+
+pub fn untrack_resource(path: &Path) {
+    TRACKED_RESOURCES.lock().unwrap().remove(path);
+}
+
+/// 监听目录及其下未被忽略的子目录，返回监听的目录数。子目录监听失败时只记录警告，
+/// 超过系统的监听数量上限时不再继续
+fn watch_tree(
+    debouncer: &mut ProjectDebouncer,
+    rules: &mut IgnoreRules,
+    dir: &Path,
+) -> notify::Result<usize> {
+    rules.load_dir(dir);
+    debouncer.watch(dir, RecursiveMode::NonRecursive)?;
+    let mut count = 1;
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(count);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // 与目录遍历一致，不跟随符号链接
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) || rules.is_ignored(&path, true) {
+            continue;
+        }
+        match watch_tree(debouncer, rules, &path) {
+            Ok(n) => count += n,
+            Err(e) if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) => return Err(e),
+            Err(e) => warn!("监听目录失败: {}, 错误: {}", path.display(), e),
+        }
+    }
+    Ok(count)
+}
+
+/// 新建或移入项目的目录补充监听
+fn watch_new_dirs(root: &Path, rules: &mut IgnoreRules, dirs: &[PathBuf]) {
+    let mut watcher = PROJECT_WATCHER.lock().unwrap();
+    // 切换项目后之前项目的事件不再处理
+    let Some(watcher) = watcher.as_mut().filter(|w| w.root == root) else {
+        return;
+    };
+    for dir in dirs {
+        if let Err(e) = watch_tree(&mut watcher.debouncer, rules, dir) {
+            warn!("监听目录失败: {}, 错误: {}", dir.display(), e);
+        }
+    }
+}
+
+/// 项目的忽略规则：排除规则及各目录中的.gitignore/.ignore文件，子目录中的规则优先
+struct IgnoreRules {
+    root: PathBuf,
+    excludes: Gitignore,
+    dirs: HashMap<PathBuf, Gitignore>,
+}
+
+impl IgnoreRules {
+    fn new(root: &Path, excludes: &[String]) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in excludes {
+            if let Err(e) = builder.add_line(None, pattern) {
+                warn!("无效的排除规则: {}, 错误: {}", pattern, e);
+            }
+        }
+        let excludes = builder.build().unwrap_or_else(|e| {
+            warn!("构建忽略规则失败: {}", e);
+            Gitignore::empty()
+        });
+        Self {
+            root: root.to_path_buf(),
+            excludes,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// 读取目录中的忽略规则文件，文件变化时重新读取
+    fn load_dir(&mut self, dir: &Path) {
+        let files: Vec<PathBuf> = [".gitignore", ".ignore"]
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|file| file.is_file())
+            .collect();
+        if files.is_empty() {
+            self.dirs.remove(dir);
+            return;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        for file in &files {
+            if let Some(e) = builder.add(file) {
+                warn!("解析忽略规则失败: {}, 错误: {}", file.display(), e);
+            }
+        }
+        match builder.build() {
+            Ok(matcher) => {
+                self.dirs.insert(dir.to_path_buf(), matcher);
+            }
+            Err(e) => warn!("构建忽略规则失败: {}, 错误: {}", dir.display(), e),
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if !path.starts_with(&self.root) || path == self.root {
+            return false;
+        }
+        if self
+            .excludes
+            .matched_path_or_any_parents(path, is_dir)
+            .is_ignore()
+        {
+            return true;
+        }
+        // 从最近的上级目录开始，第一个匹配的规则决定是否忽略
+        for dir in path.ancestors().skip(1) {
+            if let Some(matcher) = self.dirs.get(dir) {
+                match matcher.matched_path_or_any_parents(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        false
+    }
+}
+
+fn handle_events(
+    app: &AppHandle,
+    root: &Path,
+    rules: &Mutex<IgnoreRules>,
+    result: DebounceEventResult,
+) {
+    let events = match result {
+        Ok(events) => events,
+        Err(errors) => {
+            for e in errors {
+                warn!("目录监听出现错误: {}", e);
+            }
+            return;
+        }
+    };
+    let mut rules = rules.lock().unwrap();
+    let mut changes: Vec<FsChange> = Vec::new();
+    let mut new_dirs: Vec<PathBuf> = Vec::new();
+    let mut touched: Vec<PathBuf> = Vec::new();
+    let mut visible: Vec<PathBuf> = Vec::new();
+    for event in events {
+        let kind = match event.kind {
+            EventKind::Create(_) => FsChangeKind::Created,
+            EventKind::Remove(_) => FsChangeKind::Removed,
+            EventKind::Modify(ModifyKind::Name(_)) => FsChangeKind::Renamed,
+            EventKind::Modify(ModifyKind::Metadata(_)) => continue,
+            EventKind::Modify(_) => FsChangeKind::Modified,
+            _ => continue,
+        };
+        touched.extend(event.paths.iter().cloned());
+        for path in &event.paths {
+            let name = path.file_name().and_then(|name| name.to_str());
+            if matches!(name, Some(".gitignore" | ".ignore")) {
+                if let Some(dir) = path.parent() {
+                    rules.load_dir(dir);
+                }
+            }
+        }
+        let paths: Vec<PathBuf> = event
+            .paths
+            .iter()
+            .filter(|p| !rules.is_ignored(p, p.is_dir()))
+            .cloned()
+            .collect();
+        if matches!(kind, FsChangeKind::Created | FsChangeKind::Renamed) {
+            new_dirs.extend(paths.iter().filter(|p| p.is_dir()).cloned());
+        }
+        if !paths.is_empty() {
+            changes.push(FsChange {
+                kind,
+                paths: paths
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect(),
+            });
+            visible.extend(paths);
+        }
+    }
+    if !changes.is_empty() {
+        if let Err(e) = app.emit(FS_CHANGE_EVENT, &changes) {
+            error!("推送目录变化事件失败: {}", e);
+        }
+    }
+    new_dirs.sort();
+    new_dirs.dedup();
+    watch_new_dirs(root, &mut rules, &new_dirs);
+    drop(rules);
+    touched.sort();
+    touched.dedup();
+    check_tracked_resources(app, &touched);
+    visible.sort();
+    visible.dedup();
+    code_index::notify_changed(visible);
+}
+
+fn check_tracked_resources(app: &AppHandle, touched: &[PathBuf]) {
+    let tracked = TRACKED_RESOURCES.lock().unwrap();
+    for path in touched {
+        let Some(original) = tracked.get(path) else {
+            continue;
+        };
+        let current = digest_file(path).ok();
+        let change = ResourceChange {
+            path: path.to_string_lossy().to_string(),
+            changed: current != Some(*original),
+            removed: current.is_none(),
+        };
+        if let Err(e) = app.emit(RESOURCE_CHANGE_EVENT, &change) {
+            error!("推送资源变化事件失败: {}", e);
+        }
+    }
+}
+
+fn digest_file(path: &Path) -> std::io::Result<u64> {
+    let content = fs::read(path)?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_ignore_files_take_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("web/generated")).unwrap();
+        fs::create_dir_all(root.join("node_modules/a")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("web/.gitignore"), "generated/\n!keep.log\n").unwrap();
+
+        let mut rules = IgnoreRules::new(root, &["node_modules".to_string()]);
+        rules.load_dir(root);
+        rules.load_dir(&root.join("web"));
+        assert!(rules.is_ignored(&root.join("node_modules/a"), true));
+        assert!(rules.is_ignored(&root.join("a.log"), false));
+        assert!(rules.is_ignored(&root.join("web/generated"), true));
+        assert!(rules.is_ignored(&root.join("web/generated/api.ts"), false));
+        assert!(rules.is_ignored(&root.join("web/b.log"), false));
+        assert!(!rules.is_ignored(&root.join("web/keep.log"), false));
+        assert!(!rules.is_ignored(&root.join("web/src/main.ts"), false));
+        assert!(!rules.is_ignored(root, true));
+    }
+
+    #[test]
+    fn ignored_directories_are_not_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["src/a", "src/b", "node_modules/x/y", "dist/assets"] {
+            fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "dist\n").unwrap();
+        let mut rules = IgnoreRules::new(root, &["node_modules".to_string()]);
+        let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, None, |_| {}).unwrap();
+        // 根目录、src、src/a、src/b
+        assert_eq!(watch_tree(&mut debouncer, &mut rules, root).unwrap(), 4);
+    }
+}
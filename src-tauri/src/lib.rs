@@ -1,16 +1,23 @@
-use function::file::{file_existed, save_file};
+use code_index::{get_index_status, resume_index, spawn_index_build, CodeSearchHit, IndexStatus};
+use function::file::{file_existed, load_ignore_patterns, save_file};
+use function::file_tree::{list_dir_children, FileTreePage};
+use function::git_diff::{read_git_diff, DiffFileStat};
+use function::watcher::{track_resource, untrack_resource, watch_project};
 use llm::context_builder::CodeGenRequest;
+use log::error;
 use serde::Deserialize;
-use serde_json::{json, Value};
-use std::io::{self, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use storage::chat_session::*;
 use storage::code_sample::*;
 use storage::datasource::*;
 use storage::init_db;
 use storage::sys_config::*;
 use task::code_gen_task::CodeGenTask;
 use task::{periodic_cleanup_inactive_tasks, TaskLog, TaskResult};
+use tauri::AppHandle;
 use tempfile::NamedTempFile;
+pub mod code_index;
 pub mod db;
 pub mod function;
 pub mod llm;
@@ -36,6 +43,16 @@ pub async fn run() {
     //定时清理过期的用户任务
     periodic_cleanup_inactive_tasks(120);
     tauri::Builder::default()
+        .setup(|app| {
+            //监听已配置的项目目录
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = watch_configured_project(handle).await {
+                    error!("启动项目目录监听失败: {}", e);
+                }
+            });
+            Ok(())
+        })
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
@@ -61,7 +78,19 @@ pub async fn run() {
             get_user_task_logs,
             get_user_task_result,
             save_generated_file,
-            is_file_exsited
+            is_file_exsited,
+            watch_project_dir,
+            track_file_resource,
+            untrack_file_resource,
+            build_code_index,
+            get_code_index_status,
+            search_code_index,
+            get_git_diff_files,
+            create_chat_session,
+            list_chat_sessions,
+            rename_chat_session,
+            delete_chat_session,
+            get_chat_turns
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -91,59 +120,103 @@ pub struct CodeFile {
     pub content: String,
 }
 
-#[tauri::command]
-async fn get_file_system(path: &str) -> Result<Vec<Value>, String> {
-    // Helper function to recursively walk the directory
-    fn walk_dir(path: &Path, parent_id: Option<&PathBuf>) -> Result<Value, io::Error> {
-        // Ensure the file name is valid and convert it to a string
-        let file_name = path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid file name"))?;
+/// 默认每次加载的目录子节点数量
+const FILE_TREE_PAGE_SIZE: usize = 200;
 
-        // Construct the entry object with absolute path as ID
-        let mut entry = json!({
-            "id": path.to_string_lossy(), // Use absolute path as ID
-            "label": file_name,
-            "isFolder": path.is_dir(),
-            "children": []
-        });
+#[tauri::command]
+async fn get_file_system(
+    path: &str,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<FileTreePage, String> {
+    let excludes = load_ignore_patterns().await.to_tauri_result()?;
+    // 排除规则相对于项目根目录，不在项目中的目录以自身为根目录
+    let root = get_config("root_source_path".to_string())
+        .await
+        .map_err(|e| e.to_string())?
+        .filter(|root| !root.is_empty() && Path::new(path).starts_with(root))
+        .unwrap_or_else(|| path.to_string());
+    list_dir_children(
+        Path::new(&root),
+        Path::new(path),
+        &excludes,
+        offset.unwrap_or(0),
+        limit.unwrap_or(FILE_TREE_PAGE_SIZE),
+    )
+    .to_tauri_result()
+}
 
-        if let Some(pid) = parent_id {
-            entry["parentId"] = json!(pid.to_string_lossy());
+async fn watch_configured_project(app: AppHandle) -> Result<(), Error> {
+    if let Some(root) = get_config("root_source_path".to_string()).await? {
+        if !root.is_empty() {
+            let excludes = load_ignore_patterns().await?;
+            watch_project(app, Path::new(&root), &excludes)?;
+            resume_index(PathBuf::from(root));
         }
+    }
+    Ok(())
+}
 
-        // If it's a directory, recursively process its children
-        if path.is_dir() {
-            let mut children = Vec::new();
-            for entry in std::fs::read_dir(path)? {
-                let entry = entry?;
-                let entry_path = entry.path();
-                let parent_path = entry_path.parent().map(PathBuf::from);
-                children.push(walk_dir(&entry_path, parent_path.as_ref())?);
-            }
-            entry["children"] = json!(children);
-        }
+#[tauri::command]
+async fn watch_project_dir(app: AppHandle, path: String) -> Result<(), String> {
+    let excludes = load_ignore_patterns().await.to_tauri_result()?;
+    watch_project(app, Path::new(&path), &excludes).to_tauri_result()?;
+    resume_index(PathBuf::from(path));
+    Ok(())
+}
 
-        Ok(entry)
-    }
+#[tauri::command]
+async fn track_file_resource(path: String) -> Result<(), String> {
+    track_resource(Path::new(&path)).to_tauri_result()
+}
+
+#[tauri::command]
+async fn untrack_file_resource(path: String) -> Result<(), String> {
+    untrack_resource(Path::new(&path));
+    Ok(())
+}
 
-    // Start walking the directory from the given path
-    let root_path = Path::new(path);
+#[tauri::command]
+async fn build_code_index() -> Result<(), String> {
+    let root = get_config("root_source_path".to_string())
+        .await
+        .map_err(|e| e.to_string())?
+        .filter(|root| !root.is_empty())
+        .ok_or_else(|| "当前未配置项目目录".to_string())?;
+    spawn_index_build(PathBuf::from(root));
+    Ok(())
+}
 
-    // Validate the input path
-    if !root_path.exists() {
-        return Err(format!("Path does not exist: {}", path));
-    }
+#[tauri::command]
+async fn get_code_index_status() -> Result<IndexStatus, String> {
+    Ok(get_index_status())
+}
 
-    if !root_path.is_dir() {
-        return Err(format!("Path is not a directory: {}", path));
-    }
+#[tauri::command]
+async fn search_code_index(
+    query: String,
+    top_k: Option<usize>,
+) -> Result<Vec<CodeSearchHit>, String> {
+    let root = get_config("root_source_path".to_string())
+        .await
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    code_index::search(&root, &query, top_k.unwrap_or(code_index::DEFAULT_TOP_K))
+        .await
+        .to_tauri_result()
+}
 
-    match walk_dir(root_path, None) {
-        Ok(root) => Ok(vec![root]),
-        Err(e) => Err(format!("Error reading directory: {}", e)),
-    }
+//列出当前项目中指定变更涉及的文件，用于在添加git_diff资源前校验并预览
+#[tauri::command]
+async fn get_git_diff_files(spec: String) -> Result<Vec<DiffFileStat>, String> {
+    let root = get_config("root_source_path".to_string())
+        .await
+        .map_err(|e| e.to_string())?
+        .filter(|root| !root.is_empty())
+        .ok_or_else(|| "当前未配置项目目录".to_string())?;
+    read_git_diff(Path::new(&root), &spec)
+        .map(|diff| diff.files)
+        .to_tauri_result()
 }
 
 #[tauri::command]
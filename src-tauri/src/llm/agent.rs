@@ -1,10 +1,51 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::storage::sys_config::get_config;
 
-use super::{ollama::OllamaAgent, openai::OpenAIAgent};
+use super::{
+    anthropic::AnthropicAgent,
+    azure::AzureOpenAIAgent,
+    custom::{CustomHttpAgent, CustomHttpOptions},
+    gemini::GeminiAgent,
+    http::HttpOptions,
+    image::ImageInput,
+    ollama::OllamaAgent,
+    openai::OpenAIAgent,
+    structured::OutputSchema,
+};
+
+/// 支持图片输入的常见模型名称片段，用于未显式配置supportsVision时的判断。
+/// 片段需要完整出现在名称中，前后只能是名称边界或"-"、":"、"/"等分隔符，避免vl匹配到其他单词。
+/// o1、o3系列中有只支持文本的mini等版本，无法按名称区分，需要显式配置supportsVision
+const VISION_MODEL_HINTS: &[&str] = &[
+    "gpt-4o",
+    "chatgpt-4o",
+    "gpt-4.1",
+    "gpt-4-turbo",
+    "gpt-5",
+    "o4-mini",
+    "vision",
+    "vl",
+    "qwen2.5vl",
+    "llava",
+    "bakllava",
+    "minicpm-v",
+    "moondream",
+    "gemma3",
+    "pixtral",
+    "claude-3",
+    "claude-sonnet",
+    "claude-opus",
+    "gemini",
+    "glm-4v",
+];
+
+pub const VISION_NOT_SUPPORTED: &str =
+    "当前选择的模型不支持图片输入，请切换到支持视觉的模型，或移除图片资源";
 
 #[async_trait]
 pub trait AIAgent: Send + Sync {
@@ -14,6 +55,410 @@ pub trait AIAgent: Send + Sync {
     }
 
     async fn generate_raw_response(&self, prompt: &str) -> Result<String>;
+
+    /// 当前模型是否支持图片输入
+    fn supports_vision(&self) -> bool {
+        false
+    }
+
+    /// 提交附带图片的问题，没有图片时等同于generate_response
+    async fn generate_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        if images.is_empty() {
+            return self.generate_response(prompt).await;
+        }
+        if !self.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        let raw_response = self
+            .generate_raw_response_with_images(prompt, images)
+            .await?;
+        Ok(remove_think_tags(&raw_response))
+    }
+
+    async fn generate_raw_response_with_images(
+        &self,
+        _prompt: &str,
+        _images: &[ImageInput],
+    ) -> Result<String> {
+        Err(anyhow!("当前LLM供应商不支持图片输入"))
+    }
+
+    /// 要求模型按schema返回JSON。支持结构化输出的供应商会在请求中声明，其余供应商只依靠提示词约束，
+    /// 返回内容需要用structured::parse_llm_json解析
+    async fn generate_json_response(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: &OutputSchema,
+    ) -> Result<String> {
+        self.generate_stream(prompt, images, Some(schema), &mut |_| Ok(()))
+            .await
+    }
+
+    /// 流式生成，每收到一段回复内容调用一次on_delta，on_delta返回错误时立即停止生成。
+    /// 返回完整的回复内容（已去除思考内容），指定schema时的含义同generate_json_response
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        if !images.is_empty() && !self.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        let raw_response = self
+            .generate_raw_stream(prompt, images, schema, on_delta)
+            .await?;
+        Ok(remove_think_tags(&raw_response))
+    }
+
+    /// 不支持流式输出及结构化输出的供应商在收到完整回复后一次性回调
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        _schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        let response = if images.is_empty() {
+            self.generate_raw_response(prompt).await?
+        } else {
+            self.generate_raw_response_with_images(prompt, images)
+                .await?
+        };
+        on_delta(&response)?;
+        Ok(response)
+    }
+
+    /// 多轮对话的流式生成，messages按时间顺序排列且最后一条为用户消息，其余同generate_stream
+    async fn generate_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        if messages.iter().any(|m| !m.images.is_empty()) && !self.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        let reply = self
+            .generate_raw_chat_stream(messages, schema, on_delta)
+            .await?;
+        Ok(ChatReply {
+            text: remove_think_tags(&reply.text),
+            ..reply
+        })
+    }
+
+    /// 不支持多轮对话的供应商将之前的对话整理为文本，与最后一条消息合并后提交。
+    /// 这类供应商不返回结束原因，回复是否被截断只能由调用方根据内容判断
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let (prompt, images) = flatten_messages(messages)?;
+        self.generate_raw_stream(&prompt, &images, schema, on_delta)
+            .await
+            .map(ChatReply::new)
+    }
+
+    /// 是否支持工具调用
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// 提交对话并允许模型调用tools中的工具。模型发起的调用在返回结果的tool_calls中，
+    /// 由调用方执行后将结果作为工具消息追加到对话中再次提交；没有调用时text为模型的最终回复
+    async fn generate_tool_chat(
+        &self,
+        _messages: &[ChatMessage],
+        _tools: &[ToolSpec],
+    ) -> Result<ChatReply> {
+        Err(anyhow!("当前LLM供应商不支持工具调用"))
+    }
+
+    /// 实际使用的生成参数
+    fn generation_params(&self) -> &GenerationParams;
+}
+
+/// 流式输出的回调，参数为新收到的回复片段
+pub type StreamCallback<'a> = dyn FnMut(&str) -> Result<()> + Send + 'a;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatRole {
+    User,
+    Assistant,
+    /// 工具调用的结果
+    Tool,
+}
+
+/// 多轮对话中的一条消息，只有用户消息可以附带图片
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    pub images: Vec<ImageInput>,
+    /// 助手消息中模型发起的工具调用
+    pub tool_calls: Vec<ToolCall>,
+    /// 工具消息对应的调用，content为调用结果
+    pub tool_call: Option<ToolCall>,
+}
+
+impl ChatMessage {
+    pub fn user(content: &str, images: &[ImageInput]) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.to_string(),
+            images: images.to_vec(),
+            tool_calls: Vec::new(),
+            tool_call: None,
+        }
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.to_string(),
+            images: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call: None,
+        }
+    }
+
+    pub fn assistant_tool_calls(content: &str, tool_calls: &[ToolCall]) -> Self {
+        Self {
+            tool_calls: tool_calls.to_vec(),
+            ..Self::assistant(content)
+        }
+    }
+
+    pub fn tool_result(tool_call: &ToolCall, content: &str) -> Self {
+        Self {
+            role: ChatRole::Tool,
+            tool_call: Some(tool_call.clone()),
+            ..Self::assistant(content)
+        }
+    }
+}
+
+/// 提供给模型调用的工具，parameters为参数的JSON Schema
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Value,
+}
+
+/// 模型发起的一次工具调用
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    /// 调用ID，工具结果通过该ID与调用对应。不返回ID的供应商按序号生成
+    pub id: String,
+    pub name: String,
+    /// 调用参数，模型返回的不是合法JSON时为原始文本
+    pub arguments: Value,
+    /// Gemini思考模型返回的签名，需要随调用原样传回
+    pub thought_signature: Option<String>,
+}
+
+impl ToolCall {
+    /// 解析流式拼接得到的参数文本，空文本视为没有参数
+    pub fn parse_arguments(arguments: &str) -> Value {
+        if arguments.trim().is_empty() {
+            return Value::Object(Default::default());
+        }
+        serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+    }
+
+    /// 参数的JSON文本，OpenAI兼容接口要求以字符串传回
+    pub fn arguments_text(&self) -> String {
+        match &self.arguments {
+            Value::String(raw) => raw.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// 多轮对话的回复
+#[derive(Debug, Default)]
+pub struct ChatReply {
+    pub text: String,
+    /// 回复因达到最大Token数而被截断
+    pub truncated: bool,
+    /// 模型发起的工具调用，仅在提交了工具时可能不为空
+    pub tool_calls: Vec<ToolCall>,
+    /// 需要在任务日志中提示用户的问题，如提示词可能超出上下文长度
+    pub warnings: Vec<String>,
+    /// 本次调用的token用量
+    pub usage: TokenUsage,
+}
+
+/// 一次调用的token用量，供应商未返回的项为None
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TokenUsage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    /// 思考消耗的token，仅部分供应商单独返回，其余供应商计入输出
+    pub thinking_tokens: Option<u64>,
+}
+
+impl TokenUsage {
+    /// 累加多次调用的用量，某次调用未返回的项按已知部分累加
+    pub fn add(&mut self, other: &TokenUsage) {
+        let sum = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.input_tokens = sum(self.input_tokens, other.input_tokens);
+        self.output_tokens = sum(self.output_tokens, other.output_tokens);
+        self.thinking_tokens = sum(self.thinking_tokens, other.thinking_tokens);
+    }
+
+    /// 如"输入120个token，输出56个token"，只列出已知的项，全部未知时返回None
+    pub fn describe(&self) -> Option<String> {
+        let parts: Vec<String> = [
+            ("输入", self.input_tokens),
+            ("输出", self.output_tokens),
+            ("思考", self.thinking_tokens),
+        ]
+        .into_iter()
+        .filter_map(|(label, tokens)| tokens.map(|t| format!("{}{}个token", label, t)))
+        .collect();
+        (!parts.is_empty()).then(|| parts.join("，"))
+    }
+}
+
+impl ChatReply {
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+/// 将多轮对话整理为单条提示词，供不支持多轮对话的供应商使用。只有一条消息时直接返回其内容
+pub fn flatten_messages(messages: &[ChatMessage]) -> Result<(String, Vec<ImageInput>)> {
+    let (last, history) = messages
+        .split_last()
+        .ok_or_else(|| anyhow!("对话消息不能为空"))?;
+    if history.is_empty() {
+        return Ok((last.content.clone(), last.images.clone()));
+    }
+    let mut prompt = String::new();
+    for message in messages {
+        let role = match message.role {
+            ChatRole::User => "用户",
+            ChatRole::Assistant => "助手",
+            ChatRole::Tool => "工具",
+        };
+        prompt.push_str(&format!("【{}】\n{}\n\n", role, message.content));
+    }
+    let images = messages
+        .iter()
+        .flat_map(|m| m.images.iter().cloned())
+        .collect();
+    Ok((prompt.trim_end().to_string(), images))
+}
+
+/// 生成参数，未配置的参数使用模型默认值。供应商不支持的参数会被忽略并记录警告
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationParams {
+    #[serde(rename = "maxTokens")]
+    pub max_tokens: Option<u32>,
+    #[serde(rename = "temperature")]
+    pub temperature: Option<f64>,
+    #[serde(rename = "topP")]
+    pub top_p: Option<f64>,
+    /// 随机种子，配合较低的temperature可以让相同的输入得到基本一致的结果
+    #[serde(rename = "seed")]
+    pub seed: Option<u64>,
+    /// 停止序列
+    #[serde(rename = "stop")]
+    pub stop: Vec<String>,
+    /// 上下文长度（num_ctx），仅对Ollama有效。Ollama默认的上下文较短，超出部分会被直接截断
+    #[serde(rename = "contextLength")]
+    pub context_length: Option<u32>,
+}
+
+impl GenerationParams {
+    /// 以other中已配置的参数覆盖当前参数
+    pub fn merge(&self, other: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            max_tokens: other.max_tokens.or(self.max_tokens),
+            temperature: other.temperature.or(self.temperature),
+            top_p: other.top_p.or(self.top_p),
+            seed: other.seed.or(self.seed),
+            stop: if other.stop.is_empty() {
+                self.stop.clone()
+            } else {
+                other.stop.clone()
+            },
+            context_length: other.context_length.or(self.context_length),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(anyhow!("temperature需在0到2之间，当前为{}", temperature));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if top_p <= 0.0 || top_p > 1.0 {
+                return Err(anyhow!("top_p需大于0且不大于1，当前为{}", top_p));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err(anyhow!("最大Token数需大于0"));
+        }
+        Ok(())
+    }
+
+    /// 已配置的非空停止序列
+    pub fn stop_sequences(&self) -> Vec<String> {
+        self.stop
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// 用于日志的参数说明
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(max_tokens) = self.max_tokens {
+            parts.push(format!("最大Token数={}", max_tokens));
+        }
+        if let Some(temperature) = self.temperature {
+            parts.push(format!("temperature={}", temperature));
+        }
+        if let Some(top_p) = self.top_p {
+            parts.push(format!("top_p={}", top_p));
+        }
+        if let Some(seed) = self.seed {
+            parts.push(format!("seed={}", seed));
+        }
+        let stop = self.stop_sequences();
+        if !stop.is_empty() {
+            parts.push(format!("stop={:?}", stop));
+        }
+        if let Some(context_length) = self.context_length {
+            parts.push(format!("上下文长度={}", context_length));
+        }
+        if parts.is_empty() {
+            "使用模型默认参数".to_string()
+        } else {
+            parts.join("，")
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,21 +471,233 @@ pub struct LLMProvider {
     pub api_key: String,
     #[serde(rename = "model")]
     pub model: String,
-    #[serde(rename = "maxTokens")]
-    pub max_tokens: Option<u32>,
+    /// 生成参数，与其他字段平铺保存，兼容原有的maxTokens
+    #[serde(flatten)]
+    pub params: GenerationParams,
+    /// 模型是否支持图片输入，未配置时根据模型名称判断
+    #[serde(rename = "supportsVision", default)]
+    pub supports_vision: Option<bool>,
+    /// 扩展思考的Token预算，仅对Anthropic有效，未配置时不开启
+    #[serde(rename = "thinkingBudget", default)]
+    pub thinking_budget: Option<u32>,
+    /// Azure OpenAI的部署名称，未配置时使用模型名称
+    #[serde(rename = "deployment", default)]
+    pub deployment: Option<String>,
+    /// Azure OpenAI的API版本，如2024-10-21
+    #[serde(rename = "apiVersion", default)]
+    pub api_version: Option<String>,
+    /// Azure OpenAI的认证方式
+    #[serde(rename = "azureAuth", default)]
+    pub azure_auth: AzureAuthType,
+    /// 使用Entra应用（客户端密码）认证时的租户ID、客户端ID及客户端密码
+    #[serde(rename = "tenantId", default)]
+    pub tenant_id: Option<String>,
+    #[serde(rename = "clientId", default)]
+    pub client_id: Option<String>,
+    #[serde(rename = "clientSecret", default)]
+    pub client_secret: Option<String>,
+    /// 自定义HTTP接口的请求模板及响应映射，仅对Custom有效
+    #[serde(rename = "customHttp", default)]
+    pub custom_http: Option<CustomHttpOptions>,
+    /// 要求返回JSON时使用的结构化输出方式
+    #[serde(rename = "structuredOutput", default)]
+    pub structured_output: StructuredOutput,
+    /// 代码生成时回复文件内容的格式
+    #[serde(rename = "outputProtocol", default)]
+    pub output_protocol: OutputProtocol,
+    /// 超时及重试设置，与其他字段平铺保存
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+/// 结构化输出方式。部分OpenAI兼容接口不支持JSON Schema，可以退回到JSON模式或只依靠提示词约束
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StructuredOutput {
+    /// 按JSON Schema约束输出，Anthropic通过强制调用工具实现
+    #[default]
+    Schema,
+    /// 只要求输出合法的JSON
+    JsonObject,
+    /// 不在请求中声明，只依靠提示词约束
+    Off,
+}
+
+/// 代码生成的回复格式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputProtocol {
+    /// 文件列表为JSON，文件内容需要转义后放在fileContent中
+    #[default]
+    Json,
+    /// 每个文件为一个文件块：路径标题行加代码块，文件内容原样输出，
+    /// 适合转义大段代码时容易出错的模型
+    FileBlock,
+}
+
+impl LLMProvider {
+    pub fn vision_enabled(&self) -> bool {
+        self.supports_vision
+            .unwrap_or_else(|| is_vision_model(&self.model))
+    }
 }
+
+/// 按模型名称判断是否支持图片输入
+fn is_vision_model(model: &str) -> bool {
+    let model = model.to_lowercase();
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_ascii_alphanumeric());
+    VISION_MODEL_HINTS.iter().any(|hint| {
+        model.match_indices(hint).any(|(start, _)| {
+            is_boundary(model[..start].chars().next_back())
+                && is_boundary(model[start + hint.len()..].chars().next())
+        })
+    })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LLMProviderType {
     OpenAI,
     Ollama,
+    Anthropic,
+    AzureOpenAI,
+    Gemini,
+    Custom,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AzureAuthType {
+    /// 使用apiKey作为api-key请求头
+    #[default]
+    ApiKey,
+    /// apiKey中填写的是已获取的Entra访问令牌，如az account get-access-token的输出
+    EntraToken,
+    /// 使用Entra应用的客户端密码获取访问令牌，令牌过期前自动刷新
+    ClientSecret,
 }
 
-pub async fn build_agent(preamble: &str) -> Result<Box<dyn AIAgent>> {
-    let llm_provider_conf = get_config("current_llm_provider".to_string()).await?;
-    if llm_provider_conf.is_none() {
-        return Err(anyhow::anyhow!("当前未配置LLM供应商"));
+/// 命名的LLM配置，切换模型时不必重新填写密钥等信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LLMProfile {
+    pub name: String,
+    pub provider: LLMProvider,
+}
+
+/// LLM配置列表及各步骤使用的配置，对应sys_config中的llm_profiles（JSON）
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LLMProfileSettings {
+    pub profiles: Vec<LLMProfile>,
+    #[serde(rename = "defaultProfile")]
+    pub default_profile: Option<String>,
+    /// 意图分析、目标文件预测等轻量步骤使用的配置，可以指定更便宜、更快的模型
+    #[serde(rename = "intentProfile")]
+    pub intent_profile: Option<String>,
+    /// 代码生成步骤使用的配置
+    #[serde(rename = "generationProfile")]
+    pub generation_profile: Option<String>,
+}
+
+pub async fn load_llm_profile_settings() -> Result<LLMProfileSettings> {
+    match get_config("llm_profiles".to_string()).await? {
+        Some(conf) => {
+            serde_json::from_str(&conf).map_err(|e| anyhow!("LLM配置列表格式错误: {}", e))
+        }
+        None => Ok(LLMProfileSettings::default()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LLMStep {
+    Intent,
+    Generation,
+}
+
+/// 选择某个步骤使用的LLM配置：意图分析优先使用为该步骤指定的配置，其次是任务指定的配置；
+/// 代码生成优先使用任务指定的配置，其次是为该步骤指定的配置；都未指定时使用默认配置。
+/// 尚未添加任何命名配置时沿用current_llm_provider
+pub async fn resolve_llm_profile(step: LLMStep, task_profile: Option<&str>) -> Result<LLMProfile> {
+    let settings = load_llm_profile_settings().await?;
+    if settings.profiles.is_empty() {
+        let conf = get_config("current_llm_provider".to_string())
+            .await?
+            .ok_or_else(|| anyhow!("当前未配置LLM供应商"))?;
+        return Ok(LLMProfile {
+            name: "默认".to_string(),
+            provider: serde_json::from_str(&conf)?,
+        });
+    }
+    let named = |name: &Option<String>| {
+        name.as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(str::to_string)
+    };
+    let task_profile = named(&task_profile.map(str::to_string));
+    let step_profile = match step {
+        LLMStep::Intent => named(&settings.intent_profile),
+        LLMStep::Generation => named(&settings.generation_profile),
+    };
+    let preferred = match step {
+        LLMStep::Intent => step_profile.or(task_profile),
+        LLMStep::Generation => task_profile.or(step_profile),
+    };
+    match preferred.or_else(|| named(&settings.default_profile)) {
+        Some(name) => settings
+            .profiles
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow!("LLM配置\"{}\"不存在", name)),
+        // 未指定默认配置时使用第一个
+        None => Ok(settings.profiles.into_iter().next().unwrap()),
+    }
+}
+
+/// 按步骤及任务指定的配置构建Agent，task_params中已配置的参数会覆盖LLM配置中的生成参数
+pub async fn build_agent(
+    step: LLMStep,
+    task_profile: Option<&str>,
+    task_params: Option<&GenerationParams>,
+    preamble: &str,
+) -> Result<Box<dyn AIAgent>> {
+    let profile = resolve_llm_profile(step, task_profile).await?;
+    create_profile_agent(step, &profile, task_params, preamble)
+}
+
+/// 使用已选定的LLM配置构建Agent，调用方需要根据配置调整提示词时使用
+pub fn create_profile_agent(
+    step: LLMStep,
+    profile: &LLMProfile,
+    task_params: Option<&GenerationParams>,
+    preamble: &str,
+) -> Result<Box<dyn AIAgent>> {
+    let params = match task_params {
+        Some(task_params) => profile.provider.params.merge(task_params),
+        None => profile.provider.params.clone(),
+    };
+    info!(
+        "{}使用LLM配置：{}（{}），{}",
+        match step {
+            LLMStep::Intent => "意图分析",
+            LLMStep::Generation => "代码生成",
+        },
+        profile.name,
+        profile.provider.model,
+        params.describe()
+    );
+    create_agent(&profile.provider, params, preamble)
+}
+
+pub fn create_agent(
+    llm_provider: &LLMProvider,
+    mut params: GenerationParams,
+    preamble: &str,
+) -> Result<Box<dyn AIAgent>> {
+    params.validate()?;
+    if !matches!(llm_provider.name, LLMProviderType::Ollama) && params.context_length.is_some() {
+        warn!("上下文长度仅对Ollama有效，已忽略");
+        params.context_length = None;
     }
-    let llm_provider: LLMProvider = serde_json::from_str(&llm_provider_conf.unwrap())?;
     match llm_provider.name {
         LLMProviderType::OpenAI => {
             let agent = OpenAIAgent::new(
@@ -48,11 +705,58 @@ pub async fn build_agent(preamble: &str) -> Result<Box<dyn AIAgent>> {
                 &llm_provider.api_key,
                 &llm_provider.model,
                 preamble,
-            );
+                params,
+                llm_provider.vision_enabled(),
+            )
+            .with_structured_output(llm_provider.structured_output)
+            .with_http_options(llm_provider.http.clone());
             Ok(Box::new(agent))
         }
         LLMProviderType::Ollama => {
-            let agent = OllamaAgent::new(&llm_provider.base_url, &llm_provider.model, preamble);
+            let agent = OllamaAgent::new(
+                &llm_provider.base_url,
+                &llm_provider.model,
+                preamble,
+                params,
+                llm_provider.vision_enabled(),
+            )
+            .with_structured_output(llm_provider.structured_output)
+            .with_http_options(llm_provider.http.clone());
+            Ok(Box::new(agent))
+        }
+        LLMProviderType::Anthropic => {
+            let agent = AnthropicAgent::new(
+                &llm_provider.base_url,
+                &llm_provider.api_key,
+                &llm_provider.model,
+                preamble,
+                params,
+                llm_provider.thinking_budget,
+                llm_provider.vision_enabled(),
+            )?
+            .with_structured_output(llm_provider.structured_output)
+            .with_http_options(llm_provider.http.clone());
+            Ok(Box::new(agent))
+        }
+        LLMProviderType::AzureOpenAI => {
+            let agent = AzureOpenAIAgent::new(llm_provider, params, preamble)?;
+            Ok(Box::new(agent))
+        }
+        LLMProviderType::Gemini => {
+            let agent = GeminiAgent::new(
+                &llm_provider.base_url,
+                &llm_provider.api_key,
+                &llm_provider.model,
+                preamble,
+                params,
+                llm_provider.vision_enabled(),
+            )
+            .with_structured_output(llm_provider.structured_output)
+            .with_http_options(llm_provider.http.clone());
+            Ok(Box::new(agent))
+        }
+        LLMProviderType::Custom => {
+            let agent = CustomHttpAgent::new(llm_provider, params, preamble)?;
             Ok(Box::new(agent))
         }
     }
@@ -94,3 +798,88 @@ pub fn remove_think_tags(response: &str) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_describes_only_known_counts() {
+        assert_eq!(TokenUsage::default().describe(), None);
+        let usage = TokenUsage {
+            input_tokens: Some(120),
+            output_tokens: None,
+            thinking_tokens: Some(30),
+        };
+        assert_eq!(
+            usage.describe().as_deref(),
+            Some("输入120个token，思考30个token")
+        );
+    }
+
+    #[test]
+    fn usage_adds_up_across_calls() {
+        let mut usage = TokenUsage {
+            input_tokens: Some(100),
+            output_tokens: Some(40),
+            thinking_tokens: None,
+        };
+        usage.add(&TokenUsage {
+            input_tokens: Some(150),
+            output_tokens: Some(20),
+            thinking_tokens: Some(5),
+        });
+        assert_eq!(usage.input_tokens, Some(250));
+        assert_eq!(usage.output_tokens, Some(60));
+        assert_eq!(usage.thinking_tokens, Some(5));
+    }
+
+    #[test]
+    fn vision_hints_match_whole_name_parts() {
+        for model in [
+            "gpt-4o-mini",
+            "chatgpt-4o-latest",
+            "openai/gpt-4.1",
+            "o4-mini",
+            "qwen2.5-vl-72b-instruct",
+            "qwen2.5vl:7b",
+            "llama3.2-vision:11b",
+            "llava:13b",
+            "gemma3:12b",
+            "claude-3-5-sonnet-latest",
+            "gemini-2.5-flash",
+            "glm-4v-plus",
+        ] {
+            assert!(is_vision_model(model), "{} 应当支持图片", model);
+        }
+        for model in [
+            "o1-mini",
+            "o3-mini",
+            "gpt-4",
+            "gpt-3.5-turbo",
+            "deepseek-chat",
+            "qwen2.5-coder:7b",
+            "devlin-7b",
+            "llama3.1:8b",
+            "gemma2:9b",
+        ] {
+            assert!(!is_vision_model(model), "{} 不应支持图片", model);
+        }
+    }
+
+    #[test]
+    fn flattened_history_keeps_roles_in_order() {
+        let single = [ChatMessage::user("只有一条", &[])];
+        assert_eq!(flatten_messages(&single).unwrap().0, "只有一条");
+
+        let messages = [
+            ChatMessage::user("问题", &[]),
+            ChatMessage::assistant("回答"),
+            ChatMessage::user("继续", &[]),
+        ];
+        let (prompt, images) = flatten_messages(&messages).unwrap();
+        assert_eq!(prompt, "【用户】\n问题\n\n【助手】\n回答\n\n【用户】\n继续");
+        assert!(images.is_empty());
+        assert!(flatten_messages(&[]).is_err());
+    }
+}
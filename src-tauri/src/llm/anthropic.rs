@@ -0,0 +1,794 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use super::{
+    agent::{
+        AIAgent, ChatMessage, ChatReply, ChatRole, GenerationParams, StreamCallback,
+        StructuredOutput, TokenUsage, ToolCall, ToolSpec, VISION_NOT_SUPPORTED,
+    },
+    http::{next_chunk, send_with_retry, HttpOptions},
+    image::ImageInput,
+    sse::{SseDecoder, SseEvent},
+    structured::OutputSchema,
+};
+
+const PROVIDER_NAME: &str = "Anthropic接口";
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Messages API要求必须指定max_tokens，未配置时使用该值
+const DEFAULT_MAX_TOKENS: u32 = 8192;
+/// 扩展思考的最小预算
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// 直接调用Anthropic Messages API的Agent。始终使用流式响应，避免长时间生成时连接被中断；
+/// 扩展思考的内容与回复正文分开处理，不会混入生成结果
+pub struct AnthropicAgent {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    model: String,
+    preamble: String,
+    max_tokens: u32,
+    /// 实际使用的生成参数，不支持的参数已去除
+    params: GenerationParams,
+    thinking_budget: Option<u32>,
+    vision: bool,
+    structured_output: StructuredOutput,
+    http: HttpOptions,
+}
+
+/// 一次Messages API调用的结果
+#[derive(Debug, Default)]
+pub struct AnthropicResponse {
+    pub text: String,
+    /// 扩展思考的内容
+    pub thinking: String,
+    /// end_turn、max_tokens、stop_sequence、refusal等
+    pub stop_reason: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 模型发起的工具调用，不含结构化输出强制调用的工具
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// 流式响应中正在接收的内容块
+enum ContentBlock {
+    Text,
+    Thinking,
+    /// 结构化输出强制调用的工具，参数作为回复正文
+    OutputTool,
+    /// 模型发起的工具调用，参数以JSON片段的形式逐段拼接
+    ToolUse {
+        id: String,
+        name: String,
+        input: String,
+    },
+    Other,
+}
+
+impl AnthropicAgent {
+    pub fn new(
+        base_url: &str,
+        api_key: &str,
+        model: &str,
+        preamble: &str,
+        mut params: GenerationParams,
+        thinking_budget: Option<u32>,
+        vision: bool,
+    ) -> Result<Self> {
+        let max_tokens = params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+        params.max_tokens = Some(max_tokens);
+        if let Some(temperature) = params.temperature {
+            if temperature > 1.0 {
+                return Err(anyhow!(
+                    "Anthropic的temperature需在0到1之间，当前为{}",
+                    temperature
+                ));
+            }
+        }
+        if params.seed.take().is_some() {
+            warn!("Anthropic不支持seed参数，已忽略");
+        }
+        if let Some(budget) = thinking_budget {
+            if budget < MIN_THINKING_BUDGET || budget >= max_tokens {
+                return Err(anyhow!(
+                    "思考预算需要不小于{}且小于最大Token数{}，当前为{}",
+                    MIN_THINKING_BUDGET,
+                    max_tokens,
+                    budget
+                ));
+            }
+            // 开启扩展思考时不能调整temperature及top_p
+            let temperature = params.temperature.take();
+            let top_p = params.top_p.take();
+            if temperature.is_some() || top_p.is_some() {
+                warn!("开启扩展思考时不支持temperature及top_p参数，已忽略");
+            }
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint: messages_endpoint(base_url),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            preamble: preamble.to_string(),
+            max_tokens,
+            params,
+            thinking_budget,
+            vision,
+            structured_output: StructuredOutput::default(),
+            http: HttpOptions::default(),
+        })
+    }
+
+    pub fn with_structured_output(mut self, structured_output: StructuredOutput) -> Self {
+        self.structured_output = structured_output;
+        self
+    }
+
+    pub fn with_http_options(mut self, http: HttpOptions) -> Self {
+        self.client = http.client();
+        self.http = http;
+        self
+    }
+
+    /// Messages API没有JSON输出模式，按schema输出时强制模型调用一个以schema为参数的工具，
+    /// 工具参数即为结果。开启扩展思考时不能强制调用工具，只依靠提示词约束
+    fn output_tool(&self, schema: Option<&OutputSchema>) -> Option<Value> {
+        let schema = schema?;
+        if self.structured_output != StructuredOutput::Schema {
+            return None;
+        }
+        if self.thinking_budget.is_some() {
+            warn!("开启扩展思考时不支持强制调用工具，结构化输出只依靠提示词约束");
+            return None;
+        }
+        Some(json!({
+            "name": schema.name,
+            "description": schema.description,
+            "input_schema": schema.schema,
+        }))
+    }
+
+    fn request_body(
+        &self,
+        messages: &[ChatMessage],
+        output_tool: Option<&Value>,
+        tools: &[ToolSpec],
+    ) -> Value {
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": self.max_tokens,
+            "messages": request_messages(messages),
+            "stream": true,
+        });
+        if !self.preamble.is_empty() {
+            body["system"] = json!(self.preamble);
+        }
+        if let Some(temperature) = self.params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = self.params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        let stop = self.params.stop_sequences();
+        if !stop.is_empty() {
+            body["stop_sequences"] = json!(stop);
+        }
+        // 开启扩展思考时，后续请求需要原样传回思考内容及其签名才能继续工具调用，调用工具时不开启
+        if let Some(budget) = self.thinking_budget.filter(|_| tools.is_empty()) {
+            body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+        }
+        if let Some(tool) = output_tool {
+            body["tool_choice"] = json!({ "type": "tool", "name": tool["name"] });
+            body["tools"] = json!([tool]);
+        }
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "input_schema": tool.parameters
+                    })
+                })
+                .collect();
+        }
+        body
+    }
+
+    /// 提交对话消息，最后一条为用户消息
+    pub async fn send(&self, messages: &[ChatMessage]) -> Result<AnthropicResponse> {
+        self.send_stream(messages, None, &[], &mut |_| Ok(())).await
+    }
+
+    /// 提交对话消息，每收到一段回复正文（不含思考内容）调用一次on_delta。
+    /// 按schema输出时工具参数的JSON作为回复正文；提供tools时模型可以调用其中的工具
+    pub async fn send_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        tools: &[ToolSpec],
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<AnthropicResponse> {
+        let output_tool = self.output_tool(schema);
+        let output_tool_name = output_tool
+            .as_ref()
+            .and_then(|tool| tool["name"].as_str())
+            .map(str::to_string);
+        let request = self
+            .client
+            .post(&self.endpoint)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&self.request_body(messages, output_tool.as_ref(), tools));
+        let mut http_response =
+            send_with_retry(PROVIDER_NAME, &self.http, request, error_message).await?;
+
+        let mut response = AnthropicResponse::default();
+        // 内容块序号到正在接收的内容块的映射
+        let mut blocks: HashMap<u64, ContentBlock> = HashMap::new();
+        let output_tool = output_tool_name.as_deref();
+        let mut decoder = SseDecoder::new();
+        let read_timeout = self.http.read_timeout();
+        while let Some(chunk) = next_chunk(PROVIDER_NAME, &mut http_response, read_timeout).await? {
+            for event in decoder.feed(&chunk) {
+                handle_event(&event, &mut response, &mut blocks, output_tool, on_delta)?;
+            }
+        }
+        if let Some(event) = decoder.finish() {
+            handle_event(&event, &mut response, &mut blocks, output_tool, on_delta)?;
+        }
+
+        info!(
+            "Anthropic调用完成，模型：{}，输入{}个token，输出{}个token，结束原因：{}",
+            self.model,
+            response.input_tokens,
+            response.output_tokens,
+            response.stop_reason.as_deref().unwrap_or("未知")
+        );
+        if !response.thinking.is_empty() {
+            debug!("模型思考过程：{}", response.thinking);
+        }
+        match response.stop_reason.as_deref() {
+            Some("max_tokens") => warn!(
+                "回复达到最大Token数{}的限制，内容可能不完整",
+                self.max_tokens
+            ),
+            Some("refusal") => return Err(anyhow!("模型拒绝回答该问题")),
+            _ => {}
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl AIAgent for AnthropicAgent {
+    // 思考内容以独立的内容块返回，正文中不需要再去除<think>标签
+    async fn generate_response(&self, prompt: &str) -> Result<String> {
+        self.generate_raw_response(prompt).await
+    }
+
+    async fn generate_raw_response(&self, prompt: &str) -> Result<String> {
+        Ok(self.send(&[ChatMessage::user(prompt, &[])]).await?.text)
+    }
+
+    fn supports_vision(&self) -> bool {
+        self.vision
+    }
+
+    fn generation_params(&self) -> &GenerationParams {
+        &self.params
+    }
+
+    async fn generate_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        if images.is_empty() {
+            return self.generate_response(prompt).await;
+        }
+        if !self.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        self.generate_raw_response_with_images(prompt, images).await
+    }
+
+    async fn generate_raw_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        Ok(self.send(&[ChatMessage::user(prompt, images)]).await?.text)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        if !images.is_empty() && !self.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        self.generate_raw_stream(prompt, images, schema, on_delta)
+            .await
+    }
+
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        Ok(self
+            .send_stream(&[ChatMessage::user(prompt, images)], schema, &[], on_delta)
+            .await?
+            .text)
+    }
+
+    async fn generate_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        if messages.iter().any(|m| !m.images.is_empty()) && !self.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        self.generate_raw_chat_stream(messages, schema, on_delta)
+            .await
+    }
+
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let response = self.send_stream(messages, schema, &[], on_delta).await?;
+        Ok(response.into_reply())
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate_tool_chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatReply> {
+        let response = self
+            .send_stream(messages, None, tools, &mut |_| Ok(()))
+            .await?;
+        Ok(response.into_reply())
+    }
+}
+
+impl AnthropicResponse {
+    fn into_reply(self) -> ChatReply {
+        ChatReply {
+            truncated: self.stop_reason.as_deref() == Some("max_tokens"),
+            text: self.text,
+            tool_calls: self.tool_calls,
+            // 思考消耗的token计入output_tokens，不单独返回
+            usage: TokenUsage {
+                input_tokens: Some(self.input_tokens),
+                output_tokens: Some(self.output_tokens),
+                thinking_tokens: None,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// 转换对话消息：工具调用作为助手消息中的tool_use块，
+/// 工具结果作为用户消息中的tool_result块，连续的多个结果合并到同一条消息
+fn request_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    let mut result: Vec<Value> = Vec::new();
+    for message in messages {
+        match message.role {
+            ChatRole::User => result.push(json!({
+                "role": "user",
+                "content": user_content(&message.content, &message.images)
+            })),
+            ChatRole::Assistant if message.tool_calls.is_empty() => {
+                result.push(json!({ "role": "assistant", "content": message.content }))
+            }
+            ChatRole::Assistant => {
+                let mut content = Vec::new();
+                if !message.content.is_empty() {
+                    content.push(json!({ "type": "text", "text": message.content }));
+                }
+                content.extend(message.tool_calls.iter().map(|call| {
+                    json!({
+                        "type": "tool_use",
+                        "id": call.id,
+                        "name": call.name,
+                        "input": call.arguments
+                    })
+                }));
+                result.push(json!({ "role": "assistant", "content": content }));
+            }
+            ChatRole::Tool => {
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": message.tool_call.as_ref().map(|call| call.id.as_str()),
+                    "content": message.content
+                });
+                let follows_result = result
+                    .last()
+                    .and_then(|last| last["content"].as_array())
+                    .and_then(|content| content.first())
+                    .is_some_and(|first| first["type"] == "tool_result");
+                match result.last_mut() {
+                    Some(last) if follows_result => {
+                        last["content"].as_array_mut().unwrap().push(block)
+                    }
+                    _ => result.push(json!({ "role": "user", "content": [block] })),
+                }
+            }
+        }
+    }
+    result
+}
+
+/// 用户消息内容，有图片时为内容块数组
+fn user_content(prompt: &str, images: &[ImageInput]) -> Value {
+    if images.is_empty() {
+        return json!(prompt);
+    }
+    let mut content: Vec<Value> = images
+        .iter()
+        .map(|image| {
+            json!({
+                "type": "image",
+                "source": {
+                    "type": "base64",
+                    "media_type": image.media_type,
+                    "data": image.data,
+                }
+            })
+        })
+        .collect();
+    content.push(json!({ "type": "text", "text": prompt }));
+    Value::Array(content)
+}
+
+/// 兼容以/v1结尾或直接填写完整接口地址的配置
+fn messages_endpoint(base_url: &str) -> String {
+    let base_url = base_url.trim().trim_end_matches('/');
+    let base_url = if base_url.is_empty() {
+        DEFAULT_BASE_URL
+    } else {
+        base_url
+    };
+    if base_url.ends_with("/messages") {
+        base_url.to_string()
+    } else if base_url.ends_with("/v1") {
+        format!("{}/messages", base_url)
+    } else {
+        format!("{}/v1/messages", base_url)
+    }
+}
+
+/// 从错误响应中提取错误类型及说明，格式为{"type":"error","error":{"type":...,"message":...}}
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| {
+            let error = v.get("error")?;
+            Some(format!(
+                "{}: {}",
+                error.get("type")?.as_str()?,
+                error.get("message")?.as_str()?
+            ))
+        })
+        .unwrap_or_else(|| body.to_string())
+}
+
+fn handle_event(
+    event: &SseEvent,
+    response: &mut AnthropicResponse,
+    blocks: &mut HashMap<u64, ContentBlock>,
+    output_tool: Option<&str>,
+    on_delta: &mut StreamCallback<'_>,
+) -> Result<()> {
+    if event.data.is_empty() {
+        return Ok(());
+    }
+    let data: Value = serde_json::from_str(&event.data)
+        .map_err(|e| anyhow!("Anthropic响应格式错误: {}, 内容: {}", e, event.data))?;
+    let str_field = |value: &Value, key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    match data.get("type").and_then(Value::as_str).unwrap_or_default() {
+        "message_start" => {
+            if let Some(usage) = data.pointer("/message/usage") {
+                response.input_tokens = usage["input_tokens"].as_u64().unwrap_or_default();
+            }
+        }
+        "content_block_start" => {
+            let index = data["index"].as_u64().unwrap_or_default();
+            let block = &data["content_block"];
+            // 部分兼容实现会在开始事件中直接携带内容
+            let content_block = match block["type"].as_str().unwrap_or_default() {
+                "text" => {
+                    let text = str_field(block, "text");
+                    if !text.is_empty() {
+                        response.text.push_str(&text);
+                        on_delta(&text)?;
+                    }
+                    ContentBlock::Text
+                }
+                "thinking" => {
+                    response.thinking.push_str(&str_field(block, "thinking"));
+                    ContentBlock::Thinking
+                }
+                "tool_use" if block["name"].as_str() == output_tool => ContentBlock::OutputTool,
+                "tool_use" => ContentBlock::ToolUse {
+                    id: str_field(block, "id"),
+                    name: str_field(block, "name"),
+                    input: String::new(),
+                },
+                _ => ContentBlock::Other,
+            };
+            blocks.insert(index, content_block);
+        }
+        "content_block_delta" => {
+            let delta = &data["delta"];
+            match delta["type"].as_str().unwrap_or_default() {
+                "text_delta" => {
+                    let text = str_field(delta, "text");
+                    response.text.push_str(&text);
+                    on_delta(&text)?;
+                }
+                // 工具参数以JSON片段的形式流式返回，强制调用的工具参数即为回复正文
+                "input_json_delta" => {
+                    let index = data["index"].as_u64().unwrap_or_default();
+                    let json = str_field(delta, "partial_json");
+                    match blocks.get_mut(&index) {
+                        Some(ContentBlock::ToolUse { input, .. }) => input.push_str(&json),
+                        _ => {
+                            response.text.push_str(&json);
+                            on_delta(&json)?;
+                        }
+                    }
+                }
+                "thinking_delta" => response.thinking.push_str(&str_field(delta, "thinking")),
+                _ => {}
+            }
+        }
+        "content_block_stop" => {
+            let index = data["index"].as_u64().unwrap_or_default();
+            match blocks.remove(&index) {
+                Some(ContentBlock::Thinking) => response.thinking.push('\n'),
+                Some(ContentBlock::ToolUse { id, name, input }) => {
+                    response.tool_calls.push(ToolCall {
+                        id,
+                        name,
+                        arguments: ToolCall::parse_arguments(&input),
+                        thought_signature: None,
+                    });
+                }
+                _ => {}
+            }
+        }
+        "message_delta" => {
+            if let Some(reason) = data.pointer("/delta/stop_reason").and_then(Value::as_str) {
+                response.stop_reason = Some(reason.to_string());
+            }
+            if let Some(tokens) = data.pointer("/usage/output_tokens").and_then(Value::as_u64) {
+                response.output_tokens = tokens;
+            }
+        }
+        "error" => {
+            return Err(anyhow!(
+                "Anthropic接口返回错误: {}",
+                error_message(&event.data)
+            ));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    /// 在本地端口上应答一次请求，以SSE流返回预设的事件，返回接口地址及收到的请求
+    fn serve_sse(events: &[Value]) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let body: String = events
+            .iter()
+            .map(|event| {
+                format!(
+                    "event: {}\ndata: {}\n\n",
+                    event["type"].as_str().unwrap(),
+                    event
+                )
+            })
+            .collect();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // 读取请求头及按Content-Length读取请求体
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + length || n == 0 {
+                        break;
+                    }
+                }
+            }
+            sender
+                .send(String::from_utf8_lossy(&request).to_string())
+                .unwrap();
+            let header =
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n";
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(body.as_bytes()).unwrap();
+        });
+        (base_url, receiver)
+    }
+
+    fn agent(base_url: &str) -> AnthropicAgent {
+        AnthropicAgent::new(
+            base_url,
+            "test-key",
+            "claude-test",
+            "",
+            GenerationParams::default(),
+            None,
+            false,
+        )
+        .unwrap()
+    }
+
+    fn message_start() -> Value {
+        json!({"type": "message_start", "message": {"usage": {"input_tokens": 12, "output_tokens": 1}}})
+    }
+
+    fn message_delta(stop_reason: &str) -> Value {
+        json!({"type": "message_delta", "delta": {"stop_reason": stop_reason}, "usage": {"output_tokens": 34}})
+    }
+
+    #[tokio::test]
+    async fn stream_collects_text_thinking_and_tool_calls() {
+        let (base_url, request) = serve_sse(&[
+            message_start(),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "先看表结构"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Hello"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": ", world"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "{\"path\":"}}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "\"src/main.rs\"}"}}),
+            json!({"type": "content_block_stop", "index": 2}),
+            message_delta("max_tokens"),
+            json!({"type": "message_stop"}),
+        ]);
+        let mut deltas = Vec::new();
+        let response = agent(&base_url)
+            .send_stream(&[ChatMessage::user("hi", &[])], None, &[], &mut |delta| {
+                deltas.push(delta.to_string());
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let request = request.recv().unwrap();
+        assert!(request.starts_with("POST /v1/messages "));
+        assert!(request.contains("x-api-key: test-key"));
+        assert_eq!(deltas, vec!["Hello", ", world"]);
+        assert_eq!(response.thinking, "先看表结构\n");
+        assert_eq!(response.input_tokens, 12);
+        assert_eq!(response.output_tokens, 34);
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.tool_calls[0].id, "toolu_1");
+        assert_eq!(
+            response.tool_calls[0].arguments,
+            json!({"path": "src/main.rs"})
+        );
+
+        let reply = response.into_reply();
+        assert_eq!(reply.text, "Hello, world");
+        assert!(reply.truncated);
+        assert_eq!(
+            reply.usage.describe().as_deref(),
+            Some("输入12个token，输出34个token")
+        );
+    }
+
+    #[tokio::test]
+    async fn output_tool_arguments_become_reply_text() {
+        let schema = OutputSchema::file_modify_results();
+        let (base_url, _request) = serve_sse(&[
+            message_start(),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": "toolu_1", "name": schema.name, "input": {}}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"results\":"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "[]}"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            message_delta("tool_use"),
+        ]);
+        let agent = agent(&base_url).with_structured_output(StructuredOutput::Schema);
+        let response = agent
+            .send_stream(
+                &[ChatMessage::user("hi", &[])],
+                Some(&schema),
+                &[],
+                &mut |_| Ok(()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.text, "{\"results\":[]}");
+        assert!(response.tool_calls.is_empty());
+        assert!(!response.into_reply().truncated);
+    }
+
+    #[tokio::test]
+    async fn refusal_is_an_error() {
+        let (base_url, _request) = serve_sse(&[
+            message_start(),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "I can't"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            message_delta("refusal"),
+        ]);
+        let err = agent(&base_url)
+            .send(&[ChatMessage::user("hi", &[])])
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "模型拒绝回答该问题");
+    }
+
+    #[tokio::test]
+    async fn error_event_is_an_error() {
+        let (base_url, _request) = serve_sse(&[
+            message_start(),
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+        ]);
+        let err = agent(&base_url)
+            .send(&[ChatMessage::user("hi", &[])])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Anthropic接口返回错误: overloaded_error: Overloaded"
+        );
+    }
+}
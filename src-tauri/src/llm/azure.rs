@@ -0,0 +1,396 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    agent::{
+        AIAgent, AzureAuthType, ChatMessage, ChatReply, GenerationParams, LLMProvider,
+        StreamCallback, StructuredOutput, ToolSpec,
+    },
+    embedding::AIEmbedder,
+    http::{read_text, send_with_retry, HttpOptions},
+    image::ImageInput,
+    openai::{
+        chat_completion_params, chat_messages, read_chat_stream, response_format, tool_definitions,
+    },
+    structured::OutputSchema,
+};
+
+const PROVIDER_NAME: &str = "Azure OpenAI";
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+/// 访问Azure OpenAI所需的令牌范围
+const COGNITIVE_SERVICES_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+/// 令牌在过期前多久刷新
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(300);
+
+/// 按"租户/客户端ID"缓存的Entra访问令牌及其过期时间
+static TOKEN_CACHE: Lazy<Mutex<HashMap<String, (String, Instant)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+enum AzureAuth {
+    ApiKey(String),
+    EntraToken(String),
+    ClientSecret {
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+}
+
+impl AzureAuth {
+    fn from_provider(provider: &LLMProvider) -> Result<Self> {
+        let required = |value: &Option<String>, name: &str| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("使用Entra应用认证时需要配置{}", name))
+        };
+        match provider.azure_auth {
+            AzureAuthType::ApiKey => Ok(AzureAuth::ApiKey(provider.api_key.clone())),
+            AzureAuthType::EntraToken => Ok(AzureAuth::EntraToken(
+                provider
+                    .api_key
+                    .trim()
+                    .trim_start_matches("Bearer ")
+                    .to_string(),
+            )),
+            AzureAuthType::ClientSecret => Ok(AzureAuth::ClientSecret {
+                tenant_id: required(&provider.tenant_id, "租户ID")?,
+                client_id: required(&provider.client_id, "客户端ID")?,
+                client_secret: required(&provider.client_secret, "客户端密码")?,
+            }),
+        }
+    }
+
+    async fn authorize(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::RequestBuilder> {
+        Ok(match self {
+            AzureAuth::ApiKey(key) => request.header("api-key", key),
+            AzureAuth::EntraToken(token) => request.bearer_auth(token),
+            AzureAuth::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let token =
+                    client_credentials_token(client, tenant_id, client_id, client_secret).await?;
+                request.bearer_auth(token)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// 使用客户端密码获取Entra访问令牌，未过期的令牌直接复用
+async fn client_credentials_token(
+    client: &reqwest::Client,
+    tenant_id: &str,
+    client_id: &str,
+    client_secret: &str,
+) -> Result<String> {
+    let cache_key = format!("{}/{}", tenant_id, client_id);
+    if let Some((token, expires_at)) = TOKEN_CACHE.lock().unwrap().get(&cache_key) {
+        if Instant::now() + TOKEN_REFRESH_MARGIN < *expires_at {
+            return Ok(token.clone());
+        }
+    }
+    let response = client
+        .post(format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+            tenant_id
+        ))
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+            ("scope", COGNITIVE_SERVICES_SCOPE),
+        ])
+        .send()
+        .await
+        .map_err(|e| anyhow!("获取Entra访问令牌失败: {}", e))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|v| v["error_description"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        return Err(anyhow!(
+            "获取Entra访问令牌失败({}): {}",
+            status.as_u16(),
+            message
+        ));
+    }
+    let token: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| anyhow!("Entra令牌响应格式错误: {}", e))?;
+    TOKEN_CACHE.lock().unwrap().insert(
+        cache_key,
+        (
+            token.access_token.clone(),
+            Instant::now() + Duration::from_secs(token.expires_in),
+        ),
+    );
+    Ok(token.access_token)
+}
+
+/// Azure OpenAI中的一个部署，接口地址为{baseUrl}/openai/deployments/{deployment}/...?api-version=
+struct AzureDeployment {
+    client: reqwest::Client,
+    base_url: String,
+    deployment: String,
+    api_version: String,
+    auth: AzureAuth,
+    http: HttpOptions,
+}
+
+impl AzureDeployment {
+    fn new(provider: &LLMProvider) -> Result<Self> {
+        let deployment = provider
+            .deployment
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .unwrap_or(provider.model.trim());
+        if deployment.is_empty() {
+            return Err(anyhow!("未配置Azure OpenAI的部署名称"));
+        }
+        // 兼容填写到/openai为止的地址
+        let base_url = provider.base_url.trim().trim_end_matches('/');
+        let base_url = base_url.strip_suffix("/openai").unwrap_or(base_url);
+        Ok(Self {
+            client: provider.http.client(),
+            base_url: base_url.to_string(),
+            deployment: deployment.to_string(),
+            api_version: provider
+                .api_version
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .unwrap_or(DEFAULT_API_VERSION)
+                .to_string(),
+            auth: AzureAuth::from_provider(provider)?,
+            http: provider.http.clone(),
+        })
+    }
+
+    async fn send(&self, operation: &str, body: &Value) -> Result<reqwest::Response> {
+        let url = format!(
+            "{}/openai/deployments/{}/{}?api-version={}",
+            self.base_url,
+            urlencoding::encode(&self.deployment),
+            operation,
+            urlencoding::encode(&self.api_version)
+        );
+        let request = self
+            .auth
+            .authorize(&self.client, self.client.post(&url).json(body))
+            .await?;
+        send_with_retry(PROVIDER_NAME, &self.http, request, error_message).await
+    }
+
+    async fn post(&self, operation: &str, body: &Value) -> Result<Value> {
+        let response = self.send(operation, body).await?;
+        let text = read_text(PROVIDER_NAME, response, self.http.read_timeout()).await?;
+        serde_json::from_str(&text).map_err(|e| anyhow!("Azure OpenAI响应格式错误: {}", e))
+    }
+}
+
+/// 错误格式为{"error":{"code":...,"message":...}}
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| {
+            let error = v.get("error")?;
+            Some(format!(
+                "{}: {}",
+                error["code"].as_str().unwrap_or_default(),
+                error["message"].as_str()?
+            ))
+        })
+        .unwrap_or_else(|| body.to_string())
+}
+
+pub struct AzureOpenAIAgent {
+    deployment: AzureDeployment,
+    preamble: String,
+    model: String,
+    params: GenerationParams,
+    vision: bool,
+    structured_output: StructuredOutput,
+}
+
+impl AzureOpenAIAgent {
+    pub fn new(provider: &LLMProvider, params: GenerationParams, preamble: &str) -> Result<Self> {
+        Ok(Self {
+            deployment: AzureDeployment::new(provider)?,
+            preamble: preamble.to_string(),
+            model: provider.model.clone(),
+            params,
+            vision: provider.vision_enabled(),
+            structured_output: provider.structured_output,
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        tools: &[ToolSpec],
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let mut body = chat_completion_params(&self.model, &self.params);
+        body["messages"] = chat_messages(&self.preamble, messages);
+        // 2024-08-01-preview及之后的API版本才支持json_schema
+        if let Some(format) = response_format(schema, self.structured_output) {
+            body["response_format"] = format;
+        }
+        if !tools.is_empty() {
+            body["tools"] = tool_definitions(tools);
+        }
+        body["stream"] = json!(true);
+        body["stream_options"] = json!({ "include_usage": true });
+        let response = self.deployment.send("chat/completions", &body).await?;
+        let result = read_chat_stream(
+            PROVIDER_NAME,
+            response,
+            self.deployment.http.read_timeout(),
+            on_delta,
+        )
+        .await?;
+        result.log_usage(PROVIDER_NAME, &self.deployment.deployment);
+        Ok(result.into_reply())
+    }
+}
+
+#[async_trait]
+impl AIAgent for AzureOpenAIAgent {
+    async fn generate_raw_response(&self, prompt: &str) -> Result<String> {
+        self.chat_stream(
+            &[ChatMessage::user(prompt, &[])],
+            None,
+            &[],
+            &mut |_| Ok(()),
+        )
+        .await
+        .map(|reply| reply.text)
+    }
+
+    fn supports_vision(&self) -> bool {
+        self.vision
+    }
+
+    fn generation_params(&self) -> &GenerationParams {
+        &self.params
+    }
+
+    async fn generate_raw_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        self.chat_stream(&[ChatMessage::user(prompt, images)], None, &[], &mut |_| {
+            Ok(())
+        })
+        .await
+        .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        self.chat_stream(&[ChatMessage::user(prompt, images)], schema, &[], on_delta)
+            .await
+            .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, schema, &[], on_delta).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate_tool_chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, None, tools, &mut |_| Ok(()))
+            .await
+    }
+}
+
+pub struct AzureEmbedder {
+    deployment: AzureDeployment,
+    model_id: String,
+}
+
+impl AzureEmbedder {
+    pub fn new(provider: &LLMProvider, model_id: String) -> Result<Self> {
+        Ok(Self {
+            deployment: AzureDeployment::new(provider)?,
+            model_id,
+        })
+    }
+}
+
+#[async_trait]
+impl AIEmbedder for AzureEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let response = self
+            .deployment
+            .post("embeddings", &json!({ "input": texts }))
+            .await?;
+        let mut data: Vec<&Value> = response["data"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Azure OpenAI向量响应格式错误"))?
+            .iter()
+            .collect();
+        data.sort_by_key(|d| d["index"].as_u64().unwrap_or_default());
+        data.into_iter()
+            .map(|d| {
+                d["embedding"]
+                    .as_array()
+                    .map(|values| {
+                        values
+                            .iter()
+                            .map(|v| v.as_f64().unwrap_or_default() as f32)
+                            .collect()
+                    })
+                    .ok_or_else(|| anyhow!("Azure OpenAI向量响应格式错误"))
+            })
+            .collect()
+    }
+}
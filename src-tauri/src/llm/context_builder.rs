@@ -1,13 +1,28 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Result};
+use log::warn;
 use serde::Deserialize;
 
-use walkdir::WalkDir;
-
 use crate::{
+    code_index::{self, DEFAULT_TOP_K},
     db::get_table_schema,
-    storage::{code_sample::get_sample_by_id, datasource::get_ds_by_id},
+    function::{
+        dir_tree::{load_dir_tree_options, render_directory_tree, DirTreeFocus},
+        document::read_document,
+        file::load_ignore_patterns,
+        git_diff::read_git_diff,
+        outline::{extract_outline, extract_symbols, is_supported},
+    },
+    llm::{
+        agent::GenerationParams,
+        image::{load_image_options, prepare_image, ImageInput},
+        PlannedFile,
+    },
+    storage::{code_sample::get_sample_by_id, datasource::get_ds_by_id, sys_config::get_config},
 };
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +35,38 @@ pub struct CodeGenRequest {
     pub auto_detect_dir: bool,
     #[serde(rename = "currentSrcDir")]
     pub current_src_dir: String,
+    /// 是否基于代码索引自动检索与问题相关的代码片段
+    #[serde(rename = "autoRetrieve", default)]
+    pub auto_retrieve: bool,
+    #[serde(rename = "retrieveTopK", default)]
+    pub retrieve_top_k: Option<usize>,
+    /// 本次任务使用的LLM配置名称，未指定时按步骤使用默认配置
+    #[serde(rename = "llmProfile", default)]
+    pub llm_profile: Option<String>,
+    /// 本次代码生成使用的生成参数，已配置的参数覆盖LLM配置中的值
+    #[serde(rename = "generationParams", default)]
+    pub generation_params: GenerationParams,
+    /// 生成代码前是否允许模型调用工具按需获取上下文
+    #[serde(rename = "toolCalling", default)]
+    pub tool_calling: bool,
+    /// 工具调用的最大轮数，未指定时使用默认值
+    #[serde(rename = "maxToolSteps", default)]
+    pub max_tool_steps: Option<usize>,
+    /// 所属的对话会话，指定时保存本轮对话
+    #[serde(rename = "sessionId", default)]
+    pub session_id: Option<String>,
+    /// 是否在所属会话之前的对话及生成结果的基础上继续，否则作为独立的问题处理
+    #[serde(rename = "continueSession", default)]
+    pub continue_session: bool,
+    /// 只生成计划，不生成代码，计划作为任务结果返回
+    #[serde(rename = "planOnly", default)]
+    pub plan_only: bool,
+    /// 已确认的生成计划，指定时按计划逐个文件生成
+    #[serde(default)]
+    pub plan: Option<Vec<PlannedFile>>,
+    /// 按计划生成时同时进行的LLM调用数量上限，未指定时使用默认值
+    #[serde(rename = "maxConcurrency", default)]
+    pub max_concurrency: Option<usize>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,6 +75,27 @@ pub struct ResourceMeta {
     pub resource_type: String,
     pub name: String,
     pub data: String,
+    /// 文件资源的引用方式，仅对file类型的资源有效
+    #[serde(rename = "includeMode", default)]
+    pub include_mode: FileIncludeMode,
+    /// includeMode为symbols时需要引用的符号，支持"类名.方法名"形式
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// 需求文档的引用范围：PDF为页码范围，如"1-3,5"；DOCX及Markdown为章节标题，多个以逗号分隔。为空时引用全文
+    #[serde(default)]
+    pub selection: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileIncludeMode {
+    /// 引用完整的文件内容
+    #[default]
+    Full,
+    /// 只引用文件大纲：类型声明、字段及方法签名
+    Outline,
+    /// 只引用指定的类型或方法
+    Symbols,
 }
 
 struct ResourceProcessor {}
@@ -49,6 +117,8 @@ impl ResourceProcessor {
         match resource.resource_type.as_str() {
             "table" => self.process_table(resource).await,
             "file" => self.process_file(resource).await,
+            "git_diff" => self.process_git_diff(resource).await,
+            "document" => self.process_document(resource).await,
             _ => Err(anyhow!("Unsupported resource type")),
         }
     }
@@ -61,10 +131,61 @@ impl ResourceProcessor {
     }
 
     async fn process_file(&self, resource: &ResourceMeta) -> Result<String> {
-        let content = fs::read_to_string(&resource.name)?;
+        let path = Path::new(&resource.data);
+        let content = fs::read_to_string(path)?;
+        let mode = match resource.include_mode {
+            FileIncludeMode::Full => FileIncludeMode::Full,
+            _ if !is_supported(path) => {
+                warn!(
+                    "暂不支持提取该类型文件的大纲或符号，将引用完整文件: {}",
+                    resource.data
+                );
+                FileIncludeMode::Full
+            }
+            FileIncludeMode::Symbols if resource.symbols.is_empty() => FileIncludeMode::Outline,
+            mode => mode,
+        };
+        let section = match mode {
+            FileIncludeMode::Full => {
+                format!(
+                    "##引用代码文件内容：{}\n```\n{}\n```",
+                    resource.data, content
+                )
+            }
+            FileIncludeMode::Outline => format!(
+                "##引用代码文件大纲（已省略方法实现）：{}\n```\n{}\n```",
+                resource.data,
+                extract_outline(path, &content)?
+            ),
+            FileIncludeMode::Symbols => format!(
+                "##引用代码文件中的指定符号（{}）：{}\n```\n{}\n```",
+                resource.symbols.join(", "),
+                resource.data,
+                extract_symbols(path, &content, &resource.symbols)?
+            ),
+        };
+        Ok(section)
+    }
+
+    //data为要引用的变更：空表示未提交的变更，否则为提交或提交区间
+    async fn process_git_diff(&self, resource: &ResourceMeta) -> Result<String> {
+        let root = get_config("root_source_path".to_string())
+            .await?
+            .ok_or_else(|| anyhow!("当前未打开项目目录"))?;
+        let diff = read_git_diff(Path::new(&root), &resource.data)?;
+        Ok(diff.to_context())
+    }
+
+    async fn process_document(&self, resource: &ResourceMeta) -> Result<String> {
+        let content = read_document(Path::new(&resource.data), &resource.selection)?;
+        let scope = if resource.selection.trim().is_empty() {
+            String::new()
+        } else {
+            format!("（{}）", resource.selection.trim())
+        };
         Ok(format!(
-            "##引用文件： {}\n```\n{}\n```",
-            resource.name, content
+            "##引用需求文档{}：{}\n{}\n\n",
+            scope, resource.data, content
         ))
     }
 }
@@ -85,7 +206,17 @@ impl LLMContextBuilder {
             }
         }
         //资源内容
+        let mut image_count = 0;
         for resource in &request.resources {
+            // 图片随问题单独提交，上下文中只说明其对应关系
+            if resource.resource_type == "image" {
+                image_count += 1;
+                context.push_str(&format!(
+                    "##引用图片：{}（见随问题附带的第{}张图片）\n",
+                    resource.data, image_count
+                ));
+                continue;
+            }
             let content = self.processor.process(resource).await?;
             if resource.resource_type == "table" {
                 context.push_str(&format!(
@@ -93,16 +224,22 @@ impl LLMContextBuilder {
                     resource.name, content
                 ));
             }
-            if resource.resource_type == "file" {
-                context.push_str(&format!(
-                    "##引用代码文件内容：{}\n```\n{}\n```",
-                    resource.data, content
-                ));
+            if matches!(
+                resource.resource_type.as_str(),
+                "file" | "git_diff" | "document"
+            ) {
+                context.push_str(&content);
+            }
+        }
+        if request.auto_retrieve {
+            // 检索失败（如项目尚未建立索引）不影响生成，仅缺少自动检索的代码
+            match retrieve_related_code(request).await {
+                Ok(related) => context.push_str(&related),
+                Err(e) => warn!("检索相关代码失败，将不附加检索结果: {}", e),
             }
         }
         if request.auto_detect_dir {
-            let dir_structure =
-                generate_directory_structure(PathBuf::from(&request.current_src_dir));
+            let dir_structure = generate_directory_structure(request).await?;
             if !dir_structure.is_empty() {
                 context.push_str("#当前源码目录结构：");
                 context.push_str(&dir_structure);
@@ -110,23 +247,60 @@ impl LLMContextBuilder {
         }
         Ok(context)
     }
+
+    /// 读取图片资源，按配置缩小尺寸后编码，顺序与上下文中的说明一致
+    pub async fn load_images(&self, request: &CodeGenRequest) -> Result<Vec<ImageInput>> {
+        let images: Vec<&ResourceMeta> = request
+            .resources
+            .iter()
+            .filter(|r| r.resource_type == "image")
+            .collect();
+        if images.is_empty() {
+            return Ok(Vec::new());
+        }
+        let options = load_image_options().await?;
+        images
+            .into_iter()
+            .map(|r| prepare_image(Path::new(&r.data), &options))
+            .collect()
+    }
 }
 
-//基于指定的根目录，生成根目录的整个目录结构树状图文本，用于附加到LLM的上下文中。
-fn generate_directory_structure(root_dir: PathBuf) -> String {
-    let mut dir_structure = String::new();
-    dir_structure.push_str("```\n");
-    for entry in WalkDir::new(&root_dir).into_iter().filter_map(Result::ok) {
-        let path = entry.path();
-        if path.is_dir() && path != root_dir {
-            // 排除根目录本身
-            let relative_path = path.strip_prefix(&root_dir).unwrap();
-            dir_structure.push_str(&format!(
-                "/{}\n",
-                relative_path.to_string_lossy().replace('\\', "/") // 统一使用/作为路径分隔符
-            ));
+//从代码索引中检索与问题相关的代码片段，已完整引用的文件不再重复附加
+async fn retrieve_related_code(request: &CodeGenRequest) -> Result<String> {
+    let top_k = request.retrieve_top_k.unwrap_or(DEFAULT_TOP_K);
+    let hits = code_index::search(&request.current_src_dir, &request.question, top_k).await?;
+    let mut context = String::new();
+    for hit in hits {
+        let referenced = request.resources.iter().any(|r| {
+            r.resource_type == "file"
+                && r.include_mode == FileIncludeMode::Full
+                && r.data == hit.file_path
+        });
+        if referenced {
+            continue;
+        }
+        context.push_str(&format!(
+            "##自动检索的相关代码：{}（第{}-{}行）\n```\n{}\n```\n",
+            hit.file_path, hit.start_line, hit.end_line, hit.content
+        ));
+    }
+    Ok(context)
+}
+
+//基于当前源码目录生成目录结构树状图文本，用于附加到LLM的上下文中。
+//遵循.gitignore及用户配置的排除规则，并优先为与问题最相关的目录列出文件名。
+async fn generate_directory_structure(request: &CodeGenRequest) -> Result<String> {
+    let root_dir = PathBuf::from(&request.current_src_dir);
+    let excludes = load_ignore_patterns().await?;
+    let options = load_dir_tree_options().await?;
+    let mut focus = DirTreeFocus::default();
+    focus.add_keywords_from(&request.question);
+    for resource in &request.resources {
+        match resource.resource_type.as_str() {
+            "file" => focus.paths.push(PathBuf::from(&resource.data)),
+            _ => focus.add_keywords_from(&resource.name),
         }
     }
-    dir_structure.push_str("```\n");
-    dir_structure
+    render_directory_tree(&root_dir, &excludes, &options, &focus)
 }
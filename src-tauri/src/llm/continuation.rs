@@ -0,0 +1,184 @@
+use super::{
+    agent::OutputProtocol,
+    file_block::{open_block, OpenBlock},
+    structured::is_unterminated_json,
+};
+
+/// 跨行的重复内容至少为该长度（字节数）才认为是模型重复输出的内容
+const MIN_OVERLAP_BYTES: usize = 16;
+/// 查找重复内容时检查的最大长度（字节数）
+const MAX_OVERLAP_BYTES: usize = 4096;
+
+/// 根据内容判断回复是否在输出中间被截断，用于没有返回结束原因的情况
+pub fn is_unterminated(response: &str, protocol: OutputProtocol) -> bool {
+    match protocol {
+        OutputProtocol::Json => is_unterminated_json(response),
+        OutputProtocol::FileBlock => open_block(response).is_some(),
+    }
+}
+
+/// 将续写内容拼接到被截断的回复之后。模型有时会在续写内容前重新输出代码块标记，
+/// 或者重复截断处的内容，拼接前去除
+pub fn stitch(previous: &str, continuation: &str, protocol: OutputProtocol) -> String {
+    let continuation = match fence_line(continuation) {
+        Some((fence, rest)) if is_spurious_fence(previous, fence, protocol) => rest,
+        _ => continuation,
+    };
+    let overlap = overlap_len(previous, continuation);
+    format!("{}{}", previous, &continuation[overlap..])
+}
+
+/// 开头为代码块起始标记（可以带语言名称）时，返回标记及其后的内容
+fn fence_line(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let marker = text.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let (line, rest) = text.split_once('\n')?;
+    let line = line.trim_end();
+    let fence = line.trim_end_matches(|c: char| c.is_alphanumeric() || "+-_.#".contains(c));
+    let valid = fence.len() >= 3 && fence.chars().all(|c| c == marker);
+    valid.then_some((fence, rest))
+}
+
+/// JSON中的换行都经过转义，截断在JSON中间时续写内容开头的代码块标记必然是多余的；
+/// 文件内容中的代码块标记比文件块的标记短，与文件块标记等长或更长的只可能是模型重新开始了文件块
+fn is_spurious_fence(previous: &str, fence: &str, protocol: OutputProtocol) -> bool {
+    match protocol {
+        OutputProtocol::Json => is_unterminated_json(previous),
+        OutputProtocol::FileBlock => match open_block(previous) {
+            Some(OpenBlock::Content(open)) => {
+                fence.starts_with(&open[..1]) && fence.len() >= open.len()
+            }
+            _ => false,
+        },
+    }
+}
+
+/// 续写内容开头与已有回复结尾重复的长度。只有模型从某一行的开头重新输出时才认为是重复：
+/// 重复内容恰好为被截断的最后一行，或者从更早的行首开始、跨越换行且足够长。
+/// 从行中间开始的重复（如截断在分隔线或缩进中间）可能是正常的续写，不去除
+fn overlap_len(previous: &str, continuation: &str) -> usize {
+    let last_line = previous.rsplit('\n').next().unwrap_or_default();
+    let max = previous
+        .len()
+        .min(continuation.len())
+        .min(MAX_OVERLAP_BYTES);
+    (1..=max)
+        .rev()
+        .filter(|len| continuation.is_char_boundary(*len))
+        .find(|len| {
+            let start = previous.len() - len;
+            let at_line_start = start == 0 || previous.as_bytes()[start - 1] == b'\n';
+            at_line_start
+                && previous.ends_with(&continuation[..*len])
+                && ((*len == last_line.len() && last_line.trim().len() >= 3)
+                    || (*len > last_line.len() && *len >= MIN_OVERLAP_BYTES))
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_restarted_last_line() {
+        let previous = "fn main() {\n    println!(\"hel";
+        let continuation = "    println!(\"hello\");\n}\n";
+        assert_eq!(overlap_len(previous, continuation), 17);
+        assert_eq!(
+            stitch(previous, continuation, OutputProtocol::FileBlock),
+            "fn main() {\n    println!(\"hello\");\n}\n"
+        );
+    }
+
+    #[test]
+    fn removes_restarted_lines_across_line_boundary() {
+        let previous = "let a = compute_first_value();\nlet b = comp";
+        let continuation = "let a = compute_first_value();\nlet b = compute();\n";
+        assert_eq!(
+            stitch(previous, continuation, OutputProtocol::FileBlock),
+            "let a = compute_first_value();\nlet b = compute();\n"
+        );
+        // 跨行但太短的重复不去除
+        assert_eq!(overlap_len("}\n}\n", "}\n}\nfn a() {}\n"), 0);
+    }
+
+    #[test]
+    fn keeps_repeating_content_cut_mid_line() {
+        // 截断在分隔线中间，续写内容是分隔线的后半部分
+        let previous = "//==========";
+        let continuation = "==========\nfn main() {}\n";
+        assert_eq!(overlap_len(previous, continuation), 0);
+        let stitched = stitch(previous, continuation, OutputProtocol::FileBlock);
+        assert_eq!(stitched, "//====================\nfn main() {}\n");
+
+        // 截断在缩进中间
+        let previous = "fn main() {\n        ";
+        let continuation = "        let a = 1;\n";
+        assert_eq!(overlap_len(previous, continuation), 0);
+        assert_eq!(
+            stitch(previous, continuation, OutputProtocol::FileBlock),
+            "fn main() {\n                let a = 1;\n"
+        );
+
+        // 重复的长内容从行中间开始
+        let previous = "a = \"xxxxxxxxxxxxxxxxxxxxxxxx";
+        let continuation = "xxxxxxxxxxxxxxxxxxxxxxxx\"\n";
+        assert_eq!(overlap_len(previous, continuation), 0);
+    }
+
+    #[test]
+    fn keeps_short_last_line() {
+        assert_eq!(overlap_len("fn a() {\n}", "}\n"), 0);
+    }
+
+    #[test]
+    fn drops_spurious_fence() {
+        let previous = "### FILE: a.rs\n```rust\nfn a() {\n";
+        let stitched = stitch(previous, "```rust\n}\n```\n", OutputProtocol::FileBlock);
+        assert_eq!(stitched, "### FILE: a.rs\n```rust\nfn a() {\n}\n```\n");
+
+        let previous = "{\"files\": [{\"filePath\": \"a.rs\", \"fileContent\": \"fn a";
+        let stitched = stitch(previous, "```json\n() {}\"}]}", OutputProtocol::Json);
+        assert_eq!(
+            stitched,
+            "{\"files\": [{\"filePath\": \"a.rs\", \"fileContent\": \"fn a() {}\"}]}"
+        );
+    }
+
+    #[test]
+    fn detects_truncated_json_with_braces_in_file_content() {
+        let previous = "{\"files\":[{\"filePath\":\"a.js\",\"fileContent\":\"const a = {};\\nconst b = [1, 2];\\nfunction f() {";
+        assert!(is_unterminated(previous, OutputProtocol::Json));
+        let stitched = stitch(previous, "```json\n}\"}]}", OutputProtocol::Json);
+        assert_eq!(stitched, format!("{}}}\"}}]}}", previous));
+    }
+
+    #[test]
+    fn keeps_shorter_fence_inside_file_content() {
+        let previous = "### FILE: README.md\n````markdown\n# Demo\n";
+        let continuation = "```bash\nnpm run dev\n```\n````\n";
+        assert_eq!(
+            stitch(previous, continuation, OutputProtocol::FileBlock),
+            format!("{}{}", previous, continuation)
+        );
+    }
+
+    #[test]
+    fn detects_unterminated_output() {
+        assert!(is_unterminated("{\"files\": [", OutputProtocol::Json));
+        assert!(!is_unterminated("{\"files\": []}", OutputProtocol::Json));
+        assert!(is_unterminated(
+            "### FILE: a.rs\n```\nfn a() {",
+            OutputProtocol::FileBlock
+        ));
+        assert!(is_unterminated(
+            "### FILE: a.rs\n",
+            OutputProtocol::FileBlock
+        ));
+        assert!(!is_unterminated(
+            "### FILE: a.rs\n```\nfn a() {}\n```\n",
+            OutputProtocol::FileBlock
+        ));
+    }
+}
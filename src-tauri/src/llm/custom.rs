@@ -0,0 +1,682 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    agent::{
+        flatten_messages, AIAgent, ChatMessage, ChatReply, GenerationParams, LLMProvider,
+        StreamCallback, TokenUsage,
+    },
+    http::{next_chunk, read_text, send_with_retry, HttpOptions, LLMError, LLMErrorKind},
+    image::ImageInput,
+    sse::{SseDecoder, SseEvent},
+    structured::OutputSchema,
+};
+
+const PROVIDER_NAME: &str = "自定义接口";
+
+/// 错误响应写入错误信息时保留的最大长度
+const MAX_ERROR_BODY_CHARS: usize = 500;
+
+/// 自定义HTTP接口的请求及响应映射。headers及body中可以使用以下占位符（url中只支持前三个）：
+/// {{base_url}}、{{api_key}}、{{model}}、{{system}}、{{prompt}}，以及生成参数
+/// {{max_tokens}}、{{temperature}}、{{top_p}}、{{seed}}、{{stop}}。
+/// 响应中的回复内容、token用量及错误信息通过JSONPath提取，如$.choices[0].message.content。
+/// 配置streamDeltaPath时按SSE流式读取响应（请求体模板需自行开启接口的流式输出），
+/// 每个事件的data按该路径提取回复片段，token用量及错误信息同样从各事件中提取
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomHttpOptions {
+    pub url: String,
+    pub method: String,
+    pub headers: BTreeMap<String, String>,
+    /// JSON格式的请求体模板，占位符只能出现在字符串中；
+    /// 值恰好为某个生成参数占位符（如"{{temperature}}"）的字符串会替换为对应的数字或数组，未配置时替换为null
+    #[serde(rename = "bodyTemplate")]
+    pub body_template: String,
+    #[serde(rename = "textPath")]
+    pub text_path: String,
+    #[serde(rename = "inputTokensPath")]
+    pub input_tokens_path: Option<String>,
+    #[serde(rename = "outputTokensPath")]
+    pub output_tokens_path: Option<String>,
+    /// 部分网关出错时仍返回200，此时通过该路径判断是否出错
+    #[serde(rename = "errorPath")]
+    pub error_path: Option<String>,
+    /// 流式响应中每个事件的回复片段路径，如$.choices[0].delta.content；未配置时不使用流式输出
+    #[serde(rename = "streamDeltaPath")]
+    pub stream_delta_path: Option<String>,
+}
+
+impl Default for CustomHttpOptions {
+    fn default() -> Self {
+        Self {
+            url: "{{base_url}}".to_string(),
+            method: "POST".to_string(),
+            headers: BTreeMap::from([
+                ("Content-Type".to_string(), "application/json".to_string()),
+                (
+                    "Authorization".to_string(),
+                    "Bearer {{api_key}}".to_string(),
+                ),
+            ]),
+            body_template: r#"{"model":"{{model}}","messages":[{"role":"system","content":"{{system}}"},{"role":"user","content":"{{prompt}}"}],"max_tokens":"{{max_tokens}}"}"#.to_string(),
+            text_path: "$.choices[0].message.content".to_string(),
+            input_tokens_path: Some("$.usage.prompt_tokens".to_string()),
+            output_tokens_path: Some("$.usage.completion_tokens".to_string()),
+            error_path: None,
+            stream_delta_path: None,
+        }
+    }
+}
+
+pub struct CustomHttpAgent {
+    client: reqwest::Client,
+    options: CustomHttpOptions,
+    method: reqwest::Method,
+    body_template: Value,
+    text_path: JsonPath,
+    input_tokens_path: Option<JsonPath>,
+    output_tokens_path: Option<JsonPath>,
+    error_path: Option<JsonPath>,
+    stream_delta_path: Option<JsonPath>,
+    base_url: String,
+    api_key: String,
+    model: String,
+    preamble: String,
+    params: GenerationParams,
+    http: HttpOptions,
+}
+
+impl CustomHttpAgent {
+    /// 创建时即校验模板及JSONPath，配置错误不必等到提交问题时才发现
+    pub fn new(provider: &LLMProvider, params: GenerationParams, preamble: &str) -> Result<Self> {
+        let options = provider.custom_http.clone().unwrap_or_default();
+        let method = reqwest::Method::from_bytes(options.method.trim().to_uppercase().as_bytes())
+            .map_err(|_| anyhow!("自定义接口的请求方法无效: {}", options.method))?;
+        let body_template = serde_json::from_str(&options.body_template)
+            .map_err(|e| anyhow!("自定义接口的请求体模板不是有效的JSON: {}", e))?;
+        let optional_path = |path: &Option<String>| {
+            path.as_deref()
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(JsonPath::parse)
+                .transpose()
+        };
+        Ok(Self {
+            client: provider.http.client(),
+            http: provider.http.clone(),
+            method,
+            body_template,
+            text_path: JsonPath::parse(&options.text_path)?,
+            input_tokens_path: optional_path(&options.input_tokens_path)?,
+            output_tokens_path: optional_path(&options.output_tokens_path)?,
+            error_path: optional_path(&options.error_path)?,
+            stream_delta_path: optional_path(&options.stream_delta_path)?,
+            options,
+            base_url: provider.base_url.trim().trim_end_matches('/').to_string(),
+            api_key: provider.api_key.clone(),
+            model: provider.model.clone(),
+            preamble: preamble.to_string(),
+            params,
+        })
+    }
+
+    /// 生成参数占位符对应的JSON值，未配置时为null
+    fn param_values(&self) -> [(&'static str, Value); 5] {
+        let stop = self.params.stop_sequences();
+        [
+            ("{{max_tokens}}", self.params.max_tokens.into()),
+            ("{{temperature}}", self.params.temperature.into()),
+            ("{{top_p}}", self.params.top_p.into()),
+            ("{{seed}}", self.params.seed.into()),
+            (
+                "{{stop}}",
+                if stop.is_empty() {
+                    Value::Null
+                } else {
+                    stop.into()
+                },
+            ),
+        ]
+    }
+
+    fn render(&self, template: &str, prompt: &str) -> String {
+        let mut text = template
+            .replace("{{base_url}}", &self.base_url)
+            .replace("{{api_key}}", &self.api_key)
+            .replace("{{model}}", &self.model);
+        // 出现在字符串中间的生成参数以文本形式替换，未配置时替换为空
+        for (placeholder, value) in self.param_values() {
+            let value = match value {
+                Value::Null => String::new(),
+                Value::Array(items) => items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                other => other.to_string(),
+            };
+            text = text.replace(placeholder, &value);
+        }
+        text.replace("{{system}}", &self.preamble)
+            .replace("{{prompt}}", prompt)
+    }
+
+    /// 优先使用errorPath提取错误信息，未配置或提取不到时截取响应体
+    fn error_message(&self, text: &str) -> Option<String> {
+        let data: Value = serde_json::from_str(text).ok()?;
+        self.error_path
+            .as_ref()?
+            .select_text(&data)
+            .filter(|e| !e.is_empty())
+    }
+
+    fn render_body(&self, value: &Value, prompt: &str) -> Value {
+        match value {
+            Value::String(s) if s.starts_with("{{") && s.ends_with("}}") => self
+                .param_values()
+                .into_iter()
+                .find(|(placeholder, _)| placeholder == s)
+                .map(|(_, value)| value)
+                .unwrap_or_else(|| Value::String(self.render(s, prompt))),
+            Value::String(s) => Value::String(self.render(s, prompt)),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.render_body(item, prompt))
+                    .collect(),
+            ),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.render_body(v, prompt)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// 按模板组装请求并提交，配置streamDeltaPath时逐段回调，否则收到完整回复后回调一次
+    async fn send(&self, prompt: &str, on_delta: &mut StreamCallback<'_>) -> Result<ChatReply> {
+        // URL中只支持base_url、model及api_key占位符，后两者会进行URL编码
+        let url = self
+            .options
+            .url
+            .replace("{{base_url}}", &self.base_url)
+            .replace("{{model}}", &urlencoding::encode(&self.model))
+            .replace("{{api_key}}", &urlencoding::encode(&self.api_key));
+        let mut request = self.client.request(self.method.clone(), &url);
+        for (name, value) in &self.options.headers {
+            request = request.header(name, self.render(value, ""));
+        }
+        if self.method != reqwest::Method::GET {
+            request = request.json(&self.render_body(&self.body_template, prompt));
+        }
+        let response = send_with_retry(PROVIDER_NAME, &self.http, request, |text| {
+            self.error_message(text)
+                .unwrap_or_else(|| text.chars().take(MAX_ERROR_BODY_CHARS).collect())
+        })
+        .await?;
+        match &self.stream_delta_path {
+            Some(delta_path) => self.read_stream(response, delta_path, on_delta).await,
+            None => {
+                let reply = self.read_response(response).await?;
+                on_delta(&reply.text)?;
+                Ok(reply)
+            }
+        }
+    }
+
+    async fn read_response(&self, response: reqwest::Response) -> Result<ChatReply> {
+        let status = response.status().as_u16();
+        let text = read_text(PROVIDER_NAME, response, self.http.read_timeout()).await?;
+        // 出错时仍返回200的网关无法区分错误类型，不再重试
+        if let Some(message) = self.error_message(&text) {
+            return Err(LLMError {
+                status: Some(status),
+                ..LLMError::new(PROVIDER_NAME, LLMErrorKind::Other, message)
+            }
+            .into());
+        }
+        let data: Value = serde_json::from_str(&text).map_err(|_| {
+            anyhow!(
+                "自定义接口的响应不是有效的JSON: {}",
+                text.chars().take(MAX_ERROR_BODY_CHARS).collect::<String>()
+            )
+        })?;
+
+        let tokens = |path: &Option<JsonPath>| {
+            path.as_ref()
+                .and_then(|p| p.select(&data).first().and_then(|v| v.as_u64()))
+        };
+        let usage = TokenUsage {
+            input_tokens: tokens(&self.input_tokens_path),
+            output_tokens: tokens(&self.output_tokens_path),
+            thinking_tokens: None,
+        };
+        self.log_usage(&usage);
+        let text = self.text_path.select_text(&data).ok_or_else(|| {
+            anyhow!(
+                "自定义接口的响应中没有找到回复内容（{}）",
+                self.options.text_path
+            )
+        })?;
+        Ok(ChatReply {
+            text,
+            usage,
+            ..Default::default()
+        })
+    }
+
+    /// 按SSE读取流式响应，data为[DONE]的事件表示结束；token用量取最后一次出现的值
+    async fn read_stream(
+        &self,
+        mut response: reqwest::Response,
+        delta_path: &JsonPath,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let status = response.status().as_u16();
+        let mut text = String::new();
+        let mut usage = TokenUsage::default();
+        let mut handle_event = |event: SseEvent| -> Result<()> {
+            let data = event.data.trim();
+            if data.is_empty() || data == "[DONE]" {
+                return Ok(());
+            }
+            if let Some(message) = self.error_message(data) {
+                return Err(LLMError {
+                    status: Some(status),
+                    ..LLMError::new(PROVIDER_NAME, LLMErrorKind::Other, message)
+                }
+                .into());
+            }
+            let data: Value = serde_json::from_str(data).map_err(|_| {
+                anyhow!(
+                    "自定义接口的流式响应不是有效的JSON: {}",
+                    data.chars().take(MAX_ERROR_BODY_CHARS).collect::<String>()
+                )
+            })?;
+            let tokens = |path: &Option<JsonPath>| {
+                path.as_ref()
+                    .and_then(|p| p.select(&data).first().and_then(|v| v.as_u64()))
+            };
+            usage.input_tokens = tokens(&self.input_tokens_path).or(usage.input_tokens);
+            usage.output_tokens = tokens(&self.output_tokens_path).or(usage.output_tokens);
+            if let Some(delta) = delta_path.select_text(&data) {
+                text.push_str(&delta);
+                on_delta(&delta)?;
+            }
+            Ok(())
+        };
+        let mut decoder = SseDecoder::new();
+        let read_timeout = self.http.read_timeout();
+        while let Some(chunk) = next_chunk(PROVIDER_NAME, &mut response, read_timeout).await? {
+            for event in decoder.feed(&chunk) {
+                handle_event(event)?;
+            }
+        }
+        if let Some(event) = decoder.finish() {
+            handle_event(event)?;
+        }
+        self.log_usage(&usage);
+        Ok(ChatReply {
+            text,
+            usage,
+            ..Default::default()
+        })
+    }
+
+    fn log_usage(&self, usage: &TokenUsage) {
+        info!(
+            "自定义接口调用完成，模型：{}，{}",
+            self.model,
+            usage.describe().as_deref().unwrap_or("用量未知")
+        );
+    }
+}
+
+#[async_trait]
+impl AIAgent for CustomHttpAgent {
+    fn generation_params(&self) -> &GenerationParams {
+        &self.params
+    }
+
+    async fn generate_raw_response(&self, prompt: &str) -> Result<String> {
+        Ok(self.send(prompt, &mut |_| Ok(())).await?.text)
+    }
+
+    // 自定义接口不支持图片及结构化输出，未配置streamDeltaPath时收到完整回复后一次性回调
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        _images: &[ImageInput],
+        _schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        Ok(self.send(prompt, on_delta).await?.text)
+    }
+
+    // 与默认实现相同地整理多轮对话，同时保留token用量
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        _schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let (prompt, _) = flatten_messages(messages)?;
+        self.send(&prompt, on_delta).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    /// 负数表示从末尾开始计数
+    Index(i64),
+    Wildcard,
+}
+
+/// JSONPath的子集：$.a.b、$['a']、$.a[0]、$.a[-1]、$.a[*].b
+#[derive(Debug, Clone)]
+struct JsonPath {
+    segments: Vec<PathSegment>,
+}
+
+impl JsonPath {
+    fn parse(path: &str) -> Result<Self> {
+        let invalid = |reason: &str| anyhow!("JSONPath表达式\"{}\"无效: {}", path, reason);
+        let trimmed = path.trim();
+        if trimmed.is_empty() {
+            return Err(invalid("表达式为空"));
+        }
+        let body = trimmed.strip_prefix('$').unwrap_or(trimmed);
+        let chars: Vec<char> = body.chars().collect();
+        let mut segments = Vec::new();
+        let mut i = 0;
+        // 省略$时第一个字段前没有"."
+        if !chars.is_empty() && chars[0] != '.' && chars[0] != '[' {
+            let end = chars.iter().position(|c| *c == '.' || *c == '[');
+            let end = end.unwrap_or(chars.len());
+            segments.push(PathSegment::Key(chars[..end].iter().collect()));
+            i = end;
+        }
+        while i < chars.len() {
+            match chars[i] {
+                '.' => {
+                    i += 1;
+                    if chars.get(i) == Some(&'.') {
+                        return Err(invalid("不支持递归查找(..)"));
+                    }
+                    if chars.get(i) == Some(&'*') {
+                        segments.push(PathSegment::Wildcard);
+                        i += 1;
+                        continue;
+                    }
+                    let start = i;
+                    while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                        i += 1;
+                    }
+                    if start == i {
+                        return Err(invalid("\".\"后缺少字段名"));
+                    }
+                    segments.push(PathSegment::Key(chars[start..i].iter().collect()));
+                }
+                '[' => {
+                    let skip_spaces = |mut j: usize| {
+                        while chars.get(j).is_some_and(|c| c.is_whitespace()) {
+                            j += 1;
+                        }
+                        j
+                    };
+                    let start = skip_spaces(i + 1);
+                    let (segment, close) = match chars.get(start) {
+                        // 带引号的字段名中可以出现"]"、"."等字符，以相同的引号结束
+                        Some(&quote) if quote == '\'' || quote == '"' => {
+                            let end = chars[start + 1..]
+                                .iter()
+                                .position(|c| *c == quote)
+                                .map(|p| p + start + 1)
+                                .ok_or_else(|| invalid("字段名缺少结束引号"))?;
+                            let close = skip_spaces(end + 1);
+                            if chars.get(close) != Some(&']') {
+                                return Err(invalid("缺少\"]\""));
+                            }
+                            let key = chars[start + 1..end].iter().collect();
+                            (PathSegment::Key(key), close)
+                        }
+                        _ => {
+                            let close = chars[start..]
+                                .iter()
+                                .position(|c| *c == ']')
+                                .map(|p| p + start)
+                                .ok_or_else(|| invalid("缺少\"]\""))?;
+                            let inner: String = chars[start..close].iter().collect();
+                            let inner = inner.trim();
+                            let segment = if inner == "*" {
+                                PathSegment::Wildcard
+                            } else {
+                                PathSegment::Index(inner.parse().map_err(|_| {
+                                    invalid("方括号中只能是数字下标、*或带引号的字段名")
+                                })?)
+                            };
+                            (segment, close)
+                        }
+                    };
+                    segments.push(segment);
+                    i = close + 1;
+                }
+                _ => return Err(invalid("字段之间需要用\".\"分隔")),
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    fn select<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![root];
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|value| -> Vec<&'a Value> {
+                    match (segment, value) {
+                        (PathSegment::Key(key), Value::Object(map)) => {
+                            map.get(key).into_iter().collect()
+                        }
+                        (PathSegment::Index(index), Value::Array(items)) => {
+                            let index = if *index < 0 {
+                                items.len() as i64 + index
+                            } else {
+                                *index
+                            };
+                            usize::try_from(index)
+                                .ok()
+                                .and_then(|i| items.get(i))
+                                .into_iter()
+                                .collect()
+                        }
+                        (PathSegment::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (PathSegment::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        current
+    }
+
+    /// 提取文本，匹配到多个值时依次拼接（如$.content[*].text）；没有匹配或值为null时返回None
+    fn select_text(&self, root: &Value) -> Option<String> {
+        let values: Vec<&Value> = self
+            .select(root)
+            .into_iter()
+            .filter(|v| !v.is_null())
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(
+            values
+                .into_iter()
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segments(path: &str) -> Vec<PathSegment> {
+        JsonPath::parse(path).unwrap().segments
+    }
+
+    fn agent(params: Value, body_template: &str) -> CustomHttpAgent {
+        let mut provider = json!({
+            "name": "Custom",
+            "baseUrl": "http://localhost:8080/",
+            "apiKey": "sk-test",
+            "model": "my-model",
+            "customHttp": { "bodyTemplate": body_template }
+        });
+        provider
+            .as_object_mut()
+            .unwrap()
+            .extend(params.as_object().unwrap().clone());
+        let provider: LLMProvider = serde_json::from_value(provider).unwrap();
+        CustomHttpAgent::new(&provider, provider.params.clone(), "你是代码助手").unwrap()
+    }
+
+    #[test]
+    fn parses_supported_path_forms() {
+        use PathSegment::*;
+        assert_eq!(
+            segments("$.choices[0].message.content"),
+            vec![
+                Key("choices".to_string()),
+                Index(0),
+                Key("message".to_string()),
+                Key("content".to_string())
+            ]
+        );
+        assert_eq!(
+            segments("data.items[-1]"),
+            vec![Key("data".to_string()), Key("items".to_string()), Index(-1)]
+        );
+        assert_eq!(
+            segments("$.content[*].text"),
+            vec![
+                Key("content".to_string()),
+                Wildcard,
+                Key("text".to_string())
+            ]
+        );
+        assert_eq!(
+            segments("$.*[ 'a]b' ][\"x.y\"]"),
+            vec![Wildcard, Key("a]b".to_string()), Key("x.y".to_string())]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        for path in [
+            "",
+            "  ",
+            "$..content",
+            "$.",
+            "$.a.",
+            "$.a[0",
+            "$.a['b",
+            "$.a['b'x]",
+            "$.a[b]",
+            "$.a[0]b",
+        ] {
+            assert!(JsonPath::parse(path).is_err(), "{} 应当无效", path);
+        }
+    }
+
+    #[test]
+    fn selects_values_by_index_and_wildcard() {
+        let data = json!({
+            "items": [{"id": 1}, {"id": 2}, {"id": 3}],
+            "usage": {"input": 10, "output": 20}
+        });
+        let select = |path: &str| -> Vec<Value> {
+            JsonPath::parse(path)
+                .unwrap()
+                .select(&data)
+                .into_iter()
+                .cloned()
+                .collect()
+        };
+        assert_eq!(select("$.items[-1].id"), vec![json!(3)]);
+        assert_eq!(select("$.items[-3].id"), vec![json!(1)]);
+        assert!(select("$.items[-4].id").is_empty());
+        assert!(select("$.items[3].id").is_empty());
+        assert_eq!(select("$.items[*].id"), vec![json!(1), json!(2), json!(3)]);
+        assert_eq!(select("$.usage.*"), vec![json!(10), json!(20)]);
+        assert!(select("$.usage[0]").is_empty());
+        assert!(select("$.missing.id").is_empty());
+    }
+
+    #[test]
+    fn select_text_concatenates_matches() {
+        let data = json!({
+            "content": [
+                {"type": "text", "text": "Hello, "},
+                {"type": "tool_use"},
+                {"type": "text", "text": "world"},
+                {"type": "text", "text": null}
+            ],
+            "count": 42,
+            "error": null
+        });
+        let text = |path: &str| JsonPath::parse(path).unwrap().select_text(&data);
+        assert_eq!(text("$.content[*].text").as_deref(), Some("Hello, world"));
+        assert_eq!(text("$.count").as_deref(), Some("42"));
+        assert_eq!(text("$.error"), None);
+        assert_eq!(text("$.missing"), None);
+    }
+
+    #[test]
+    fn typed_placeholders_become_json_values() {
+        let template = r#"{"model":"{{model}}","temperature":"{{temperature}}","top_p":"{{top_p}}","stop":"{{stop}}","max_tokens":"{{max_tokens}}","note":"t={{temperature}}","messages":[{"role":"user","content":"{{prompt}}"}]}"#;
+        let agent = agent(
+            json!({"temperature": 0.5, "maxTokens": 1024, "stop": ["END"]}),
+            template,
+        );
+        let body = agent.render_body(&agent.body_template, "你好");
+        assert_eq!(
+            body,
+            json!({
+                "model": "my-model",
+                "temperature": 0.5,
+                "top_p": null,
+                "stop": ["END"],
+                "max_tokens": 1024,
+                "note": "t=0.5",
+                "messages": [{"role": "user", "content": "你好"}]
+            })
+        );
+    }
+
+    #[test]
+    fn unset_params_render_as_null_or_empty_text() {
+        let agent = agent(
+            json!({}),
+            r#"{"temperature":"{{temperature}}","stop":"{{stop}}","note":"t={{temperature}}","system":"{{system}}"}"#,
+        );
+        let body = agent.render_body(&agent.body_template, "");
+        assert_eq!(
+            body,
+            json!({"temperature": null, "stop": null, "note": "t=", "system": "你是代码助手"})
+        );
+    }
+}
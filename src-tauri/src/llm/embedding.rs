@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rig::{
+    embeddings::EmbeddingModel,
+    providers::{gemini, ollama, openai},
+};
+
+use crate::storage::sys_config::get_config;
+
+use super::{
+    agent::{LLMProvider, LLMProviderType},
+    azure::AzureEmbedder,
+};
+
+#[async_trait]
+pub trait AIEmbedder: Send + Sync {
+    /// 向量模型标识，模型变化后已有的向量不再可比，需要重建索引
+    fn model_id(&self) -> &str;
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
+}
+
+struct RigEmbedder<M: EmbeddingModel> {
+    model: M,
+    model_id: String,
+}
+
+#[async_trait]
+impl<M: EmbeddingModel + 'static> AIEmbedder for RigEmbedder<M> {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let embeddings = self.model.embed_texts(texts).await?;
+        Ok(embeddings
+            .into_iter()
+            .map(|e| e.vec.into_iter().map(|v| v as f32).collect())
+            .collect())
+    }
+}
+
+/// 根据sys_config中的embedding_provider配置（格式同LLMProvider）构建向量模型
+pub async fn build_embedder() -> Result<Box<dyn AIEmbedder>> {
+    let provider_conf = get_config("embedding_provider".to_string())
+        .await?
+        .ok_or_else(|| anyhow!("当前未配置向量模型"))?;
+    let provider: LLMProvider = serde_json::from_str(&provider_conf)?;
+    let model_id = format!("{:?}:{}", provider.name, provider.model);
+    match provider.name {
+        LLMProviderType::OpenAI => {
+            let client = openai::Client::from_url(&provider.api_key, &provider.base_url);
+            Ok(Box::new(RigEmbedder {
+                model: client.embedding_model(&provider.model),
+                model_id,
+            }))
+        }
+        LLMProviderType::Ollama => {
+            let client = ollama::Client::from_url(&provider.base_url);
+            Ok(Box::new(RigEmbedder {
+                model: client.embedding_model(&provider.model),
+                model_id,
+            }))
+        }
+        LLMProviderType::Gemini => {
+            let base_url = if provider.base_url.trim().is_empty() {
+                super::gemini::DEFAULT_BASE_URL
+            } else {
+                provider.base_url.trim()
+            };
+            let client = gemini::Client::from_url(&provider.api_key, base_url);
+            Ok(Box::new(RigEmbedder {
+                model: client.embedding_model(&provider.model),
+                model_id,
+            }))
+        }
+        LLMProviderType::AzureOpenAI => Ok(Box::new(AzureEmbedder::new(&provider, model_id)?)),
+        LLMProviderType::Anthropic => Err(anyhow!(
+            "Anthropic没有提供向量模型，请使用OpenAI兼容接口或Ollama作为向量模型"
+        )),
+        LLMProviderType::Custom => Err(anyhow!(
+            "自定义接口暂不支持作为向量模型，请使用OpenAI兼容接口或Ollama作为向量模型"
+        )),
+    }
+}
@@ -0,0 +1,268 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::FileModifyResult;
+
+/// 文件块的标题行，如“### FILE: src/main.rs”。路径后必须换行，流式输出时据此判断路径已经完整
+pub static FILE_HEADER_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?m)^[ \t]*(?:#{1,6}[ \t]*)?(?:\*\*)?FILE:(?:\*\*)?[ \t]*(.*?)[ \t]*\r?\n")
+        .unwrap()
+});
+
+/// 解析文件块格式的回复。每个文件块由标题行及紧随其后的代码块组成，代码块中的内容原样作为文件内容，
+/// 结束标记必须与起始标记使用相同的字符且长度不小于起始标记，因此内容中较短的代码块标记不会提前结束文件块。
+/// 文件块之外的说明文字会被忽略
+pub fn parse_file_blocks(response: &str) -> Result<Vec<FileModifyResult>> {
+    let mut files = Vec::new();
+    // 保留行尾换行符，文件内容按原样拼接
+    let mut lines = response.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let Some(path) = header_path(line) else {
+            continue;
+        };
+        // 标题行之后的第一个非空行为代码块的起始标记
+        let fence = loop {
+            match lines.next() {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => {
+                    break opening_fence(line)
+                        .ok_or_else(|| anyhow!("文件{}的内容缺少起始的代码块标记", path))?
+                }
+                None => return Err(anyhow!("文件{}没有内容，回复可能不完整", path)),
+            }
+        };
+        let mut content = String::new();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            if is_closing_fence(line, &fence) {
+                closed = true;
+                break;
+            }
+            content.push_str(line);
+        }
+        if !closed {
+            return Err(anyhow!(
+                "文件{}的内容缺少结束的代码块标记，回复可能不完整",
+                path
+            ));
+        }
+        files.push(FileModifyResult {
+            file_path: path,
+            file_content: content,
+        });
+    }
+    if files.is_empty() {
+        return Err(anyhow!(
+            "LLM响应中没有找到文件块，没有按“### FILE: 路径”加代码块的格式输出"
+        ));
+    }
+    Ok(files)
+}
+
+/// 按文件块格式输出文件，代码块标记比内容中最长的连续`多一个且至少为4个
+pub fn render_file_blocks(files: &[FileModifyResult]) -> String {
+    let mut output = String::new();
+    for file in files {
+        let longest = file
+            .file_content
+            .split(|c| c != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or_default();
+        let fence = "`".repeat((longest + 1).max(4));
+        let language = Path::new(&file.file_path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        output.push_str(&format!(
+            "### FILE: {}\n{}{}\n",
+            file.file_path, fence, language
+        ));
+        output.push_str(&file.file_content);
+        if !file.file_content.ends_with('\n') {
+            output.push('\n');
+        }
+        output.push_str(&fence);
+        output.push_str("\n\n");
+    }
+    output
+}
+
+/// 回复在文件块中间被截断时，最后一个文件块的状态
+#[derive(Debug, PartialEq)]
+pub enum OpenBlock {
+    /// 只输出了标题行，代码块尚未开始
+    Header,
+    /// 代码块已开始但没有结束，值为起始标记
+    Content(String),
+}
+
+/// 查找没有结束的文件块，即输出在文件块中间被截断
+pub fn open_block(response: &str) -> Option<OpenBlock> {
+    let mut lines = response.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        if header_path(line).is_none() {
+            continue;
+        }
+        let fence = loop {
+            match lines.next() {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => break opening_fence(line),
+                None => return Some(OpenBlock::Header),
+            }
+        };
+        // 缺少起始标记属于格式错误，不是截断
+        let Some(fence) = fence else {
+            continue;
+        };
+        if !lines.by_ref().any(|line| is_closing_fence(line, &fence)) {
+            return Some(OpenBlock::Content(fence));
+        }
+    }
+    None
+}
+
+/// 标题行中的文件路径，路径可以为空（未提供目录结构时）
+fn header_path(line: &str) -> Option<String> {
+    let line = line.trim().trim_start_matches('#').trim_start();
+    let line = line.strip_prefix("**").unwrap_or(line);
+    let path = line.strip_prefix("FILE:")?;
+    let path = path.strip_prefix("**").unwrap_or(path);
+    Some(
+        path.trim()
+            .trim_matches(['`', '"', '\''])
+            .trim()
+            .to_string(),
+    )
+}
+
+/// 起始标记为至少3个`或~，后面可以跟语言名称
+fn opening_fence(line: &str) -> Option<String> {
+    let line = line.trim();
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence: String = line.chars().take_while(|c| *c == marker).collect();
+    if fence.len() < 3 || (marker == '`' && line[fence.len()..].contains('`')) {
+        return None;
+    }
+    Some(fence)
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let line = line.trim();
+    let marker = fence.chars().next().unwrap();
+    line.len() >= fence.len() && line.chars().all(|c| c == marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, content: &str) -> FileModifyResult {
+        FileModifyResult {
+            file_path: path.to_string(),
+            file_content: content.to_string(),
+        }
+    }
+
+    fn paths_and_contents(files: &[FileModifyResult]) -> Vec<(&str, &str)> {
+        files
+            .iter()
+            .map(|f| (f.file_path.as_str(), f.file_content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_header_variants() {
+        let response = "**FILE:** src/a.rs\n```rust\nfn a() {}\n```\n\
+                        # FILE: `src/b.rs`\n\n```\nfn b() {}\n```\n\
+                        ###   FILE:src/c.rs  \n~~~\nc\n~~~\n";
+        let files = parse_file_blocks(response).unwrap();
+        assert_eq!(
+            paths_and_contents(&files),
+            vec![
+                ("src/a.rs", "fn a() {}\n"),
+                ("src/b.rs", "fn b() {}\n"),
+                ("src/c.rs", "c\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_empty_path() {
+        let files = parse_file_blocks("### FILE:\n```java\nclass A {}\n```\n").unwrap();
+        assert_eq!(paths_and_contents(&files), vec![("", "class A {}\n")]);
+    }
+
+    #[test]
+    fn shorter_fence_inside_content_does_not_close_block() {
+        let response =
+            "### FILE: README.md\n````markdown\n# Demo\n```bash\nnpm run dev\n```\n````\n";
+        let files = parse_file_blocks(response).unwrap();
+        assert_eq!(
+            paths_and_contents(&files),
+            vec![("README.md", "# Demo\n```bash\nnpm run dev\n```\n")]
+        );
+    }
+
+    #[test]
+    fn ignores_prose_between_blocks() {
+        let response = "Here are the files.\n\n### FILE: a.txt\n```\nA\n```\n\
+                        Now the second one, which uses `FILE:` inline.\n\n\
+                        ### FILE: b.txt\n```\nB\n```\nDone.\n";
+        let files = parse_file_blocks(response).unwrap();
+        assert_eq!(
+            paths_and_contents(&files),
+            vec![("a.txt", "A\n"), ("b.txt", "B\n")]
+        );
+    }
+
+    #[test]
+    fn missing_closing_fence_is_an_error() {
+        let response = "### FILE: a.rs\n```rust\nfn a() {\n";
+        let err = parse_file_blocks(response).unwrap_err();
+        assert!(err.to_string().contains("缺少结束的代码块标记"));
+        assert_eq!(open_block(response), Some(OpenBlock::Content("```".into())));
+    }
+
+    #[test]
+    fn missing_content_and_opening_fence_are_errors() {
+        assert!(parse_file_blocks("### FILE: a.rs\n\n").is_err());
+        assert!(parse_file_blocks("### FILE: a.rs\nfn a() {}\n").is_err());
+        assert!(parse_file_blocks("no files here").is_err());
+    }
+
+    #[test]
+    fn detects_open_block() {
+        assert_eq!(open_block("### FILE: a.rs\n"), Some(OpenBlock::Header));
+        assert_eq!(
+            open_block("### FILE: a.rs\n````rust\nfn a() {}\n```\n"),
+            Some(OpenBlock::Content("````".into()))
+        );
+        assert_eq!(open_block("### FILE: a.rs\n```\nfn a() {}\n```\n"), None);
+        // 缺少起始标记是格式错误，不视为截断
+        assert_eq!(open_block("### FILE: a.rs\nfn a() {}\n"), None);
+    }
+
+    #[test]
+    fn render_round_trips() {
+        let files = vec![
+            file("src/main.rs", "fn main() {}\n"),
+            file("README.md", "# Demo\n````bash\nnpm run dev\n````\n"),
+            file("", "no trailing newline"),
+        ];
+        let rendered = render_file_blocks(&files);
+        assert!(rendered.contains("### FILE: README.md\n`````md\n"));
+        let parsed = parse_file_blocks(&rendered).unwrap();
+        assert_eq!(
+            paths_and_contents(&parsed),
+            vec![
+                ("src/main.rs", "fn main() {}\n"),
+                ("README.md", "# Demo\n````bash\nnpm run dev\n````\n"),
+                ("", "no trailing newline\n"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use super::{
+    agent::{
+        AIAgent, ChatMessage, ChatReply, ChatRole, GenerationParams, StreamCallback,
+        StructuredOutput, TokenUsage, ToolCall, ToolSpec,
+    },
+    http::{next_chunk, send_with_retry, HttpOptions},
+    image::ImageInput,
+    sse::SseDecoder,
+    structured::{to_openapi_schema, OutputSchema},
+};
+
+const PROVIDER_NAME: &str = "Gemini接口";
+
+pub const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+
+/// 调用Gemini streamGenerateContent接口的Agent
+pub struct GeminiAgent {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    model: String,
+    preamble: String,
+    params: GenerationParams,
+    vision: bool,
+    structured_output: StructuredOutput,
+    http: HttpOptions,
+}
+
+impl GeminiAgent {
+    pub fn new(
+        base_url: &str,
+        api_key: &str,
+        model: &str,
+        preamble: &str,
+        params: GenerationParams,
+        vision: bool,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: generate_content_endpoint(base_url, model),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            preamble: preamble.to_string(),
+            params,
+            vision,
+            structured_output: StructuredOutput::default(),
+            http: HttpOptions::default(),
+        }
+    }
+
+    pub fn with_structured_output(mut self, structured_output: StructuredOutput) -> Self {
+        self.structured_output = structured_output;
+        self
+    }
+
+    pub fn with_http_options(mut self, http: HttpOptions) -> Self {
+        self.client = http.client();
+        self.http = http;
+        self
+    }
+
+    fn generation_config(&self, schema: Option<&OutputSchema>) -> Value {
+        let mut config = json!({});
+        if let Some(max_tokens) = self.params.max_tokens {
+            config["maxOutputTokens"] = json!(max_tokens);
+        }
+        if let Some(temperature) = self.params.temperature {
+            config["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = self.params.top_p {
+            config["topP"] = json!(top_p);
+        }
+        if let Some(seed) = self.params.seed {
+            config["seed"] = json!(seed);
+        }
+        let stop = self.params.stop_sequences();
+        if !stop.is_empty() {
+            config["stopSequences"] = json!(stop);
+        }
+        if let Some(schema) = schema {
+            match self.structured_output {
+                StructuredOutput::Schema => {
+                    config["responseMimeType"] = json!("application/json");
+                    config["responseSchema"] = schema.openapi_schema();
+                }
+                StructuredOutput::JsonObject => {
+                    config["responseMimeType"] = json!("application/json");
+                }
+                StructuredOutput::Off => {}
+            }
+        }
+        config
+    }
+
+    /// 提交对话消息，指定schema时要求模型直接返回符合schema的JSON
+    async fn generate(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        tools: &[ToolSpec],
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let mut body = json!({ "contents": Self::contents(messages) });
+        if !self.preamble.is_empty() {
+            body["systemInstruction"] = json!({ "parts": [{ "text": self.preamble }] });
+        }
+        if !tools.is_empty() {
+            let declarations: Vec<Value> = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": to_openapi_schema(&tool.parameters)
+                    })
+                })
+                .collect();
+            body["tools"] = json!([{ "functionDeclarations": declarations }]);
+        }
+        let generation_config = self.generation_config(schema);
+        if generation_config.as_object().is_some_and(|c| !c.is_empty()) {
+            body["generationConfig"] = generation_config;
+        }
+        let request = self
+            .client
+            .post(&self.endpoint)
+            .header("x-goog-api-key", &self.api_key)
+            .json(&body);
+        let mut response =
+            send_with_retry(PROVIDER_NAME, &self.http, request, error_message).await?;
+
+        // 每个事件都是一个完整的响应片段，用量及结束原因在最后一个片段中
+        let mut reply = ChatReply::default();
+        let mut last = Value::Null;
+        let mut decoder = SseDecoder::new();
+        let read_timeout = self.http.read_timeout();
+        while let Some(chunk) = next_chunk(PROVIDER_NAME, &mut response, read_timeout).await? {
+            for event in decoder.feed(&chunk) {
+                if let Some(data) = handle_chunk(&event.data, &mut reply, on_delta)? {
+                    last = data;
+                }
+            }
+        }
+        if let Some(event) = decoder.finish() {
+            if let Some(data) = handle_chunk(&event.data, &mut reply, on_delta)? {
+                last = data;
+            }
+        }
+
+        let usage = &last["usageMetadata"];
+        reply.usage = TokenUsage {
+            input_tokens: usage["promptTokenCount"].as_u64(),
+            output_tokens: usage["candidatesTokenCount"].as_u64(),
+            thinking_tokens: usage["thoughtsTokenCount"].as_u64(),
+        };
+        info!(
+            "Gemini调用完成，模型：{}，{}",
+            self.model,
+            reply.usage.describe().as_deref().unwrap_or("用量未知")
+        );
+        reply.truncated = last["candidates"][0]["finishReason"].as_str() == Some("MAX_TOKENS");
+        if reply.truncated {
+            warn!("回复达到最大Token数的限制，内容可能不完整");
+        }
+        Ok(reply)
+    }
+
+    /// Gemini中模型回复的角色为model，工具结果以functionResponse的形式放在用户消息中，
+    /// 连续的工具结果合并为一条消息
+    fn contents(messages: &[ChatMessage]) -> Vec<Value> {
+        let mut contents: Vec<Value> = Vec::new();
+        for message in messages {
+            match message.role {
+                ChatRole::User => contents.push(json!({
+                    "role": "user",
+                    "parts": Self::parts(&message.content, &message.images)
+                })),
+                ChatRole::Assistant => {
+                    let mut parts = Vec::new();
+                    if !message.content.is_empty() || message.tool_calls.is_empty() {
+                        parts.push(json!({ "text": message.content }));
+                    }
+                    for call in &message.tool_calls {
+                        let mut part = json!({
+                            "functionCall": { "name": call.name, "args": call.arguments }
+                        });
+                        if let Some(signature) = &call.thought_signature {
+                            part["thoughtSignature"] = json!(signature);
+                        }
+                        parts.push(part);
+                    }
+                    contents.push(json!({ "role": "model", "parts": parts }));
+                }
+                ChatRole::Tool => {
+                    let name = message.tool_call.as_ref().map(|call| call.name.as_str());
+                    let part = json!({
+                        "functionResponse": {
+                            "name": name,
+                            "response": { "content": message.content }
+                        }
+                    });
+                    let follows_response = contents
+                        .last()
+                        .is_some_and(|last| last["parts"][0].get("functionResponse").is_some());
+                    match contents
+                        .last_mut()
+                        .and_then(|last| last["parts"].as_array_mut())
+                    {
+                        Some(parts) if follows_response => parts.push(part),
+                        _ => contents.push(json!({ "role": "user", "parts": [part] })),
+                    }
+                }
+            }
+        }
+        contents
+    }
+
+    fn parts(prompt: &str, images: &[ImageInput]) -> Vec<Value> {
+        let mut parts: Vec<Value> = images
+            .iter()
+            .map(|image| {
+                json!({
+                    "inlineData": { "mimeType": image.media_type, "data": image.data }
+                })
+            })
+            .collect();
+        parts.push(json!({ "text": prompt }));
+        parts
+    }
+}
+
+#[async_trait]
+impl AIAgent for GeminiAgent {
+    async fn generate_raw_response(&self, prompt: &str) -> Result<String> {
+        self.generate(
+            &[ChatMessage::user(prompt, &[])],
+            None,
+            &[],
+            &mut |_| Ok(()),
+        )
+        .await
+        .map(|reply| reply.text)
+    }
+
+    fn supports_vision(&self) -> bool {
+        self.vision
+    }
+
+    fn generation_params(&self) -> &GenerationParams {
+        &self.params
+    }
+
+    async fn generate_raw_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        self.generate(&[ChatMessage::user(prompt, images)], None, &[], &mut |_| {
+            Ok(())
+        })
+        .await
+        .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        self.generate(&[ChatMessage::user(prompt, images)], schema, &[], on_delta)
+            .await
+            .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        self.generate(messages, schema, &[], on_delta).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate_tool_chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatReply> {
+        self.generate(messages, None, tools, &mut |_| Ok(())).await
+    }
+}
+
+/// 处理一个响应片段，返回解析后的片段以便读取用量及结束原因
+fn handle_chunk(
+    data: &str,
+    reply: &mut ChatReply,
+    on_delta: &mut StreamCallback<'_>,
+) -> Result<Option<Value>> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let data: Value =
+        serde_json::from_str(data).map_err(|e| anyhow!("Gemini响应格式错误: {}", e))?;
+    if data.get("error").is_some() {
+        return Err(anyhow!(
+            "Gemini接口返回错误: {}",
+            error_message(&data.to_string())
+        ));
+    }
+    // 问题本身被拦截时没有候选回复，只有promptFeedback
+    if let Some(reason) = data.pointer("/promptFeedback/blockReason") {
+        return Err(anyhow!(
+            "问题被Gemini拦截：{}{}",
+            block_reason_text(reason.as_str().unwrap_or_default()),
+            blocked_categories(&data["promptFeedback"]["safetyRatings"])
+        ));
+    }
+    let Some(candidate) = data.pointer("/candidates/0") else {
+        return Ok(Some(data));
+    };
+    match candidate["finishReason"].as_str().unwrap_or_default() {
+        "" | "STOP" | "MAX_TOKENS" => {}
+        reason => {
+            return Err(anyhow!(
+                "回复被Gemini拦截：{}{}",
+                block_reason_text(reason),
+                blocked_categories(&candidate["safetyRatings"])
+            ));
+        }
+    }
+    // 思考模型的思考内容以thought为true的part返回，不属于回复正文
+    if let Some(parts) = candidate["content"]["parts"].as_array() {
+        for part in parts {
+            if part["thought"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            if let Some(delta) = part["text"].as_str() {
+                reply.text.push_str(delta);
+                on_delta(delta)?;
+            }
+            if let Some(call) = part.get("functionCall") {
+                reply.tool_calls.push(ToolCall {
+                    id: call["id"]
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("call_{}", reply.tool_calls.len())),
+                    name: call["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: call["args"].clone(),
+                    thought_signature: part["thoughtSignature"].as_str().map(str::to_string),
+                });
+            }
+        }
+    }
+    Ok(Some(data))
+}
+
+/// 接口地址为{baseUrl}/v1beta/models/{model}:streamGenerateContent，兼容已包含版本号的地址
+fn generate_content_endpoint(base_url: &str, model: &str) -> String {
+    let base_url = base_url.trim().trim_end_matches('/');
+    let base_url = if base_url.is_empty() {
+        DEFAULT_BASE_URL
+    } else {
+        base_url
+    };
+    let model = model.trim().trim_start_matches("models/");
+    if base_url.ends_with("/v1beta") || base_url.ends_with("/v1") {
+        format!(
+            "{}/models/{}:streamGenerateContent?alt=sse",
+            base_url, model
+        )
+    } else {
+        format!(
+            "{}/v1beta/models/{}:streamGenerateContent?alt=sse",
+            base_url, model
+        )
+    }
+}
+
+/// 错误格式为{"error":{"code":...,"message":...,"status":...}}
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| {
+            let error = v.get("error")?;
+            Some(format!(
+                "{}: {}",
+                error["status"].as_str().unwrap_or_default(),
+                error["message"].as_str()?
+            ))
+        })
+        .unwrap_or_else(|| body.to_string())
+}
+
+fn block_reason_text(reason: &str) -> String {
+    match reason {
+        "SAFETY" => "触发了安全策略".to_string(),
+        "BLOCKLIST" => "包含被禁止的词语".to_string(),
+        "PROHIBITED_CONTENT" => "包含被禁止的内容".to_string(),
+        "RECITATION" => "内容可能与受版权保护的资料雷同".to_string(),
+        "SPII" => "包含敏感的个人信息".to_string(),
+        "IMAGE_SAFETY" => "图片触发了安全策略".to_string(),
+        "LANGUAGE" => "使用了不支持的语言".to_string(),
+        "MALFORMED_FUNCTION_CALL" => "模型生成的函数调用格式错误".to_string(),
+        reason => format!("原因为{}", reason),
+    }
+}
+
+/// 列出被拦截或风险较高的安全类别
+fn blocked_categories(ratings: &Value) -> String {
+    let categories: Vec<&str> = ratings
+        .as_array()
+        .map(|ratings| {
+            ratings
+                .iter()
+                .filter(|r| {
+                    r["blocked"].as_bool().unwrap_or(false)
+                        || matches!(r["probability"].as_str(), Some("HIGH") | Some("MEDIUM"))
+                })
+                .filter_map(|r| r["category"].as_str())
+                .map(|category| match category {
+                    "HARM_CATEGORY_HARASSMENT" => "骚扰",
+                    "HARM_CATEGORY_HATE_SPEECH" => "仇恨言论",
+                    "HARM_CATEGORY_SEXUALLY_EXPLICIT" => "色情内容",
+                    "HARM_CATEGORY_DANGEROUS_CONTENT" => "危险内容",
+                    "HARM_CATEGORY_CIVIC_INTEGRITY" => "公民诚信",
+                    category => category,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if categories.is_empty() {
+        String::new()
+    } else {
+        format!("（{}）", categories.join("、"))
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// 重试的初始间隔，之后每次翻倍
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// 重试间隔的上限，Retry-After超过该值时也只等待该时长
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(120);
+
+/// LLM接口的超时及重试设置，对应LLM配置中的同名字段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpOptions {
+    /// 建立连接的超时时间（秒）
+    #[serde(rename = "connectTimeout")]
+    pub connect_timeout: u64,
+    /// 等待响应以及流式输出时两次收到数据之间的最长间隔（秒），不限制生成的总耗时
+    #[serde(rename = "readTimeout")]
+    pub read_timeout: u64,
+    /// 限流、服务端错误及网络错误时的最大重试次数
+    #[serde(rename = "maxRetries")]
+    pub max_retries: u32,
+    /// 每次重试前的回调，不属于配置。任务中用于把重试信息写入任务日志
+    #[serde(skip)]
+    pub on_retry: Option<RetryListener>,
+}
+
+/// 重试前的回调，参数为本次失败的错误、等待时间及第几次重试
+type RetryFn = dyn Fn(&LLMError, Duration, u32) + Send + Sync;
+
+#[derive(Clone)]
+pub struct RetryListener(Arc<RetryFn>);
+
+impl RetryListener {
+    pub fn new(listener: impl Fn(&LLMError, Duration, u32) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(listener))
+    }
+}
+
+impl fmt::Debug for RetryListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RetryListener")
+    }
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            // 不支持流式输出的接口在生成完成后才返回响应，需要留出足够的时间
+            read_timeout: 300,
+            max_retries: 3,
+            on_retry: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    pub fn client(&self) -> reqwest::Client {
+        reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout.max(1)))
+            .build()
+            .unwrap_or_default()
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout.max(1))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LLMErrorKind {
+    /// API Key、令牌无效或没有权限
+    Auth,
+    /// 余额或配额不足，重试无效
+    Quota,
+    /// 请求过于频繁
+    RateLimited,
+    /// 模型或接口地址不存在
+    ModelNotFound,
+    /// 提示词超过模型的上下文长度
+    ContextTooLong,
+    Timeout,
+    Network,
+    /// 服务端错误或过载
+    Server,
+    Other,
+}
+
+/// 分类后的LLM接口错误，通过anyhow传递，需要区分处理时使用downcast_ref取得
+#[derive(Debug)]
+pub struct LLMError {
+    pub kind: LLMErrorKind,
+    /// 供应商名称，用于错误信息
+    pub provider: String,
+    pub status: Option<u16>,
+    pub message: String,
+    /// 服务端要求的重试等待时间
+    pub retry_after: Option<Duration>,
+    /// 出错前已经重试的次数
+    pub retries: u32,
+}
+
+impl LLMError {
+    pub fn new(provider: &str, kind: LLMErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            provider: provider.to_string(),
+            status: None,
+            message: message.into(),
+            retry_after: None,
+            retries: 0,
+        }
+    }
+
+    /// 根据HTTP状态码及错误说明对错误分类
+    pub fn from_status(provider: &str, status: u16, message: String) -> Self {
+        Self {
+            kind: classify(status, &message),
+            status: Some(status),
+            ..Self::new(provider, LLMErrorKind::Other, message)
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind,
+            LLMErrorKind::RateLimited
+                | LLMErrorKind::Timeout
+                | LLMErrorKind::Network
+                | LLMErrorKind::Server
+        )
+    }
+
+    fn category(&self) -> &'static str {
+        match self.kind {
+            LLMErrorKind::Auth => "认证失败",
+            LLMErrorKind::Quota => "配额不足",
+            LLMErrorKind::RateLimited => "请求过于频繁",
+            LLMErrorKind::ModelNotFound => "模型不存在",
+            LLMErrorKind::ContextTooLong => "上下文超长",
+            LLMErrorKind::Timeout => "请求超时",
+            LLMErrorKind::Network => "网络错误",
+            LLMErrorKind::Server => "服务端错误",
+            LLMErrorKind::Other => "返回错误",
+        }
+    }
+
+    fn hint(&self) -> &'static str {
+        match self.kind {
+            LLMErrorKind::Auth => "，请检查API Key或访问令牌是否正确、是否有权限访问该模型",
+            LLMErrorKind::Quota => "，请检查账户余额或配额",
+            LLMErrorKind::RateLimited => "，请稍后重试或调大重试次数",
+            LLMErrorKind::ModelNotFound => "，请检查模型名称及基础URL",
+            LLMErrorKind::ContextTooLong => "，请减少引用的资源或改为引用大纲、指定符号",
+            LLMErrorKind::Timeout => "，可以在LLM配置中调大读取超时",
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for LLMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.provider, self.category())?;
+        if let Some(status) = self.status {
+            write!(f, "({})", status)?;
+        }
+        write!(f, ": {}{}", self.message, self.hint())
+    }
+}
+
+impl std::error::Error for LLMError {}
+
+fn classify(status: u16, message: &str) -> LLMErrorKind {
+    let message = message.to_lowercase();
+    let contains_any = |words: &[&str]| words.iter().any(|w| message.contains(w));
+    let context_too_long = contains_any(&[
+        "context length",
+        "context_length",
+        "context window",
+        "maximum context",
+        "prompt is too long",
+        "too many tokens",
+        "input is too long",
+        "exceeds the maximum number of tokens",
+    ]);
+    let model_not_found = message.contains("model")
+        && contains_any(&["not found", "does not exist", "not exist", "not_found"]);
+    match status {
+        401 | 403 => LLMErrorKind::Auth,
+        402 => LLMErrorKind::Quota,
+        // 配额用尽与限流都返回429，只有前者重试无效。Gemini限流时的说明中也有quota，不能据此判断
+        429 if contains_any(&["insufficient", "billing", "balance", "credit"]) => {
+            LLMErrorKind::Quota
+        }
+        429 => LLMErrorKind::RateLimited,
+        404 => LLMErrorKind::ModelNotFound,
+        400 | 413 | 422 if context_too_long => LLMErrorKind::ContextTooLong,
+        400 if model_not_found => LLMErrorKind::ModelNotFound,
+        408 | 504 => LLMErrorKind::Timeout,
+        // 529为Anthropic的过载错误
+        500..=599 => LLMErrorKind::Server,
+        _ => LLMErrorKind::Other,
+    }
+}
+
+/// 发送请求，限流、服务端错误、超时及网络错误时按指数退避（带随机抖动）重试，
+/// 服务端返回Retry-After时按其等待。error_message用于从错误响应体中提取错误说明
+pub async fn send_with_retry(
+    provider: &str,
+    options: &HttpOptions,
+    request: reqwest::RequestBuilder,
+    error_message: impl Fn(&str) -> String,
+) -> Result<reqwest::Response> {
+    let read_timeout = options.read_timeout();
+    let mut retries = 0;
+    loop {
+        let current = request
+            .try_clone()
+            .ok_or_else(|| anyhow!("请求体不支持重试"))?;
+        let mut error = match tokio::time::timeout(read_timeout, current.send()).await {
+            Err(_) => LLMError::new(
+                provider,
+                LLMErrorKind::Timeout,
+                format!("{}秒内没有收到响应", read_timeout.as_secs()),
+            ),
+            Ok(Err(e)) => request_error(provider, e),
+            Ok(Ok(response)) if response.status().is_success() => return Ok(response),
+            Ok(Ok(response)) => {
+                let status = response.status().as_u16();
+                let retry_after = retry_after(response.headers());
+                let body = read_text(provider, response, read_timeout)
+                    .await
+                    .unwrap_or_default();
+                LLMError {
+                    retry_after,
+                    ..LLMError::from_status(provider, status, error_message(&body))
+                }
+            }
+        };
+        error.retries = retries;
+        if !error.is_retryable() || retries >= options.max_retries {
+            return Err(error.into());
+        }
+        retries += 1;
+        let delay = match error.retry_after {
+            Some(delay) => delay.min(MAX_RETRY_DELAY),
+            None => backoff(retries),
+        };
+        warn!("{}", describe_retry(&error, delay, retries));
+        if let Some(listener) = &options.on_retry {
+            (listener.0)(&error, delay, retries);
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// 重试提示，包含错误分类及等待时间
+pub fn describe_retry(error: &LLMError, delay: Duration, retry: u32) -> String {
+    format!(
+        "{}，{:.1}秒后进行第{}次重试",
+        error,
+        delay.as_secs_f32(),
+        retry
+    )
+}
+
+/// 读取流式响应的下一段数据，超过读取超时仍未收到数据时返回错误
+pub async fn next_chunk(
+    provider: &str,
+    response: &mut reqwest::Response,
+    read_timeout: Duration,
+) -> Result<Option<Vec<u8>>> {
+    match tokio::time::timeout(read_timeout, response.chunk()).await {
+        Err(_) => Err(LLMError::new(
+            provider,
+            LLMErrorKind::Timeout,
+            format!("{}秒内没有收到新的回复内容", read_timeout.as_secs()),
+        )
+        .into()),
+        Ok(Err(e)) => Err(request_error(provider, e).into()),
+        Ok(Ok(chunk)) => Ok(chunk.map(|c| c.to_vec())),
+    }
+}
+
+/// 读取完整的响应体，超过读取超时时返回错误
+pub async fn read_text(
+    provider: &str,
+    response: reqwest::Response,
+    read_timeout: Duration,
+) -> Result<String> {
+    match tokio::time::timeout(read_timeout, response.text()).await {
+        Err(_) => Err(LLMError::new(
+            provider,
+            LLMErrorKind::Timeout,
+            format!("{}秒内没有读取到完整的响应", read_timeout.as_secs()),
+        )
+        .into()),
+        Ok(Err(e)) => Err(request_error(provider, e).into()),
+        Ok(Ok(text)) => Ok(text),
+    }
+}
+
+fn request_error(provider: &str, e: reqwest::Error) -> LLMError {
+    let kind = if e.is_timeout() {
+        LLMErrorKind::Timeout
+    } else {
+        LLMErrorKind::Network
+    };
+    LLMError::new(provider, kind, e.to_string())
+}
+
+/// OpenAI返回毫秒数retry-after-ms，其余供应商返回秒数retry-after。HTTP日期格式的值不处理，按指数退避等待
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v >= 0.0)
+    };
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+/// 第n次重试的等待时间：基础间隔按2的幂增长，再随机取其50%~100%，避免多个请求同时重试
+fn backoff(retry: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    delay.mul_f64(0.5 + (nanos % 1000) as f64 / 2000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::*;
+
+    #[test]
+    fn classifies_by_status_and_message() {
+        assert_eq!(classify(401, ""), LLMErrorKind::Auth);
+        assert_eq!(classify(403, "forbidden"), LLMErrorKind::Auth);
+        assert_eq!(classify(402, ""), LLMErrorKind::Quota);
+        assert_eq!(
+            classify(429, "You exceeded your current quota, check your billing"),
+            LLMErrorKind::Quota
+        );
+        assert_eq!(
+            classify(429, "Quota exceeded for requests per minute"),
+            LLMErrorKind::RateLimited
+        );
+        assert_eq!(classify(404, ""), LLMErrorKind::ModelNotFound);
+        assert_eq!(
+            classify(400, "The model `gpt-x` does not exist"),
+            LLMErrorKind::ModelNotFound
+        );
+        assert_eq!(
+            classify(400, "This model's maximum context length is 8192 tokens"),
+            LLMErrorKind::ContextTooLong
+        );
+        assert_eq!(
+            classify(413, "prompt is too long"),
+            LLMErrorKind::ContextTooLong
+        );
+        assert_eq!(classify(400, "invalid request"), LLMErrorKind::Other);
+        assert_eq!(classify(408, ""), LLMErrorKind::Timeout);
+        assert_eq!(classify(504, ""), LLMErrorKind::Timeout);
+        assert_eq!(classify(500, ""), LLMErrorKind::Server);
+        assert_eq!(classify(529, "Overloaded"), LLMErrorKind::Server);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retryable() {
+        let error = |status| LLMError::from_status("test", status, String::new());
+        assert!(error(429).is_retryable());
+        assert!(error(503).is_retryable());
+        assert!(error(504).is_retryable());
+        assert!(!error(401).is_retryable());
+        assert!(!error(402).is_retryable());
+        assert!(!error(400).is_retryable());
+    }
+
+    #[test]
+    fn reads_retry_after_headers() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut map = HeaderMap::new();
+            for (name, value) in pairs {
+                map.insert(*name, HeaderValue::from_static(value));
+            }
+            map
+        };
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "20")])),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "1.5")])),
+            Some(Duration::from_millis(1500))
+        );
+        // 毫秒数优先
+        assert_eq!(
+            retry_after(&headers(&[
+                ("retry-after-ms", "250"),
+                ("retry-after", "20")
+            ])),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            retry_after(&headers(&[(
+                "retry-after",
+                "Wed, 21 Oct 2015 07:28:00 GMT"
+            )])),
+            None
+        );
+        assert_eq!(retry_after(&headers(&[("retry-after", "-1")])), None);
+        assert_eq!(retry_after(&headers(&[])), None);
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter_and_is_capped() {
+        for retry in 1..=10 {
+            let base = RETRY_BASE_DELAY
+                .saturating_mul(2u32.saturating_pow(retry - 1))
+                .min(MAX_RETRY_DELAY);
+            let delay = backoff(retry);
+            assert!(
+                delay >= base / 2 && delay <= base,
+                "retry {}: {:?}",
+                retry,
+                delay
+            );
+        }
+        assert!(backoff(100) <= MAX_RETRY_DELAY);
+    }
+}
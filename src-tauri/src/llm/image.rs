@@ -0,0 +1,114 @@
+use std::{fs, io::Cursor, path::Path};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat};
+use serde::Deserialize;
+
+use crate::storage::sys_config::get_config;
+
+/// 支持作为资源引用的图片格式
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+/// 图片处理选项，对应sys_config中的image_options（JSON）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImageOptions {
+    /// 图片最长边的像素上限，超出时等比缩小
+    #[serde(rename = "maxDimension")]
+    pub max_dimension: u32,
+    /// 重新编码为JPEG时的质量（1-100）
+    #[serde(rename = "jpegQuality")]
+    pub jpeg_quality: u8,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            max_dimension: 1568,
+            jpeg_quality: 85,
+        }
+    }
+}
+
+pub async fn load_image_options() -> Result<ImageOptions> {
+    match get_config("image_options".to_string()).await? {
+        Some(conf) => {
+            serde_json::from_str(&conf).map_err(|e| anyhow!("图片选项配置格式错误: {}", e))
+        }
+        None => Ok(ImageOptions::default()),
+    }
+}
+
+/// 提交给LLM的图片，data为base64编码后的内容
+#[derive(Debug, Clone)]
+pub struct ImageInput {
+    pub name: String,
+    pub media_type: &'static str,
+    pub data: String,
+}
+
+impl ImageInput {
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+pub fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// 读取图片并按配置缩小尺寸。尺寸未超出限制的PNG/JPEG原样提交，其余统一重新编码：
+/// 带透明通道的编码为PNG，否则编码为JPEG
+pub fn prepare_image(path: &Path, options: &ImageOptions) -> Result<ImageInput> {
+    let format = ImageFormat::from_path(path)
+        .ok()
+        .filter(|f| matches!(f, ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP))
+        .ok_or_else(|| anyhow!("不支持的图片格式，仅支持PNG/JPEG/WebP: {}", path.display()))?;
+    let image_error =
+        |e: &dyn std::fmt::Display| anyhow!("读取图片失败: {}, 错误: {}", path.display(), e);
+    let bytes = fs::read(path).map_err(|e| image_error(&e))?;
+    let image = image::load_from_memory_with_format(&bytes, format).map_err(|e| image_error(&e))?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (width, height) = image.dimensions();
+    let oversized = width.max(height) > options.max_dimension;
+    if !oversized && format != ImageFormat::WebP {
+        return Ok(ImageInput {
+            name,
+            media_type: format.to_mime_type(),
+            data: STANDARD.encode(&bytes),
+        });
+    }
+    let image = if oversized {
+        image.resize(
+            options.max_dimension,
+            options.max_dimension,
+            FilterType::Lanczos3,
+        )
+    } else {
+        image
+    };
+    let (media_type, encoded) = encode(&image, options.jpeg_quality)?;
+    Ok(ImageInput {
+        name,
+        media_type,
+        data: STANDARD.encode(encoded),
+    })
+}
+
+fn encode(image: &DynamicImage, jpeg_quality: u8) -> Result<(&'static str, Vec<u8>)> {
+    let mut buf = Cursor::new(Vec::new());
+    if image.color().has_alpha() {
+        image.write_to(&mut buf, ImageFormat::Png)?;
+        Ok(("image/png", buf.into_inner()))
+    } else {
+        let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, jpeg_quality);
+        image.to_rgb8().write_with_encoder(encoder)?;
+        Ok(("image/jpeg", buf.into_inner()))
+    }
+}
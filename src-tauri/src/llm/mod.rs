@@ -1,5 +1,18 @@
+pub mod anthropic;
+pub mod azure;
 pub mod context_builder;
+pub mod continuation;
+pub mod custom;
+pub mod embedding;
+pub mod file_block;
+pub mod gemini;
+pub mod http;
+pub mod image;
 pub mod openai;
+pub mod redaction;
+mod sse;
+pub mod structured;
+pub mod tools;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
@@ -8,7 +21,7 @@ pub mod agent;
 mod ollama;
 pub mod prompt;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileModifyResult {
     #[serde(rename = "filePath")]
     pub file_path: String,
@@ -17,6 +30,17 @@ pub struct FileModifyResult {
     pub file_content: String,
 }
 
+/// 生成计划中的一个文件，可由用户修改后再逐个生成
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub path: String,
+    /// 文件的用途及需要包含的内容
+    pub purpose: String,
+    /// 该文件依赖的计划中其他文件的路径，依赖的文件先生成
+    #[serde(rename = "dependsOn", default)]
+    pub depends_on: Vec<String>,
+}
+
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq)]
@@ -38,26 +62,3 @@ impl FromStr for Intent {
         }
     }
 }
-
-pub fn extract_json_from_llm_response(response: &str) -> Option<String> {
-    // 查找 JSON 的开始和结束位置
-    let json_start = response.find('[').or_else(|| response.find('{'))?;
-    let json_end = response.rfind(']').or_else(|| response.rfind('}'))?;
-
-    // 提取 JSON 部分
-    let json_str = &response[json_start..=json_end];
-
-    // 验证 JSON 有效性
-    if serde_json::from_str::<serde_json::Value>(json_str).is_ok() {
-        Some(json_str.to_string())
-    } else {
-        // 尝试进一步清理无效字符
-        let cleaned = json_str
-            .chars()
-            .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
-            .collect::<String>();
-        serde_json::from_str::<serde_json::Value>(&cleaned)
-            .ok()
-            .map(|_| cleaned)
-    }
-}
@@ -1,28 +1,338 @@
-use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use rig::{
-    agent::Agent,
-    completion::Prompt,
-    providers::ollama::{self, CompletionModel},
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+
+use super::{
+    agent::{
+        AIAgent, ChatMessage, ChatReply, ChatRole, GenerationParams, StreamCallback,
+        StructuredOutput, TokenUsage, ToolCall, ToolSpec,
+    },
+    http::{next_chunk, send_with_retry, HttpOptions},
+    image::ImageInput,
+    openai::tool_definitions,
+    structured::OutputSchema,
 };
 
-use super::agent::AIAgent;
+const PROVIDER_NAME: &str = "Ollama";
 
+/// 直接调用Ollama /api/chat接口的Agent，流式响应为每行一个JSON对象
 pub struct OllamaAgent {
-    agent: Agent<CompletionModel>,
+    client: reqwest::Client,
+    endpoint: String,
+    model: String,
+    preamble: String,
+    params: GenerationParams,
+    vision: bool,
+    structured_output: StructuredOutput,
+    http: HttpOptions,
+    /// 未配置上下文长度的提示只需给出一次
+    context_length_hinted: AtomicBool,
 }
 
 impl OllamaAgent {
-    pub fn new(base_url: &str, model: &str, preamble: &str) -> Self {
-        let ollama_client = ollama::Client::from_url(base_url);
-        let agent = ollama_client.agent(model).preamble(preamble).build();
-        OllamaAgent { agent }
+    pub fn new(
+        base_url: &str,
+        model: &str,
+        preamble: &str,
+        params: GenerationParams,
+        vision: bool,
+    ) -> Self {
+        OllamaAgent {
+            client: reqwest::Client::new(),
+            endpoint: format!("{}/api/chat", base_url.trim().trim_end_matches('/')),
+            model: model.to_string(),
+            preamble: preamble.to_string(),
+            params,
+            vision,
+            structured_output: StructuredOutput::default(),
+            http: HttpOptions::default(),
+            context_length_hinted: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_structured_output(mut self, structured_output: StructuredOutput) -> Self {
+        self.structured_output = structured_output;
+        self
+    }
+
+    pub fn with_http_options(mut self, http: HttpOptions) -> Self {
+        self.client = http.client();
+        self.http = http;
+        self
+    }
+
+    /// Ollama超出上下文长度时直接截断提示词而不报错，这里按字符数粗略估算并提前警告。
+    /// 未配置上下文长度时使用服务端的默认值，无法得知具体长度，只提示用户配置
+    fn check_context_length(&self, messages: &[ChatMessage]) -> Option<String> {
+        let chars: usize = messages.iter().map(|m| m.content.chars().count()).sum();
+        // 中英文混合的代码大约每3个字符一个token
+        let estimated_tokens = (self.preamble.chars().count() + chars) / 3;
+        let message = match self.params.context_length {
+            Some(num_ctx) if estimated_tokens > num_ctx as usize => format!(
+                "提示词约{}个token，可能超过Ollama的上下文长度{}，超出部分会被截断，请在LLM配置中调大上下文长度",
+                estimated_tokens, num_ctx
+            ),
+            Some(_) => return None,
+            None if !self.context_length_hinted.swap(true, Ordering::Relaxed) => format!(
+                "未配置Ollama的上下文长度，将使用服务端的默认值，提示词约{}个token，超出部分会被直接截断，请在LLM配置中设置上下文长度",
+                estimated_tokens
+            ),
+            None => return None,
+        };
+        warn!("{}", message);
+        Some(message)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        tools: &[ToolSpec],
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let warnings: Vec<String> = self.check_context_length(messages).into_iter().collect();
+        let mut chat_messages = Vec::new();
+        if !self.preamble.is_empty() {
+            chat_messages.push(json!({ "role": "system", "content": self.preamble }));
+        }
+        for message in messages {
+            let role = match message.role {
+                ChatRole::User => "user",
+                ChatRole::Assistant => "assistant",
+                ChatRole::Tool => "tool",
+            };
+            let mut chat_message = json!({ "role": role, "content": message.content });
+            if !message.images.is_empty() {
+                // Ollama直接接收base64编码的图片内容
+                chat_message["images"] =
+                    json!(message.images.iter().map(|i| &i.data).collect::<Vec<_>>());
+            }
+            // Ollama的工具调用没有ID，工具结果通过工具名称对应
+            if !message.tool_calls.is_empty() {
+                chat_message["tool_calls"] = json!(message
+                    .tool_calls
+                    .iter()
+                    .map(|call| json!({
+                        "function": { "name": call.name, "arguments": call.arguments }
+                    }))
+                    .collect::<Vec<_>>());
+            }
+            if let Some(call) = &message.tool_call {
+                chat_message["tool_name"] = json!(call.name);
+            }
+            chat_messages.push(chat_message);
+        }
+        let mut body = json!({
+            "model": self.model,
+            "messages": chat_messages,
+            "options": ollama_options(&self.params),
+            "stream": true,
+        });
+        // format为JSON Schema时按schema约束输出，为"json"时只要求输出合法的JSON
+        if let Some(schema) = schema {
+            match self.structured_output {
+                StructuredOutput::Schema => body["format"] = schema.schema.clone(),
+                StructuredOutput::JsonObject => body["format"] = json!("json"),
+                StructuredOutput::Off => {}
+            }
+        }
+        if !tools.is_empty() {
+            body["tools"] = tool_definitions(tools);
+        }
+        let request = self.client.post(&self.endpoint).json(&body);
+        // 首次调用时需要加载模型，等待响应的时间可能较长
+        let mut response =
+            send_with_retry(PROVIDER_NAME, &self.http, request, error_message).await?;
+
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut last: Value = Value::Null;
+        let read_timeout = self.http.read_timeout();
+        while let Some(chunk) = next_chunk(PROVIDER_NAME, &mut response, read_timeout).await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                if let Some(data) = parse_line(&line)? {
+                    push_content(&data, &mut text, on_delta)?;
+                    push_tool_calls(&data, &mut tool_calls);
+                    last = data;
+                }
+            }
+        }
+        if let Some(data) = parse_line(&buffer)? {
+            push_content(&data, &mut text, on_delta)?;
+            push_tool_calls(&data, &mut tool_calls);
+            last = data;
+        }
+
+        // 最后一行的done为true，包含用量及结束原因
+        let usage = TokenUsage {
+            input_tokens: last["prompt_eval_count"].as_u64(),
+            output_tokens: last["eval_count"].as_u64(),
+            thinking_tokens: None,
+        };
+        info!(
+            "Ollama调用完成，模型：{}，{}，结束原因：{}",
+            self.model,
+            usage.describe().as_deref().unwrap_or("用量未知"),
+            last["done_reason"].as_str().unwrap_or("未知")
+        );
+        let truncated = last["done_reason"].as_str() == Some("length");
+        if truncated {
+            warn!("回复达到最大Token数的限制，内容可能不完整");
+        }
+        Ok(ChatReply {
+            text,
+            truncated,
+            tool_calls,
+            warnings,
+            usage,
+        })
+    }
+}
+
+fn parse_line(line: &[u8]) -> Result<Option<Value>> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let data: Value = serde_json::from_str(line)
+        .map_err(|e| anyhow!("Ollama响应格式错误: {}, 内容: {}", e, line))?;
+    if let Some(error) = data["error"].as_str() {
+        return Err(anyhow!("Ollama返回错误: {}", error));
+    }
+    Ok(Some(data))
+}
+
+fn push_content(data: &Value, text: &mut String, on_delta: &mut StreamCallback<'_>) -> Result<()> {
+    if let Some(content) = data["message"]["content"].as_str() {
+        if !content.is_empty() {
+            text.push_str(content);
+            on_delta(content)?;
+        }
+    }
+    Ok(())
+}
+
+/// 工具调用在一个数据块中完整返回，参数为JSON对象
+fn push_tool_calls(data: &Value, tool_calls: &mut Vec<ToolCall>) {
+    let Some(calls) = data["message"]["tool_calls"].as_array() else {
+        return;
+    };
+    for call in calls {
+        let Some(name) = call["function"]["name"].as_str() else {
+            continue;
+        };
+        tool_calls.push(ToolCall {
+            id: format!("call_{}", tool_calls.len()),
+            name: name.to_string(),
+            arguments: call["function"]["arguments"].clone(),
+            thought_signature: None,
+        });
+    }
+}
+
+/// 错误格式为{"error":"..."}
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+fn ollama_options(params: &GenerationParams) -> Value {
+    let mut options = Map::new();
+    if let Some(max_tokens) = params.max_tokens {
+        options.insert("num_predict".to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = params.temperature {
+        options.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = params.top_p {
+        options.insert("top_p".to_string(), json!(top_p));
     }
+    if let Some(seed) = params.seed {
+        options.insert("seed".to_string(), json!(seed));
+    }
+    let stop = params.stop_sequences();
+    if !stop.is_empty() {
+        options.insert("stop".to_string(), json!(stop));
+    }
+    if let Some(context_length) = params.context_length {
+        options.insert("num_ctx".to_string(), json!(context_length));
+    }
+    Value::Object(options)
 }
 
 #[async_trait]
 impl AIAgent for OllamaAgent {
     async fn generate_raw_response(&self, prompt: &str) -> Result<String> {
-        Ok(self.agent.prompt(prompt).await?)
+        self.chat_stream(
+            &[ChatMessage::user(prompt, &[])],
+            None,
+            &[],
+            &mut |_| Ok(()),
+        )
+        .await
+        .map(|reply| reply.text)
+    }
+
+    fn supports_vision(&self) -> bool {
+        self.vision
+    }
+
+    fn generation_params(&self) -> &GenerationParams {
+        &self.params
+    }
+
+    async fn generate_raw_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        self.chat_stream(&[ChatMessage::user(prompt, images)], None, &[], &mut |_| {
+            Ok(())
+        })
+        .await
+        .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        self.chat_stream(&[ChatMessage::user(prompt, images)], schema, &[], on_delta)
+            .await
+            .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, schema, &[], on_delta).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate_tool_chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, None, tools, &mut |_| Ok(()))
+            .await
     }
 }
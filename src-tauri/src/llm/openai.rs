@@ -1,28 +1,465 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use rig::{
-    agent::Agent,
-    completion::Prompt,
-    providers::openai::{self, CompletionModel},
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+
+use super::{
+    agent::{
+        AIAgent, ChatMessage, ChatReply, ChatRole, GenerationParams, StreamCallback,
+        StructuredOutput, TokenUsage, ToolCall, ToolSpec,
+    },
+    http::{next_chunk, read_text, send_with_retry, HttpOptions},
+    image::ImageInput,
+    sse::SseDecoder,
+    structured::OutputSchema,
 };
 
-use super::agent::AIAgent;
+const PROVIDER_NAME: &str = "OpenAI兼容接口";
 
+/// 直接调用Chat Completions接口的Agent，始终使用流式响应
 pub struct OpenAIAgent {
-    agent: Agent<CompletionModel>,
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    model: String,
+    preamble: String,
+    params: GenerationParams,
+    vision: bool,
+    structured_output: StructuredOutput,
+    http: HttpOptions,
 }
 
 impl OpenAIAgent {
-    pub fn new(base_url: &str, api_key: &str, model: &str, preamble: &str) -> Self {
-        let openai_client = openai::Client::from_url(api_key, base_url);
-        let agent = openai_client.agent(model).preamble(preamble).build();
-        OpenAIAgent { agent }
+    pub fn new(
+        base_url: &str,
+        api_key: &str,
+        model: &str,
+        preamble: &str,
+        params: GenerationParams,
+        vision: bool,
+    ) -> Self {
+        OpenAIAgent {
+            client: reqwest::Client::new(),
+            endpoint: format!("{}/chat/completions", base_url.trim().trim_end_matches('/')),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            preamble: preamble.to_string(),
+            params,
+            vision,
+            structured_output: StructuredOutput::default(),
+            http: HttpOptions::default(),
+        }
+    }
+
+    pub fn with_structured_output(mut self, structured_output: StructuredOutput) -> Self {
+        self.structured_output = structured_output;
+        self
+    }
+
+    pub fn with_http_options(mut self, http: HttpOptions) -> Self {
+        self.client = http.client();
+        self.http = http;
+        self
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        tools: &[ToolSpec],
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        let mut body = chat_completion_params(&self.model, &self.params);
+        body["model"] = json!(self.model);
+        body["messages"] = chat_messages(&self.preamble, messages);
+        if let Some(format) = response_format(schema, self.structured_output) {
+            body["response_format"] = format;
+        }
+        if !tools.is_empty() {
+            body["tools"] = tool_definitions(tools);
+        }
+        body["stream"] = json!(true);
+        body["stream_options"] = json!({ "include_usage": true });
+        let mut request = self.client.post(&self.endpoint).json(&body);
+        // 本地部署的兼容接口通常不需要密钥
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+        let response = send_with_retry(PROVIDER_NAME, &self.http, request, error_message).await?;
+        let result =
+            read_chat_stream(PROVIDER_NAME, response, self.http.read_timeout(), on_delta).await?;
+        result.log_usage(PROVIDER_NAME, &self.model);
+        Ok(result.into_reply())
+    }
+}
+
+/// 流式Chat Completions调用的结果
+#[derive(Debug, Default)]
+pub struct ChatStreamResult {
+    pub text: String,
+    /// stop、length、content_filter等
+    pub finish_reason: Option<String>,
+    pub usage: TokenUsage,
+    /// 工具调用，参数以JSON片段的形式流式返回，按序号拼接
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Debug, Default)]
+struct ToolCallDelta {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+impl ChatStreamResult {
+    pub fn log_usage(&self, provider: &str, model: &str) {
+        info!(
+            "{}调用完成，模型：{}，{}，结束原因：{}",
+            provider,
+            model,
+            self.usage.describe().as_deref().unwrap_or("用量未知"),
+            self.finish_reason.as_deref().unwrap_or("未知")
+        );
+        if self.finish_reason.as_deref() == Some("length") {
+            warn!("回复达到最大Token数的限制，内容可能不完整");
+        }
+    }
+
+    /// 处理一组工具调用片段，非流式响应中的完整调用同样按片段处理
+    fn push_tool_calls(&mut self, calls: &[Value]) {
+        for (position, call) in calls.iter().enumerate() {
+            let index = call["index"].as_u64().map_or(position, |i| i as usize);
+            if self.tool_calls.len() <= index {
+                self.tool_calls.resize_with(index + 1, Default::default);
+            }
+            let delta = &mut self.tool_calls[index];
+            if let Some(id) = call["id"].as_str().filter(|id| !id.is_empty()) {
+                delta.id = id.to_string();
+            }
+            if let Some(name) = call["function"]["name"].as_str().filter(|n| !n.is_empty()) {
+                delta.name = name.to_string();
+            }
+            if let Some(arguments) = call["function"]["arguments"].as_str() {
+                delta.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    pub fn into_reply(self) -> ChatReply {
+        let tool_calls = self
+            .tool_calls
+            .into_iter()
+            .enumerate()
+            .filter(|(_, call)| !call.name.is_empty())
+            .map(|(index, call)| ToolCall {
+                id: if call.id.is_empty() {
+                    format!("call_{}", index)
+                } else {
+                    call.id
+                },
+                name: call.name,
+                arguments: ToolCall::parse_arguments(&call.arguments),
+                thought_signature: None,
+            })
+            .collect();
+        ChatReply {
+            truncated: self.finish_reason.as_deref() == Some("length"),
+            text: self.text,
+            tool_calls,
+            usage: self.usage,
+            ..Default::default()
+        }
+    }
+}
+
+/// 推理模型的思考用量在completion_tokens_details.reasoning_tokens中，同时也计入completion_tokens
+fn parse_usage(usage: &Value) -> TokenUsage {
+    TokenUsage {
+        input_tokens: usage["prompt_tokens"].as_u64(),
+        output_tokens: usage["completion_tokens"].as_u64(),
+        thinking_tokens: usage["completion_tokens_details"]["reasoning_tokens"]
+            .as_u64()
+            .filter(|tokens| *tokens > 0),
+    }
+}
+
+/// 读取Chat Completions的流式响应。不支持流式输出的兼容接口会直接返回完整的JSON，此时一次性回调
+pub async fn read_chat_stream(
+    provider: &str,
+    mut response: reqwest::Response,
+    read_timeout: Duration,
+    on_delta: &mut StreamCallback<'_>,
+) -> Result<ChatStreamResult> {
+    let mut result = ChatStreamResult::default();
+    let is_json = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if is_json {
+        let text = read_text(provider, response, read_timeout).await?;
+        let data: Value =
+            serde_json::from_str(&text).map_err(|e| anyhow!("LLM响应格式错误: {}", e))?;
+        let choice = &data["choices"][0];
+        result.text = choice["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        result.finish_reason = choice["finish_reason"].as_str().map(str::to_string);
+        if let Some(calls) = choice["message"]["tool_calls"].as_array() {
+            result.push_tool_calls(calls);
+        }
+        result.usage = parse_usage(&data["usage"]);
+        on_delta(&result.text)?;
+    } else {
+        let mut decoder = SseDecoder::new();
+        'read: while let Some(chunk) = next_chunk(provider, &mut response, read_timeout).await? {
+            for event in decoder.feed(&chunk) {
+                if !handle_chunk(&event.data, &mut result, on_delta)? {
+                    break 'read;
+                }
+            }
+        }
+        if let Some(event) = decoder.finish() {
+            handle_chunk(&event.data, &mut result, on_delta)?;
+        }
+    }
+    if result.finish_reason.as_deref() == Some("content_filter") {
+        return Err(anyhow!("回复被内容过滤策略拦截"));
+    }
+    Ok(result)
+}
+
+/// 处理一个流式数据块，收到[DONE]时返回false
+fn handle_chunk(
+    data: &str,
+    result: &mut ChatStreamResult,
+    on_delta: &mut StreamCallback<'_>,
+) -> Result<bool> {
+    if data.is_empty() {
+        return Ok(true);
+    }
+    if data == "[DONE]" {
+        return Ok(false);
+    }
+    let chunk: Value = serde_json::from_str(data)
+        .map_err(|e| anyhow!("LLM响应格式错误: {}, 内容: {}", e, data))?;
+    if chunk.get("error").is_some() {
+        return Err(anyhow!("LLM返回错误: {}", error_message(data)));
+    }
+    // 开启include_usage后，最后一个数据块只包含用量，choices为空
+    if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+        result.usage = parse_usage(usage);
     }
+    let choice = &chunk["choices"][0];
+    // 推理模型的思考内容在reasoning_content中，不属于回复正文
+    if let Some(content) = choice["delta"]["content"].as_str() {
+        if !content.is_empty() {
+            result.text.push_str(content);
+            on_delta(content)?;
+        }
+    }
+    if let Some(calls) = choice["delta"]["tool_calls"].as_array() {
+        result.push_tool_calls(calls);
+    }
+    if let Some(reason) = choice["finish_reason"].as_str() {
+        result.finish_reason = Some(reason.to_string());
+    }
+    Ok(true)
+}
+
+/// 错误格式为{"error":{"message":...,"type":...,"code":...}}
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// 系统提示词及对话消息
+pub fn chat_messages(preamble: &str, messages: &[ChatMessage]) -> Value {
+    let mut result = Vec::new();
+    if !preamble.is_empty() {
+        result.push(json!({ "role": "system", "content": preamble }));
+    }
+    for message in messages {
+        result.push(match message.role {
+            ChatRole::User => json!({
+                "role": "user",
+                "content": user_content(&message.content, &message.images)
+            }),
+            ChatRole::Assistant if message.tool_calls.is_empty() => {
+                json!({ "role": "assistant", "content": message.content })
+            }
+            ChatRole::Assistant => json!({
+                "role": "assistant",
+                "content": message.content,
+                "tool_calls": message.tool_calls.iter().map(|call| json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": call.arguments_text() }
+                })).collect::<Vec<_>>()
+            }),
+            ChatRole::Tool => json!({
+                "role": "tool",
+                "tool_call_id": message.tool_call.as_ref().map(|call| call.id.as_str()),
+                "content": message.content
+            }),
+        });
+    }
+    Value::Array(result)
+}
+
+/// 工具定义，Ollama使用相同的格式
+pub fn tool_definitions(tools: &[ToolSpec]) -> Value {
+    tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters
+                }
+            })
+        })
+        .collect()
+}
+
+/// 用户消息内容，图片以data URL的形式传递
+pub fn user_content(prompt: &str, images: &[ImageInput]) -> Value {
+    if images.is_empty() {
+        return json!(prompt);
+    }
+    let mut content = vec![json!({ "type": "text", "text": prompt })];
+    for image in images {
+        content.push(json!({
+            "type": "image_url",
+            "image_url": { "url": image.data_url() }
+        }));
+    }
+    Value::Array(content)
+}
+
+/// 结构化输出的response_format，strict模式要求schema中的对象都声明additionalProperties为false
+pub fn response_format(
+    schema: Option<&OutputSchema>,
+    structured_output: StructuredOutput,
+) -> Option<Value> {
+    let schema = schema?;
+    match structured_output {
+        StructuredOutput::Schema => Some(json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": schema.name,
+                "description": schema.description,
+                "schema": schema.schema,
+                "strict": true
+            }
+        })),
+        StructuredOutput::JsonObject => Some(json!({ "type": "json_object" })),
+        StructuredOutput::Off => None,
+    }
+}
+
+/// Chat Completions接口的生成参数。推理模型（o系列、gpt-5）不接受max_tokens，需要使用max_completion_tokens
+pub fn chat_completion_params(model: &str, params: &GenerationParams) -> Value {
+    let mut body = Map::new();
+    if let Some(max_tokens) = params.max_tokens {
+        let model = model.to_lowercase();
+        let key = if ["o1", "o3", "o4", "gpt-5"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            "max_completion_tokens"
+        } else {
+            "max_tokens"
+        };
+        body.insert(key.to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = params.temperature {
+        body.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = params.top_p {
+        body.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(seed) = params.seed {
+        body.insert("seed".to_string(), json!(seed));
+    }
+    let stop = params.stop_sequences();
+    if !stop.is_empty() {
+        body.insert("stop".to_string(), json!(stop));
+    }
+    Value::Object(body)
 }
 
 #[async_trait]
 impl AIAgent for OpenAIAgent {
     async fn generate_raw_response(&self, prompt: &str) -> Result<String> {
-        Ok(self.agent.prompt(prompt).await?)
+        self.chat_stream(
+            &[ChatMessage::user(prompt, &[])],
+            None,
+            &[],
+            &mut |_| Ok(()),
+        )
+        .await
+        .map(|reply| reply.text)
+    }
+
+    fn supports_vision(&self) -> bool {
+        self.vision
+    }
+
+    fn generation_params(&self) -> &GenerationParams {
+        &self.params
+    }
+
+    async fn generate_raw_response_with_images(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+    ) -> Result<String> {
+        self.chat_stream(&[ChatMessage::user(prompt, images)], None, &[], &mut |_| {
+            Ok(())
+        })
+        .await
+        .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_stream(
+        &self,
+        prompt: &str,
+        images: &[ImageInput],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<String> {
+        self.chat_stream(&[ChatMessage::user(prompt, images)], schema, &[], on_delta)
+            .await
+            .map(|reply| reply.text)
+    }
+
+    async fn generate_raw_chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        schema: Option<&OutputSchema>,
+        on_delta: &mut StreamCallback<'_>,
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, schema, &[], on_delta).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn generate_tool_chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, None, tools, &mut |_| Ok(()))
+            .await
     }
 }
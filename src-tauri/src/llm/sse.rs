@@ -0,0 +1,63 @@
+/// Server-Sent Events中的一条事件
+#[derive(Debug, Default)]
+pub struct SseEvent {
+    /// event字段，未指定时为空
+    pub event: String,
+    /// 多行data字段以换行连接
+    pub data: String,
+}
+
+/// 增量解析SSE响应体。网络分块可能在任意位置截断（包括多字节字符的中间），
+/// 因此按字节缓存，直到遇到空行才解析出完整的事件
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一段响应数据，返回其中已完整的事件
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend(chunk.iter().filter(|b| **b != b'\r'));
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+            if let Some(event) = parse_event(&String::from_utf8_lossy(&block)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// 响应结束时解析缓冲区中剩余的内容（最后一个事件后可能没有空行）
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let block = std::mem::take(&mut self.buffer);
+        parse_event(&String::from_utf8_lossy(&block))
+    }
+}
+
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = SseEvent::default();
+    let mut data_lines = Vec::new();
+    for line in block.lines() {
+        // 以冒号开头的是注释，常用于保持连接
+        if line.is_empty() || line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event.event = value.to_string(),
+            "data" => data_lines.push(value),
+            _ => {}
+        }
+    }
+    if event.event.is_empty() && data_lines.is_empty() {
+        return None;
+    }
+    event.data = data_lines.join("\n");
+    Some(event)
+}
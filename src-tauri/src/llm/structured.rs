@@ -0,0 +1,420 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::{
+    de::{DeserializeOwned, Error},
+    Deserialize, Deserializer,
+};
+use serde_json::{json, Value};
+
+/// 要求模型按JSON Schema返回的结构化结果。OpenAI的结构化输出及Anthropic的工具参数都要求根节点为对象，
+/// 因此列表统一包装在files字段中
+#[derive(Debug, Clone)]
+pub struct OutputSchema {
+    /// 只能包含字母、数字、下划线及短横线
+    pub name: &'static str,
+    pub description: &'static str,
+    pub schema: Value,
+}
+
+impl OutputSchema {
+    /// 生成或修改的文件列表，对应FileModifyResult
+    pub fn file_modify_results() -> Self {
+        Self {
+            name: "file_modify_results",
+            description: "需要生成或修改的文件及其完整内容",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "files": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "filePath": { "type": "string" },
+                                "fileContent": { "type": "string" }
+                            },
+                            "required": ["filePath", "fileContent"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["files"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    /// 需要生成或修改的文件路径列表
+    pub fn target_files() -> Self {
+        Self {
+            name: "target_files",
+            description: "需要生成或修改的文件路径",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "files": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["files"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    /// 生成计划，对应PlannedFile
+    pub fn file_plan() -> Self {
+        Self {
+            name: "file_plan",
+            description: "需要生成或修改的文件、用途及依赖关系",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "files": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "path": { "type": "string" },
+                                "purpose": { "type": "string" },
+                                "dependsOn": { "type": "array", "items": { "type": "string" } }
+                            },
+                            "required": ["path", "purpose", "dependsOn"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["files"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    /// Gemini的responseSchema是OpenAPI Schema的子集，不支持additionalProperties
+    pub fn openapi_schema(&self) -> Value {
+        to_openapi_schema(&self.schema)
+    }
+}
+
+/// 去除JSON Schema中OpenAPI Schema不支持的additionalProperties，用于Gemini的responseSchema及工具参数
+pub fn to_openapi_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(k, _)| k.as_str() != "additionalProperties")
+                .map(|(k, v)| (k.clone(), to_openapi_schema(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(to_openapi_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+/// 文件列表，兼容结构化输出的{"files":[...]}及直接返回的数组
+#[derive(Debug)]
+pub struct FileList<T>(Vec<T>);
+
+impl<T> FileList<T> {
+    pub fn into_files(self) -> Vec<T> {
+        self.0
+    }
+}
+
+// 不使用untagged枚举，以便解析失败时保留具体的错误原因（如缺少fileContent字段），重试时反馈给模型
+impl<'de, T: DeserializeOwned> Deserialize<'de> for FileList<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        let files = if value.get("files").is_some() {
+            value["files"].take()
+        } else {
+            value
+        };
+        Vec::<T>::deserialize(files)
+            .map(FileList)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// 从LLM回复中解析出指定类型的JSON。依次尝试回复中每个括号配对完整的顶层JSON值，
+/// 回复中夹杂说明文字或代码块标记时也能找到正确的内容。错误信息不包含回复内容，需要时由调用方记录
+pub fn parse_llm_json<T: DeserializeOwned>(response: &str) -> Result<T> {
+    let candidates = extract_json_values(response);
+    if candidates.is_empty() {
+        return Err(anyhow!(
+            "LLM响应中没有找到完整的JSON，回复可能不完整或没有按JSON格式输出"
+        ));
+    }
+    let mut first_error = None;
+    for candidate in &candidates {
+        match serde_json::from_str::<T>(&candidate.text) {
+            Ok(value) => {
+                if candidate.repairs > 0 {
+                    info!(
+                        "LLM返回的JSON存在{}处格式错误，已在本地修复",
+                        candidate.repairs
+                    );
+                }
+                return Ok(value);
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(anyhow!("LLM返回数据格式错误: {}", first_error.unwrap()))
+}
+
+/// 回复中的一个JSON值，已修复常见的格式错误
+#[derive(Debug)]
+pub struct RepairedJson {
+    pub text: String,
+    /// 修复的格式错误数量，为0时即为原始内容
+    pub repairs: usize,
+}
+
+/// 找出回复中所有括号配对完整且为合法JSON的顶层对象或数组。字符串中的括号不参与配对；
+/// 字符串中未转义的控制字符、无效的转义序列及多余的结尾逗号会在校验前修复
+pub fn extract_json_values(response: &str) -> Vec<RepairedJson> {
+    scan_candidates(response)
+        .into_iter()
+        .filter_map(|candidate| match candidate {
+            Candidate::Complete(repaired) => serde_json::from_str::<Value>(&repaired.text)
+                .is_ok()
+                .then_some(repaired),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 回复中没有完整的JSON，且最后一个顶层JSON值直到结尾仍未闭合，即输出在JSON中间被截断
+pub fn is_unterminated_json(response: &str) -> bool {
+    let candidates = scan_candidates(response);
+    let has_value = candidates.iter().any(|candidate| match candidate {
+        Candidate::Complete(repaired) => serde_json::from_str::<Value>(&repaired.text).is_ok(),
+        _ => false,
+    });
+    !has_value && matches!(candidates.last(), Some(Candidate::Open))
+}
+
+/// 回复中从左括号开始的一段内容
+enum Candidate {
+    /// 括号配对完整，不一定是合法的JSON
+    Complete(RepairedJson),
+    /// 括号不匹配
+    Broken,
+    /// 直到回复结尾仍未闭合
+    Open,
+}
+
+/// 依次扫描回复中的顶层括号。一段内容扫描结束后（无论是否为合法的JSON）从其结尾继续查找，
+/// 不在其中重新查找，避免把字符串中的代码片段（如fileContent中的“[]”）当作顶层JSON，
+/// 同时每个字符只扫描一次
+fn scan_candidates(response: &str) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = response[offset..].find(['{', '[']).map(|p| p + offset) {
+        let mut scanner = JsonScanner::default();
+        let mut complete = false;
+        offset = response.len();
+        for (i, c) in response[start..].char_indices() {
+            complete = scanner.push(c);
+            if complete || scanner.broken {
+                offset = start + i + c.len_utf8();
+                break;
+            }
+        }
+        candidates.push(match (complete, scanner.broken) {
+            (true, _) => Candidate::Complete(scanner.into_repaired()),
+            (false, true) => Candidate::Broken,
+            (false, false) => Candidate::Open,
+        });
+    }
+    candidates
+}
+
+/// 逐字符扫描JSON，跟踪括号嵌套及字符串状态，可以在流式输出的过程中增量使用
+#[derive(Default)]
+pub struct JsonScanner {
+    /// 尚未闭合的括号
+    stack: Vec<char>,
+    in_string: bool,
+    escaped: bool,
+    /// 已扫描并修复的内容
+    repaired: String,
+    repairs: usize,
+    /// 最近一个逗号在repaired中的位置，其后紧跟右括号时为多余的结尾逗号
+    pending_comma: Option<usize>,
+    /// 括号不匹配，不可能是合法的JSON
+    broken: bool,
+}
+
+impl JsonScanner {
+    /// 追加一个字符，顶层的值完整结束时返回true
+    pub fn push(&mut self, c: char) -> bool {
+        if self.broken {
+            return false;
+        }
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+                // 模型常把正则表达式、Windows路径中的反斜杠原样输出，如"\d"，补全为"\\d"
+                if !matches!(c, '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' | 'u') {
+                    self.repaired.push('\\');
+                    self.repairs += 1;
+                }
+            } else if c == '\\' {
+                self.escaped = true;
+            } else if c == '"' {
+                self.in_string = false;
+            } else if c.is_control() {
+                self.repairs += 1;
+                match c {
+                    '\n' => self.repaired.push_str("\\n"),
+                    '\r' => self.repaired.push_str("\\r"),
+                    '\t' => self.repaired.push_str("\\t"),
+                    c => self.repaired.push_str(&format!("\\u{:04x}", c as u32)),
+                }
+                return false;
+            }
+            self.repaired.push(c);
+            return false;
+        }
+        if c.is_whitespace() {
+            self.repaired.push(c);
+            return false;
+        }
+        if let Some(pos) = self.pending_comma.take() {
+            if c == '}' || c == ']' {
+                self.repaired.remove(pos);
+                self.repairs += 1;
+            }
+        }
+        if c == ',' {
+            self.pending_comma = Some(self.repaired.len());
+        }
+        self.repaired.push(c);
+        match c {
+            '"' => self.in_string = true,
+            '{' | '[' => self.stack.push(c),
+            '}' | ']' => {
+                let open = if c == '}' { '{' } else { '[' };
+                if self.stack.pop() != Some(open) {
+                    self.broken = true;
+                    return false;
+                }
+                return self.stack.is_empty();
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// 已扫描的内容是一个尚未结束的JSON值的开头
+    pub fn is_open(&self) -> bool {
+        !self.broken && !self.stack.is_empty()
+    }
+
+    pub fn into_repaired(self) -> RepairedJson {
+        RepairedJson {
+            text: self.repaired,
+            repairs: self.repairs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::FileModifyResult;
+
+    fn texts(response: &str) -> Vec<String> {
+        extract_json_values(response)
+            .into_iter()
+            .map(|v| v.text)
+            .collect()
+    }
+
+    #[test]
+    fn extracts_json_surrounded_by_prose() {
+        let response =
+            "Sure [see below]:\n```json\n{\"a\": [1, {\"b\": \"}]{\"}]}\n```\nDone {not json}.";
+        let values = extract_json_values(response);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].text, "{\"a\": [1, {\"b\": \"}]{\"}]}");
+        assert_eq!(values[0].repairs, 0);
+    }
+
+    #[test]
+    fn repairs_invalid_escapes_and_control_characters() {
+        let response = "{\"regex\": \"\\d+\\.\\w\", \"text\": \"line1\nline2\tend\"}";
+        let values = extract_json_values(response);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].repairs, 5);
+        let value: Value = serde_json::from_str(&values[0].text).unwrap();
+        assert_eq!(value["regex"], "\\d+\\.\\w");
+        assert_eq!(value["text"], "line1\nline2\tend");
+    }
+
+    #[test]
+    fn keeps_valid_escapes() {
+        let response = r#"{"path": "C:\\temp\\a.txt", "quote": "say \"hi\"\n", "u": "\u00e9"}"#;
+        let values = extract_json_values(response);
+        assert_eq!(values[0].repairs, 0);
+        assert_eq!(values[0].text, response);
+    }
+
+    #[test]
+    fn removes_trailing_commas() {
+        let values = extract_json_values("[{\"a\": 1, \"b\": [1, 2,],},\n]");
+        assert_eq!(values[0].repairs, 3);
+        assert_eq!(
+            serde_json::from_str::<Value>(&values[0].text).unwrap(),
+            json!([{"a": 1, "b": [1, 2]}])
+        );
+        // 字符串中的逗号不受影响
+        assert_eq!(texts("{\"a\": \",}\"}"), vec!["{\"a\": \",}\"}"]);
+    }
+
+    #[test]
+    fn first_valid_candidate_wins() {
+        let response = "Plan: {\"step\": 1}\n\
+                        {\"files\": [{\"filePath\": \"a.rs\", \"fileContent\": \"A\"}]}\n\
+                        [{\"filePath\": \"b.rs\", \"fileContent\": \"B\"}]";
+        assert_eq!(texts(response).len(), 3);
+        let files = parse_llm_json::<FileList<FileModifyResult>>(response)
+            .unwrap()
+            .into_files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file_path, "a.rs");
+    }
+
+    #[test]
+    fn reports_first_error_when_no_candidate_matches() {
+        let err =
+            parse_llm_json::<FileList<FileModifyResult>>("{\"files\": [{\"filePath\": \"a.rs\"}]}")
+                .unwrap_err();
+        assert!(err.to_string().contains("fileContent"));
+        assert!(parse_llm_json::<Value>("no json here").is_err());
+    }
+
+    #[test]
+    fn unescaped_quote_does_not_expose_code_fragments() {
+        let response =
+            r#"{"files":[{"filePath":"a.js","fileContent":"const s = "x"; const list = [];"}]}"#;
+        assert!(extract_json_values(response).is_empty());
+        assert!(parse_llm_json::<FileList<FileModifyResult>>(response).is_err());
+        assert!(!is_unterminated_json(response));
+    }
+
+    #[test]
+    fn detects_unterminated_json() {
+        assert!(is_unterminated_json(
+            "Here you go:\n{\"files\": [{\"filePath\": \"a.rs\", \"fileContent\": \"fn a() {"
+        ));
+        assert!(is_unterminated_json("[1, 2, [3"));
+        assert!(!is_unterminated_json("{\"a\": 1}"));
+        assert!(!is_unterminated_json("no json here"));
+        // 括号不匹配是格式错误，不是截断
+        assert!(!is_unterminated_json("{\"a\": [1}"));
+        // 说明文字中的括号之后出现被截断的JSON
+        assert!(is_unterminated_json("Files [see below]:\n{\"files\": ["));
+    }
+}
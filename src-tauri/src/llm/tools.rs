@@ -0,0 +1,367 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::{
+    code_index::{self, is_indexable},
+    db::{get_table_schema, get_tables},
+    function::{
+        file::{ignore_aware_walker, is_ignored_path, load_ignore_patterns},
+        file_tree::list_dir_children,
+        outline::{extract_outline, is_supported},
+    },
+    llm::agent::{ToolCall, ToolSpec},
+    storage::{
+        code_sample::get_all_samples,
+        datasource::{get_all_ds, DataSource},
+    },
+};
+
+/// 单次工具结果的最大字符数，超出部分截断，避免个别大文件占满上下文
+const MAX_RESULT_CHARS: usize = 20_000;
+/// list_dir返回的最大条目数
+const MAX_DIR_ENTRIES: usize = 200;
+/// search_code返回的最大结果数
+const MAX_SEARCH_HITS: usize = 20;
+/// 日志中工具参数值的最大显示长度
+const MAX_ARG_DISPLAY: usize = 60;
+
+/// 代码生成前供模型按需获取上下文的工具，路径均限定在项目目录内，且与目录树一样遵循忽略规则
+pub struct ContextTools {
+    /// 代码索引中记录的项目目录
+    source_dir: String,
+    /// 规范化后的项目目录，用于校验路径
+    root: PathBuf,
+    excludes: Vec<String>,
+}
+
+impl ContextTools {
+    pub async fn new(root: &str) -> Result<Self> {
+        let canonical = fs::canonicalize(root)
+            .map_err(|e| anyhow!("无法访问项目目录: {}, 错误: {}", root, e))?;
+        Ok(Self {
+            source_dir: root.to_string(),
+            root: canonical,
+            excludes: load_ignore_patterns().await?,
+        })
+    }
+
+    pub fn specs() -> Vec<ToolSpec> {
+        vec![
+            ToolSpec {
+                name: "list_datasources",
+                description: "List the configured database data sources.",
+                parameters: json!({ "type": "object", "properties": {} }),
+            },
+            ToolSpec {
+                name: "list_tables",
+                description: "List the tables of a data source.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "datasource": { "type": "string", "description": "Name or id of the data source" }
+                    },
+                    "required": ["datasource"]
+                }),
+            },
+            ToolSpec {
+                name: "get_table_schema",
+                description: "Get the schema (columns, types, keys and comments) of a table.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "datasource": { "type": "string", "description": "Name or id of the data source" },
+                        "table": { "type": "string", "description": "Table name" }
+                    },
+                    "required": ["datasource", "table"]
+                }),
+            },
+            ToolSpec {
+                name: "list_dir",
+                description: "List the direct children of a directory in the project. Directories end with \"/\".",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Directory path relative to the project root, empty for the root" }
+                    }
+                }),
+            },
+            ToolSpec {
+                name: "read_file",
+                description: "Read a source file in the project, either in full or as an outline of declarations and signatures.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "File path relative to the project root" },
+                        "mode": { "type": "string", "enum": ["full", "outline"], "description": "Defaults to full" }
+                    },
+                    "required": ["path"]
+                }),
+            },
+            ToolSpec {
+                name: "search_code",
+                description: "Search the project for code related to a query. Uses the semantic code index when available, otherwise a plain text search.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "Natural language description or identifier to search for" }
+                    },
+                    "required": ["query"]
+                }),
+            },
+            ToolSpec {
+                name: "get_rule",
+                description: "Get a coding rule or code sample configured by the user. Call with an empty name to list the available rules.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "description": "Name of the rule" }
+                    }
+                }),
+            },
+        ]
+    }
+
+    /// 执行工具调用，结果超出长度上限时截断
+    pub async fn execute(&self, call: &ToolCall) -> Result<String> {
+        let result = match call.name.as_str() {
+            "list_datasources" => self.list_datasources().await,
+            "list_tables" => {
+                let ds = find_datasource(string_arg(call, "datasource")?).await?;
+                Ok(get_tables(ds).await?.join("\n"))
+            }
+            "get_table_schema" => {
+                let ds = find_datasource(string_arg(call, "datasource")?).await?;
+                get_table_schema(ds, string_arg(call, "table")?.to_string()).await
+            }
+            "list_dir" => self.list_dir(optional_arg(call, "path")),
+            "read_file" => self.read_file(string_arg(call, "path")?, optional_arg(call, "mode")),
+            "search_code" => self.search_code(string_arg(call, "query")?).await,
+            "get_rule" => get_rule(optional_arg(call, "name")).await,
+            name => Err(anyhow!("未知的工具: {}", name)),
+        }?;
+        Ok(truncate_result(result))
+    }
+
+    async fn list_datasources(&self) -> Result<String> {
+        let list: Vec<String> = get_all_ds()
+            .await?
+            .into_iter()
+            .map(|ds| {
+                format!(
+                    "{} (id: {}, {}, 数据库: {})",
+                    ds.name, ds.id, ds.db_type, ds.database
+                )
+            })
+            .collect();
+        if list.is_empty() {
+            return Ok("未配置任何数据源".to_string());
+        }
+        Ok(list.join("\n"))
+    }
+
+    fn list_dir(&self, path: &str) -> Result<String> {
+        let dir = self.resolve(path)?;
+        if is_ignored_path(&self.root, &dir, &self.excludes)? {
+            return Err(anyhow!("该目录已被忽略规则排除，不允许查看: {}", path));
+        }
+        let page = list_dir_children(&self.root, &dir, &self.excludes, 0, MAX_DIR_ENTRIES)?;
+        let mut lines: Vec<String> = page
+            .items
+            .iter()
+            .map(|node| match node.is_folder {
+                true => format!("{}/", node.label),
+                false => node.label.clone(),
+            })
+            .collect();
+        if page.has_more {
+            lines.push(format!(
+                "……共{}项，只列出前{}项",
+                page.total, MAX_DIR_ENTRIES
+            ));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn read_file(&self, path: &str, mode: &str) -> Result<String> {
+        let file = self.resolve(path)?;
+        if is_ignored_path(&self.root, &file, &self.excludes)? {
+            return Err(anyhow!("该文件已被忽略规则排除，不允许读取: {}", path));
+        }
+        if !file.is_file() {
+            return Err(anyhow!("文件不存在: {}", path));
+        }
+        let content = fs::read_to_string(&file)?;
+        if mode == "outline" && is_supported(&file) {
+            return extract_outline(&file, &content);
+        }
+        Ok(content)
+    }
+
+    /// 已建立代码索引时按语义检索，否则逐行查找包含关键字的代码
+    async fn search_code(&self, query: &str) -> Result<String> {
+        match code_index::search(&self.source_dir, query, code_index::DEFAULT_TOP_K).await {
+            Ok(hits) => {
+                let sections: Vec<String> = hits
+                    .into_iter()
+                    .map(|hit| {
+                        format!(
+                            "{}（第{}-{}行）\n```\n{}\n```",
+                            self.relative(Path::new(&hit.file_path)),
+                            hit.start_line,
+                            hit.end_line,
+                            hit.content
+                        )
+                    })
+                    .collect();
+                return Ok(sections.join("\n"));
+            }
+            Err(e) => debug!("代码索引不可用，改为按关键字查找: {}", e),
+        }
+        let keyword = query.trim().to_lowercase();
+        if keyword.is_empty() {
+            return Err(anyhow!("查找内容不能为空"));
+        }
+        let mut lines = Vec::new();
+        for entry in ignore_aware_walker(&self.root, &self.excludes)?
+            .build()
+            .flatten()
+        {
+            let path = entry.path();
+            if !is_indexable(path) {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(path) else {
+                continue;
+            };
+            for (number, line) in content.lines().enumerate() {
+                if line.to_lowercase().contains(&keyword) {
+                    lines.push(format!(
+                        "{}:{}: {}",
+                        self.relative(path),
+                        number + 1,
+                        line.trim()
+                    ));
+                    if lines.len() >= MAX_SEARCH_HITS {
+                        return Ok(lines.join("\n"));
+                    }
+                }
+            }
+        }
+        if lines.is_empty() {
+            return Ok(format!("未找到包含\"{}\"的代码", query.trim()));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// 将相对于项目目录的路径转换为绝对路径，拒绝访问项目目录之外的路径
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let path = path.trim().trim_start_matches(['/', '\\']);
+        let full =
+            fs::canonicalize(self.root.join(path)).map_err(|_| anyhow!("路径不存在: {}", path))?;
+        if !full.starts_with(&self.root) {
+            return Err(anyhow!("不允许访问项目目录之外的路径: {}", path));
+        }
+        Ok(full)
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .or_else(|_| path.strip_prefix(&self.source_dir))
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+}
+
+/// 日志中展示的工具调用，如read_file(path=src/main.rs, mode=outline)
+pub fn describe_call(call: &ToolCall) -> String {
+    let args: Vec<String> = match &call.arguments {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                let display: String = value.chars().take(MAX_ARG_DISPLAY).collect();
+                match display.len() < value.len() {
+                    true => format!("{}={}…", key, display),
+                    false => format!("{}={}", key, display),
+                }
+            })
+            .collect(),
+        Value::Null => Vec::new(),
+        other => vec![other.to_string()],
+    };
+    format!("{}({})", call.name, args.join(", "))
+}
+
+fn string_arg<'a>(call: &'a ToolCall, key: &str) -> Result<&'a str> {
+    call.arguments
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| anyhow!("缺少参数: {}", key))
+}
+
+fn optional_arg<'a>(call: &'a ToolCall, key: &str) -> &'a str {
+    call.arguments
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+/// 按ID或名称（不区分大小写）查找数据源
+async fn find_datasource(name: &str) -> Result<DataSource> {
+    let all = get_all_ds().await?;
+    let names: Vec<String> = all.iter().map(|ds| ds.name.clone()).collect();
+    all.into_iter()
+        .find(|ds| ds.id == name || ds.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("数据源不存在: {}，可用的数据源：{}", name, names.join(", ")))
+}
+
+/// 规则即用户配置的代码示例，未找到时返回可用的规则名称
+async fn get_rule(name: &str) -> Result<String> {
+    let samples = get_all_samples().await?;
+    let name = name.trim();
+    if !name.is_empty() {
+        let found = samples
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+            .or_else(|| {
+                let name = name.to_lowercase();
+                samples
+                    .iter()
+                    .find(|s| s.name.to_lowercase().contains(&name))
+            });
+        if let Some(sample) = found {
+            return Ok(format!("{}\n```\n{}\n```", sample.name, sample.content));
+        }
+    }
+    if samples.is_empty() {
+        return Ok("未配置任何规则".to_string());
+    }
+    let names: Vec<&str> = samples.iter().map(|s| s.name.as_str()).collect();
+    let prefix = match name.is_empty() {
+        true => String::new(),
+        false => format!("规则不存在: {}，", name),
+    };
+    Ok(format!("{}可用的规则：{}", prefix, names.join(", ")))
+}
+
+fn truncate_result(result: String) -> String {
+    match result.char_indices().nth(MAX_RESULT_CHARS) {
+        Some((end, _)) => format!(
+            "{}\n……内容过长，已截断（共{}个字符）",
+            &result[..end],
+            result.chars().count()
+        ),
+        None => result,
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Row};
+use uuid::Uuid;
+
+use super::{DataServiceError, DB_POOL};
+use crate::llm::FileModifyResult;
+
+// 对话会话，时间为秒级时间戳
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+    /// 会话中已完成的对话轮数
+    #[serde(rename = "turnCount")]
+    pub turn_count: i64,
+}
+
+// 会话中的一轮对话，files为本轮LLM输出的文件，内容已还原脱敏信息
+#[derive(Debug, Serialize)]
+pub struct ChatTurn {
+    pub id: String,
+    pub seq: i64,
+    pub question: String,
+    /// 本轮提交给LLM的完整上下文，仅作记录，继续会话时不再提交
+    #[serde(skip_serializing)]
+    pub context: String,
+    pub files: Vec<FileModifyResult>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+#[tauri::command]
+// 创建会话
+pub async fn create_chat_session(name: String) -> Result<ChatSession, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO chat_session (id, name, created_at, updated_at)
+           VALUES ($1, $2, strftime('%s', 'now'), strftime('%s', 'now'))"#,
+    )
+    .bind(&id)
+    .bind(&name)
+    .execute(pool)
+    .await?;
+    get_chat_session(&id).await
+}
+
+// 根据ID获取会话
+pub async fn get_chat_session(id: &str) -> Result<ChatSession, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    sqlx::query_as::<_, ChatSession>(
+        r#"SELECT s.*, (SELECT COUNT(*) FROM chat_turn t WHERE t.session_id = s.id) AS turn_count
+           FROM chat_session s WHERE s.id = $1"#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(DataServiceError::NotFound)
+}
+
+#[tauri::command]
+// 获取全部会话，最近更新的在前
+pub async fn list_chat_sessions() -> Result<Vec<ChatSession>, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let list = sqlx::query_as::<_, ChatSession>(
+        r#"SELECT s.*, (SELECT COUNT(*) FROM chat_turn t WHERE t.session_id = s.id) AS turn_count
+           FROM chat_session s ORDER BY s.updated_at DESC"#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(list)
+}
+
+#[tauri::command]
+// 重命名会话
+pub async fn rename_chat_session(id: String, name: String) -> Result<bool, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let rows_affected = sqlx::query("UPDATE chat_session SET name = $1 WHERE id = $2")
+        .bind(&name)
+        .bind(&id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+#[tauri::command]
+// 删除会话及其全部对话记录
+pub async fn delete_chat_session(id: String) -> Result<bool, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM chat_turn WHERE session_id = $1")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+    let rows_affected = sqlx::query("DELETE FROM chat_session WHERE id = $1")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+    Ok(rows_affected > 0)
+}
+
+#[tauri::command]
+// 获取会话中的全部对话，按先后顺序排列
+pub async fn get_chat_turns(session_id: String) -> Result<Vec<ChatTurn>, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let rows = sqlx::query(
+        r#"SELECT id, seq, question, context, files, created_at
+           FROM chat_turn WHERE session_id = $1 ORDER BY seq"#,
+    )
+    .bind(&session_id)
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let files: String = row.get("files");
+            Ok(ChatTurn {
+                id: row.get("id"),
+                seq: row.get("seq"),
+                question: row.get("question"),
+                context: row.get("context"),
+                files: serde_json::from_str(&files)
+                    .map_err(|e| DataServiceError::Other(format!("对话记录格式错误: {}", e)))?,
+                created_at: row.get("created_at"),
+            })
+        })
+        .collect()
+}
+
+// 追加一轮对话并更新会话的更新时间
+pub async fn append_chat_turn(
+    session_id: &str,
+    question: &str,
+    context: &str,
+    files: &[FileModifyResult],
+) -> Result<(), DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let files = serde_json::to_string(files).map_err(|e| DataServiceError::Other(e.to_string()))?;
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"INSERT INTO chat_turn (id, session_id, seq, question, context, files, created_at)
+           VALUES ($1, $2, (SELECT COALESCE(MAX(seq), 0) + 1 FROM chat_turn WHERE session_id = $2),
+                   $3, $4, $5, strftime('%s', 'now'))"#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(session_id)
+    .bind(question)
+    .bind(context)
+    .bind(files)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE chat_session SET updated_at = strftime('%s', 'now') WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
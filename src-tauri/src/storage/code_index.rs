@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sqlx::{FromRow, Row};
+use uuid::Uuid;
+
+use super::{DataServiceError, DB_POOL};
+
+// 已索引文件的记录
+#[derive(Debug, FromRow)]
+pub struct IndexedFile {
+    pub path: String,
+    pub content_hash: String,
+    pub model: String,
+}
+
+// 待保存的代码片段
+#[derive(Debug)]
+pub struct NewCodeChunk {
+    pub symbol: Option<String>,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+// 已保存的代码片段及其向量
+#[derive(Debug)]
+pub struct StoredCodeChunk {
+    pub file_path: String,
+    pub symbol: Option<String>,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+// 获取某个项目下已索引的全部文件，以路径为key
+pub async fn get_indexed_files(
+    root: &str,
+) -> Result<HashMap<String, IndexedFile>, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let files = sqlx::query_as::<_, IndexedFile>(
+        "SELECT path, content_hash, model FROM code_index_file WHERE root = $1",
+    )
+    .bind(root)
+    .fetch_all(pool)
+    .await?;
+    Ok(files.into_iter().map(|f| (f.path.clone(), f)).collect())
+}
+
+// 替换某个文件的全部代码片段，并更新文件的索引记录
+pub async fn save_file_chunks(
+    root: &str,
+    path: &str,
+    content_hash: &str,
+    model: &str,
+    chunks: &[NewCodeChunk],
+) -> Result<(), DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM code_chunk WHERE file_path = $1")
+        .bind(path)
+        .execute(&mut *tx)
+        .await?;
+    for chunk in chunks {
+        sqlx::query(
+            r#"INSERT INTO code_chunk
+                (id, root, file_path, symbol, start_line, end_line, content, embedding)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(root)
+        .bind(path)
+        .bind(&chunk.symbol)
+        .bind(chunk.start_line)
+        .bind(chunk.end_line)
+        .bind(&chunk.content)
+        .bind(encode_embedding(&chunk.embedding))
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query(
+        r#"INSERT INTO code_index_file (path, root, content_hash, model, indexed_at)
+           VALUES ($1, $2, $3, $4, strftime('%s', 'now'))
+           ON CONFLICT(path) DO UPDATE SET
+               root = $2, content_hash = $3, model = $4, indexed_at = strftime('%s', 'now')"#,
+    )
+    .bind(path)
+    .bind(root)
+    .bind(content_hash)
+    .bind(model)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 删除某个文件（或某个目录下全部文件）的索引记录及代码片段
+pub async fn delete_indexed_path(path: &str) -> Result<(), DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    // 按前缀比较而非LIKE，避免目录名中的_和%被当作通配符
+    let children = format!("{}{}", path, std::path::MAIN_SEPARATOR);
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "DELETE FROM code_chunk WHERE file_path = $1 OR substr(file_path, 1, length($2)) = $2",
+    )
+    .bind(path)
+    .bind(&children)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM code_index_file WHERE path = $1 OR substr(path, 1, length($2)) = $2")
+        .bind(path)
+        .bind(&children)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 加载某个项目下的全部代码片段及向量
+pub async fn load_code_chunks(root: &str) -> Result<Vec<StoredCodeChunk>, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let rows = sqlx::query(
+        r#"SELECT file_path, symbol, start_line, end_line, content, embedding
+           FROM code_chunk WHERE root = $1"#,
+    )
+    .bind(root)
+    .fetch_all(pool)
+    .await?;
+    let chunks = rows
+        .into_iter()
+        .map(|row| StoredCodeChunk {
+            file_path: row.get("file_path"),
+            symbol: row.get("symbol"),
+            start_line: row.get("start_line"),
+            end_line: row.get("end_line"),
+            content: row.get("content"),
+            embedding: decode_embedding(row.get::<Vec<u8>, _>("embedding").as_slice()),
+        })
+        .collect();
+    Ok(chunks)
+}
+
+// 统计某个项目下的代码片段数量
+pub async fn count_code_chunks(root: &str) -> Result<i64, DataServiceError> {
+    let pool = DB_POOL.get().context("DB not initialized")?;
+    let count = sqlx::query_scalar("SELECT COUNT(*) FROM code_chunk WHERE root = $1")
+        .bind(root)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
@@ -0,0 +1,46 @@
+-- 每次启动时执行，只能包含幂等的语句，用于为已存在的数据库补充新增的表
+
+-- 代码索引：已索引的文件
+CREATE TABLE IF NOT EXISTS code_index_file (
+    path TEXT PRIMARY KEY,
+    root TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    model TEXT NOT NULL,
+    indexed_at INTEGER NOT NULL
+);
+
+-- 代码索引：按符号切分的代码片段及其向量
+CREATE TABLE IF NOT EXISTS code_chunk (
+    id TEXT PRIMARY KEY,
+    root TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    symbol TEXT,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_code_chunk_root ON code_chunk (root);
+CREATE INDEX IF NOT EXISTS idx_code_chunk_file ON code_chunk (file_path);
+
+-- 对话会话
+CREATE TABLE IF NOT EXISTS chat_session (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- 会话中的每一轮对话：问题、提交给LLM的上下文及本轮生成的文件（JSON数组）
+CREATE TABLE IF NOT EXISTS chat_turn (
+    id TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    question TEXT NOT NULL,
+    context TEXT NOT NULL,
+    files TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_chat_turn_session ON chat_turn (session_id);
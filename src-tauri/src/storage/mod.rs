@@ -5,6 +5,8 @@ use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
 
 static DB_POOL: OnceCell<SqlitePool> = OnceCell::new();
 
+pub mod chat_session;
+pub mod code_index;
 pub mod code_sample;
 pub mod datasource;
 pub mod sys_config;
@@ -42,6 +44,9 @@ pub async fn init_db() -> Result<()> {
         let init_sql = include_str!("init.sql");
         sqlx::query(init_sql).execute(&pool).await?;
     }
+    // 为已存在的数据库补充新增的表
+    let migration_sql = include_str!("migration.sql");
+    sqlx::query(migration_sql).execute(&pool).await?;
     // 将连接池存储在全局 OnceCell 中
     DB_POOL.set(pool).map_err(|_| "Failed to set pool").unwrap();
     Ok(())
@@ -1,37 +1,167 @@
 use crate::{
-    function::file::merge_paths,
+    function::file::{is_ignored_path, load_ignore_patterns, merge_paths},
     llm::{
-        agent::{build_agent, AIAgent},
-        context_builder::{CodeGenRequest, LLMContextBuilder},
-        extract_json_from_llm_response,
-        prompt::GENERATE_FILE_PROMPT,
-        FileModifyResult,
+        agent::{
+            create_profile_agent, resolve_llm_profile, AIAgent, ChatMessage, ChatReply,
+            GenerationParams, LLMProfile, LLMStep, OutputProtocol, VISION_NOT_SUPPORTED,
+        },
+        context_builder::{CodeGenRequest, FileIncludeMode, LLMContextBuilder},
+        continuation::{is_unterminated, stitch},
+        file_block::{parse_file_blocks, render_file_blocks, FILE_HEADER_PATTERN},
+        http::{describe_retry, LLMError, RetryListener},
+        image::ImageInput,
+        prompt::{
+            CONTINUE_PROMPT, FOLLOW_UP_PROMPT, FORMAT_CORRECTION_PROMPT,
+            GENERATE_FILE_BLOCK_PROMPT, GENERATE_FILE_PROMPT, PLANNED_FILE_PROMPT,
+            PLAN_FILES_PROMPT, PREDICT_TARGET_FILES_PROMPT, TOOL_GATHER_PROMPT,
+        },
+        redaction::{load_redaction_options, RedactionReport, Redactor},
+        structured::{parse_llm_json, FileList, OutputSchema},
+        tools::{describe_call, ContextTools},
+        FileModifyResult, PlannedFile,
     },
-    storage::sys_config::get_config,
-    task::TaskGenFile,
+    storage::{
+        chat_session::{append_chat_turn, get_chat_session, get_chat_turns, ChatTurn},
+        sys_config::get_config,
+    },
+    task::{FileChangeType, TaskGenFile},
 };
 
-use super::{Task, TaskLog, TaskResult};
-use std::{path::PathBuf, str::FromStr, sync::Arc};
+use super::{Task, TaskLog, TaskLogLevel, TaskResult};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::task::TaskLogLevel::*;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use log::error;
+use futures::{stream::FuturesUnordered, StreamExt};
+use log::{error, warn};
 use once_cell::sync::Lazy;
-use serde_json::from_str;
+use regex::Regex;
+use serde_json::{from_str, json};
 
 static LLM_CONTEXT_BUILDER: Lazy<Arc<LLMContextBuilder>> =
     Lazy::new(|| Arc::new(LLMContextBuilder::default()));
 
-async fn get_intent_agent() -> Result<Box<dyn AIAgent>> {
-    let agent = build_agent("").await?;
-    Ok(agent)
+/// 附加到上下文中的已有目标文件数量上限
+const MAX_EXISTING_TARGETS: usize = 10;
+
+/// 回复格式错误时最多请求的次数，重试时将错误的回复及解析错误反馈给模型
+const MAX_FORMAT_ATTEMPTS: usize = 3;
+
+/// 回复被截断时请求模型继续输出的最大次数
+const MAX_CONTINUATIONS: usize = 5;
+
+/// 流式输出中途断开时的重试次数，间隔按指数增长。发送请求时的错误已由HTTP层按LLM配置重试
+const MAX_TRANSPORT_RETRIES: u32 = 3;
+const TRANSPORT_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// 生成代码前调用工具获取上下文的默认最大轮数
+const DEFAULT_TOOL_STEPS: usize = 8;
+
+/// 继续会话时最多提交之前的对话轮数，以及这些对话的总字符数上限
+const MAX_HISTORY_TURNS: usize = 5;
+const MAX_HISTORY_CHARS: usize = 60_000;
+
+/// 按计划生成时默认最多同时进行的LLM调用数量
+const DEFAULT_CONCURRENCY: usize = 3;
+
+/// 流式输出时进度日志的最小间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// 没有找到文件路径时，回复结尾保留该长度（字节数）待下次查找，其中可能有尚未输出完整的路径
+const PATH_SCAN_TAIL: usize = 1024;
+
+/// 回复中已完整输出的文件路径
+static FILE_PATH_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#""filePath"\s*:\s*"((?:[^"\\]|\\.)*)""#).unwrap());
+
+/// 按计划生成单个文件的提示词中的占位符
+static PLACEHOLDER_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{(plan|path|purpose)\}").unwrap());
+
+//按步骤选定LLM配置，接口重试的信息同时写入任务日志，避免限流等待期间任务长时间没有输出
+async fn resolve_profile(
+    step: LLMStep,
+    profile: Option<&str>,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<LLMProfile> {
+    let mut profile = resolve_llm_profile(step, profile).await?;
+    let sender = sender.clone();
+    profile.provider.http.on_retry = Some(RetryListener::new(move |error, delay, retry| {
+        let _ = sender.try_send(TaskLog::new(describe_retry(error, delay, retry), Warn));
+    }));
+    Ok(profile)
+}
+
+async fn get_intent_agent(
+    profile: Option<&str>,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<Box<dyn AIAgent>> {
+    let profile = resolve_profile(LLMStep::Intent, profile, sender).await?;
+    create_profile_agent(LLMStep::Intent, &profile, None, "")
+}
+
+async fn get_tool_gather_agent(
+    profile: Option<&str>,
+    params: &GenerationParams,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<Box<dyn AIAgent>> {
+    let profile = resolve_profile(LLMStep::Generation, profile, sender).await?;
+    create_profile_agent(
+        LLMStep::Generation,
+        &profile,
+        Some(params),
+        TOOL_GATHER_PROMPT,
+    )
 }
 
-async fn get_code_generate_agent() -> Result<Box<dyn AIAgent>> {
-    let agent = build_agent(GENERATE_FILE_PROMPT).await?;
-    Ok(agent)
+//计划使用代码生成的LLM配置，返回的回复格式用于还原会话中之前的回复
+async fn get_plan_agent(
+    profile: Option<&str>,
+    params: &GenerationParams,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<(Box<dyn AIAgent>, OutputProtocol)> {
+    let profile = resolve_profile(LLMStep::Generation, profile, sender).await?;
+    let agent = create_profile_agent(
+        LLMStep::Generation,
+        &profile,
+        Some(params),
+        PLAN_FILES_PROMPT,
+    )?;
+    Ok((agent, profile.provider.output_protocol))
+}
+
+async fn get_target_predict_agent(
+    profile: Option<&str>,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<Box<dyn AIAgent>> {
+    let profile = resolve_profile(LLMStep::Intent, profile, sender).await?;
+    create_profile_agent(LLMStep::Intent, &profile, None, PREDICT_TARGET_FILES_PROMPT)
+}
+
+//提示词及回复的解析方式取决于所选LLM配置的回复格式
+async fn get_code_generate_agent(
+    profile: Option<&str>,
+    params: &GenerationParams,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<(Box<dyn AIAgent>, OutputProtocol)> {
+    let profile = resolve_profile(LLMStep::Generation, profile, sender).await?;
+    let protocol = profile.provider.output_protocol;
+    let preamble = match protocol {
+        OutputProtocol::Json => GENERATE_FILE_PROMPT,
+        OutputProtocol::FileBlock => GENERATE_FILE_BLOCK_PROMPT,
+    };
+    let agent = create_profile_agent(LLMStep::Generation, &profile, Some(params), preamble)?;
+    Ok((agent, protocol))
 }
 
 pub struct CodeGenTask {
@@ -82,35 +212,83 @@ impl Task for CodeGenTask {
         self.check_cancelled()?;
         self.send_log(&sender, "开始执行代码生成任务").await?;
 
-        let intent = self.analyze_intent(&sender).await?;
-        if intent != Intent::CodeGen {
-            return Err(anyhow!("当前不支持该类型的问题处理"));
+        // 按已确认的计划生成时，意图已在生成计划时分析过
+        if self.req.plan.is_none() {
+            let intent = self.analyze_intent(&sender).await?;
+            if intent != Intent::CodeGen {
+                return Err(anyhow!("当前不支持该类型的问题处理"));
+            }
+        }
+        if self.req.plan_only {
+            return self.plan_files(&sender).await;
         }
 
-        let context = self.build_context(&sender).await?;
-        let agent = get_code_generate_agent().await?;
-        const MAX_RETRIES: usize = 3;
-        let mut retry_count = 0;
-        loop {
-            let res = self.query_llm(&sender, &agent, &context).await?;
-            match self.process_llm_response(&res).await {
-                Ok(result) => break Ok(result),
-                Err(e) => {
-                    retry_count += 1;
-                    if retry_count >= MAX_RETRIES {
-                        break Err(e);
-                    }
-                    self.send_log(
-                        &sender,
-                        &format!(
-                            "LLM回复格式错误，正在重试 ({}/{})",
-                            retry_count, MAX_RETRIES
-                        ),
-                    )
-                    .await?;
+        let (agent, protocol) = get_code_generate_agent(
+            self.req.llm_profile.as_deref(),
+            &self.req.generation_params,
+            &sender,
+        )
+        .await?;
+        self.send_log(
+            &sender,
+            &format!("生成参数：{}", agent.generation_params().describe()),
+        )
+        .await?;
+        if protocol == OutputProtocol::FileBlock {
+            self.send_log(&sender, "回复格式：文件块").await?;
+        }
+        let images = self.load_images(&sender).await?;
+        if !images.is_empty() && !agent.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        let history = self.load_history(&sender).await?;
+        let (messages, context, mut redactor) = self
+            .build_messages(&sender, &history, protocol, &images)
+            .await?;
+        let files = match self.req.plan.as_deref() {
+            Some(plan) => {
+                self.generate_planned(
+                    &sender,
+                    agent.as_ref(),
+                    protocol,
+                    &messages,
+                    &mut redactor,
+                    plan,
+                )
+                .await?
+            }
+            None => {
+                self.generate_files(
+                    &sender,
+                    agent.as_ref(),
+                    protocol,
+                    messages,
+                    &redactor.report,
+                    "",
+                )
+                .await?
+            }
+        };
+        let files = match &self.req.session_id {
+            Some(session_id) => {
+                if let Err(e) =
+                    append_chat_turn(session_id, &self.req.question, &context, &files).await
+                {
+                    warn!("保存会话记录失败: {}", e);
+                    self.send_warn(&sender, format!("保存会话记录失败：{}", e))
+                        .await?;
+                }
+                if self.req.continue_session {
+                    // 之前各轮没有路径的文件无法与本轮的文件对应，合并时全部保留
+                    let previous = history.into_iter().flat_map(|turn| turn.files);
+                    merge_files(previous.chain(files))
+                } else {
+                    files
                 }
             }
-        }
+            None => files,
+        };
+        self.to_task_result(files).await
     }
 
     async fn cancel(&mut self) -> anyhow::Result<()> {
@@ -131,39 +309,671 @@ impl CodeGenTask {
         Ok(())
     }
 
+    async fn send_warn(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        message: String,
+    ) -> Result<()> {
+        self.check_cancelled()?;
+        sender.send(TaskLog::new(message, Warn)).await?;
+        Ok(())
+    }
+
+    async fn send_reply_warnings(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        reply: &ChatReply,
+    ) -> Result<()> {
+        for warning in &reply.warnings {
+            self.send_warn(sender, warning.clone()).await?;
+        }
+        Ok(())
+    }
+
     async fn analyze_intent(&self, sender: &tokio::sync::mpsc::Sender<TaskLog>) -> Result<Intent> {
         self.send_log(sender, "正在分析用户意图").await?;
-        let intent = analyze_intent(&self.req.question).await?;
+        // 问题中可能粘贴了密钥、连接串等，与上下文一样脱敏后再提交
+        let question = ContextRedactor::load().await?.redact(&self.req.question);
+        let intent = analyze_intent(&question, self.req.llm_profile.as_deref(), sender).await?;
         Ok(intent)
     }
 
-    async fn build_context(&self, sender: &tokio::sync::mpsc::Sender<TaskLog>) -> Result<String> {
+    //读取所属会话之前的对话，未指定会话或不继续会话时为空
+    async fn load_history(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+    ) -> Result<Vec<ChatTurn>> {
+        let Some(session_id) = self
+            .req
+            .session_id
+            .as_ref()
+            .filter(|_| self.req.continue_session)
+        else {
+            return Ok(Vec::new());
+        };
+        let session = get_chat_session(session_id)
+            .await
+            .map_err(|e| anyhow!("读取会话失败: {}, 错误: {}", session_id, e))?;
+        let turns = get_chat_turns(session_id.clone()).await?;
+        if !turns.is_empty() {
+            self.send_log(
+                sender,
+                &format!("在会话“{}”中继续，已有{}轮对话", session.name, turns.len()),
+            )
+            .await?;
+        }
+        Ok(turns)
+    }
+
+    //构建提交给LLM的消息：会话中最近几轮的问题及生成的文件，以及本轮的完整上下文。
+    //消息在提交之前替换其中的密码、密钥等敏感信息，返回的本轮上下文为原文，用于保存到会话中；
+    //返回的脱敏器用于继续替换之后追加的内容并还原回复中的占位符
+    async fn build_messages(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        history: &[ChatTurn],
+        protocol: OutputProtocol,
+        images: &[ImageInput],
+    ) -> Result<(Vec<ChatMessage>, String, ContextRedactor)> {
         self.send_log(sender, "正在构建与问题相关联的上下文")
             .await?;
-        let context = LLM_CONTEXT_BUILDER.build(&self.req).await?;
+        let mut redactor = ContextRedactor::load().await?;
+        let mut context = LLM_CONTEXT_BUILDER.build(&self.req).await?;
+        if self.req.auto_detect_dir || self.req.plan.is_some() {
+            let submitted = redactor.redact(&context);
+            for (path, content) in self.find_existing_targets(sender, &submitted).await? {
+                context.push_str(&format!(
+                    "##将被修改的已有文件：{}\n```\n{}\n```",
+                    path.display(),
+                    content
+                ));
+            }
+        }
+        if self.req.tool_calling {
+            let submitted = redactor.redact(&context);
+            let gathered = self
+                .gather_context(sender, &submitted, &mut redactor)
+                .await?;
+            context.push_str(&gathered);
+        }
+        let (recent, omitted) = recent_history(history, protocol);
+        if omitted > 0 {
+            self.send_warn(
+                sender,
+                format!("会话中较早的{}轮对话超出长度限制，未提交给LLM", omitted),
+            )
+            .await?;
+        }
+        let mut messages = Vec::new();
+        for (question, files) in recent {
+            messages.push(ChatMessage::user(&redactor.redact(&question), &[]));
+            messages.push(ChatMessage::assistant(&redactor.redact(&files)));
+        }
+        let mut current = redactor.redact(&context);
+        if !history.is_empty() {
+            current.push_str(FOLLOW_UP_PROMPT);
+        }
+        messages.push(ChatMessage::user(&current, images));
+        if !redactor.report.is_empty() {
+            self.send_warn(
+                sender,
+                format!(
+                    "已对上下文中的敏感信息进行脱敏：{}",
+                    redactor.report.summary()
+                ),
+            )
+            .await?;
+        }
         self.send_log(sender, "上下文已构建完成").await?;
-        Ok(context)
+        Ok((messages, context, redactor))
     }
 
-    async fn query_llm(
+    //由模型调用工具按需获取上下文，工具结果在提交给模型之前脱敏。
+    //返回需要附加到代码生成上下文中的工具结果及模型的结论（原文），获取失败不影响代码生成，仅记录警告
+    async fn gather_context(
         &self,
         sender: &tokio::sync::mpsc::Sender<TaskLog>,
-        agent: &Box<dyn AIAgent>,
         context: &str,
+        redactor: &mut ContextRedactor,
     ) -> Result<String> {
-        self.send_log(sender, "开始提交问题到LLM").await?;
-        let res = agent.generate_response(context).await?;
-        self.send_log(sender, "LLM已完成回答").await?;
+        let agent = get_tool_gather_agent(
+            self.req.llm_profile.as_deref(),
+            &self.req.generation_params,
+            sender,
+        )
+        .await?;
+        if !agent.supports_tools() {
+            self.send_warn(
+                sender,
+                "当前LLM供应商不支持工具调用，已跳过按需获取上下文".to_string(),
+            )
+            .await?;
+            return Ok(String::new());
+        }
+        self.send_log(sender, "正在由LLM调用工具获取上下文").await?;
+        let tools = ContextTools::new(&self.req.current_src_dir).await?;
+        let specs = ContextTools::specs();
+        let max_steps = self.req.max_tool_steps.unwrap_or(DEFAULT_TOOL_STEPS);
+        let mut messages = vec![ChatMessage::user(context, &[])];
+        let mut gathered = String::new();
+        let mut step = 0;
+        loop {
+            self.check_cancelled()?;
+            let reply = match agent.generate_tool_chat(&messages, &specs).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    warn!("调用工具获取上下文失败: {}", e);
+                    self.send_warn(sender, format!("调用工具获取上下文失败：{}", e))
+                        .await?;
+                    break;
+                }
+            };
+            self.send_reply_warnings(sender, &reply).await?;
+            if reply.tool_calls.is_empty() {
+                if !reply.text.trim().is_empty() {
+                    gathered.push_str(&format!(
+                        "##工具获取的上下文结论：\n{}\n",
+                        redactor.restore(reply.text.trim())
+                    ));
+                }
+                break;
+            }
+            if step >= max_steps {
+                self.send_warn(
+                    sender,
+                    format!("工具调用已达到{}轮上限，停止获取上下文", max_steps),
+                )
+                .await?;
+                break;
+            }
+            step += 1;
+            messages.push(ChatMessage::assistant_tool_calls(
+                &reply.text,
+                &reply.tool_calls,
+            ));
+            for call in &reply.tool_calls {
+                let description = describe_call(call);
+                self.send_log(sender, &format!("调用工具：{}", description))
+                    .await?;
+                let result = match tools.execute(call).await {
+                    Ok(result) => {
+                        gathered.push_str(&format!(
+                            "##工具获取的上下文：{}\n```\n{}\n```\n",
+                            description, result
+                        ));
+                        redactor.redact(&result)
+                    }
+                    Err(e) => {
+                        self.send_warn(sender, format!("工具调用失败：{}，{}", description, e))
+                            .await?;
+                        format!("工具调用失败：{}", e)
+                    }
+                };
+                messages.push(ChatMessage::tool_result(call, &result));
+            }
+        }
+        self.send_log(sender, &format!("工具调用完成，共{}轮", step))
+            .await?;
+        Ok(gathered)
+    }
+
+    async fn load_images(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+    ) -> Result<Vec<ImageInput>> {
+        let images = LLM_CONTEXT_BUILDER.load_images(&self.req).await?;
+        if !images.is_empty() {
+            self.send_log(sender, &format!("已加载{}张图片", images.len()))
+                .await?;
+        }
+        Ok(images)
+    }
+
+    //预测需要生成或修改的文件，返回其中已存在的文件及其内容，以便LLM在原文件基础上修改而不是重新生成。
+    //按计划生成时直接使用计划中的文件。预测失败不影响代码生成，仅记录警告
+    async fn find_existing_targets(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        context: &str,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        self.send_log(sender, "正在分析需要修改的已有文件").await?;
+        let predicted = match &self.req.plan {
+            Some(plan) => plan.iter().map(|file| file.path.clone()).collect(),
+            None => {
+                match predict_target_files(context, self.req.llm_profile.as_deref(), sender).await {
+                    Ok(paths) => paths,
+                    Err(e) => {
+                        warn!("预测目标文件失败: {}", e);
+                        self.send_warn(sender, format!("预测目标文件失败: {}", e))
+                            .await?;
+                        return Ok(Vec::new());
+                    }
+                }
+            }
+        };
+        let excludes = load_ignore_patterns().await?;
+        let root = fs::canonicalize(&self.req.current_src_dir)
+            .unwrap_or_else(|_| PathBuf::from(&self.req.current_src_dir));
+        let mut existing: Vec<(PathBuf, String)> = Vec::new();
+        for path in predicted {
+            let path = merge_paths(&self.req.current_src_dir, &path);
+            // 已完整引用的文件已经在上下文中
+            let referenced = self.req.resources.iter().any(|r| {
+                r.resource_type == "file"
+                    && r.include_mode == FileIncludeMode::Full
+                    && path == Path::new(&r.data)
+            });
+            if referenced || !path.is_file() || existing.iter().any(|(p, _)| *p == path) {
+                continue;
+            }
+            // 被忽略规则排除的文件（如.env、构建产物）不提交给LLM
+            if !is_visible_file(&root, &path, &excludes) {
+                warn!("已有文件被忽略规则排除，不附加到上下文: {}", path.display());
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(content) => existing.push((path, content)),
+                Err(e) => warn!("读取已有文件失败: {}, 错误: {}", path.display(), e),
+            }
+            if existing.len() >= MAX_EXISTING_TARGETS {
+                break;
+            }
+        }
+        if !existing.is_empty() {
+            let names: Vec<String> = existing
+                .iter()
+                .map(|(p, _)| p.display().to_string())
+                .collect();
+            self.send_log(
+                sender,
+                &format!("以下已有文件将在原内容基础上修改：{}", names.join(", ")),
+            )
+            .await?;
+        }
+        Ok(existing)
+    }
+
+    //生成计划：由模型列出需要生成的文件、用途及依赖关系，用户确认或修改后再按计划生成
+    async fn plan_files(&self, sender: &tokio::sync::mpsc::Sender<TaskLog>) -> Result<TaskResult> {
+        let (agent, protocol) = get_plan_agent(
+            self.req.llm_profile.as_deref(),
+            &self.req.generation_params,
+            sender,
+        )
+        .await?;
+        let images = self.load_images(sender).await?;
+        if !images.is_empty() && !agent.supports_vision() {
+            return Err(anyhow!(VISION_NOT_SUPPORTED));
+        }
+        let history = self.load_history(sender).await?;
+        let (messages, _, redactor) = self
+            .build_messages(sender, &history, protocol, &images)
+            .await?;
+        self.send_log(sender, "正在生成计划").await?;
+        let reply = agent
+            .generate_chat_stream(&messages, Some(&OutputSchema::file_plan()), &mut |_| {
+                self.check_cancelled()
+            })
+            .await?;
+        self.send_reply_warnings(sender, &reply).await?;
+        if let Some(usage) = reply.usage.describe() {
+            self.send_log(sender, &format!("计划生成完成，{}", usage))
+                .await?;
+        }
+        let files: Vec<PlannedFile> = parse_llm_json::<FileList<PlannedFile>>(&reply.text)
+            .map_err(|e| {
+                error!("生成计划解析失败: {:?}\n原始内容: {}", e, reply.text);
+                e
+            })?
+            .into_files()
+            .into_iter()
+            .map(|file| PlannedFile {
+                purpose: redactor.restore(&file.purpose),
+                ..file
+            })
+            .collect();
+        if files.is_empty() {
+            return Err(anyhow!("LLM没有规划任何需要生成的文件"));
+        }
+        self.send_log(
+            sender,
+            &format!("已生成计划，共{}个文件，请确认后开始生成", files.len()),
+        )
+        .await?;
+        Ok(TaskResult::Plan { files })
+    }
+
+    //提交消息并解析回复中的文件。调用失败时重新提交相同的内容；
+    //回复格式错误时重复提交只会得到相同的错误，改为请求模型纠正。label为日志的前缀
+    async fn generate_files(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        agent: &dyn AIAgent,
+        protocol: OutputProtocol,
+        mut messages: Vec<ChatMessage>,
+        redaction: &RedactionReport,
+        label: &str,
+    ) -> Result<Vec<FileModifyResult>> {
+        // 会话之前的对话及本轮的问题，纠正格式时只替换之后的消息
+        let base_messages = messages.len();
+        let mut format_attempts = 0;
+        let mut transport_retries = 0;
+        loop {
+            let res = match self
+                .query_llm(sender, agent, protocol, &messages, label)
+                .await
+            {
+                Ok(res) => res,
+                Err(e) => {
+                    self.check_cancelled()?;
+                    // 认证失败、配额不足、上下文超长等错误重试无效，HTTP层已经重试过的错误也不再重试
+                    let retryable = e
+                        .downcast_ref::<LLMError>()
+                        .is_some_and(|e| e.is_retryable() && e.retries == 0);
+                    if !retryable || transport_retries >= MAX_TRANSPORT_RETRIES {
+                        return Err(e);
+                    }
+                    transport_retries += 1;
+                    let delay = TRANSPORT_RETRY_DELAY * 2u32.pow(transport_retries - 1);
+                    warn!("调用LLM失败: {}", e);
+                    self.send_warn(
+                        sender,
+                        format!(
+                            "{}调用LLM失败：{}，{}秒后重试 ({}/{})",
+                            label,
+                            e,
+                            delay.as_secs(),
+                            transport_retries,
+                            MAX_TRANSPORT_RETRIES
+                        ),
+                    )
+                    .await?;
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            };
+            match self.parse_llm_response(&res, protocol, redaction) {
+                Ok(files) => return Ok(files),
+                Err(e) => {
+                    format_attempts += 1;
+                    if format_attempts >= MAX_FORMAT_ATTEMPTS {
+                        return Err(e);
+                    }
+                    self.send_warn(
+                        sender,
+                        format!(
+                            "{}LLM回复格式错误：{}，正在请求LLM纠正 ({}/{})",
+                            label,
+                            e,
+                            format_attempts,
+                            MAX_FORMAT_ATTEMPTS - 1
+                        ),
+                    )
+                    .await?;
+                    // 只保留最近一次错误的回复，避免上下文随重试不断增长
+                    messages.truncate(base_messages);
+                    messages.push(ChatMessage::assistant(&res));
+                    messages.push(ChatMessage::user(
+                        &FORMAT_CORRECTION_PROMPT.replace("{error}", &e.to_string()),
+                        &[],
+                    ));
+                }
+            }
+        }
+    }
+
+    //按计划逐个文件生成，每个文件单独调用LLM，同时进行的调用数量不超过上限。
+    //依赖的文件先生成并附加到依赖它的文件的上下文中；部分文件生成失败时返回其余文件，全部失败时返回错误
+    async fn generate_planned(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        agent: &dyn AIAgent,
+        protocol: OutputProtocol,
+        messages: &[ChatMessage],
+        redactor: &mut ContextRedactor,
+        plan: &[PlannedFile],
+    ) -> Result<Vec<FileModifyResult>> {
+        if plan.is_empty() {
+            return Err(anyhow!("生成计划中没有任何文件"));
+        }
+        let total = plan.len();
+        let concurrency = self
+            .req
+            .max_concurrency
+            .unwrap_or(DEFAULT_CONCURRENCY)
+            .max(1);
+        self.send_log(
+            sender,
+            &format!("按计划生成{}个文件，最多同时生成{}个", total, concurrency),
+        )
+        .await?;
+        let plan_text = render_plan(plan);
+        // 未提供目录结构时路径为空，不能据此确定依赖的文件
+        let index_of = |path: &str| match path.trim().is_empty() {
+            true => None,
+            false => plan.iter().position(|file| file.path == path),
+        };
+        // 每个文件依赖的计划中其他文件的序号
+        let dependencies: Vec<Vec<usize>> = plan
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                file.depends_on
+                    .iter()
+                    .filter_map(|path| index_of(path))
+                    .filter(|dep| *dep != i)
+                    .collect()
+            })
+            .collect();
+        let mut generated: Vec<Option<Vec<FileModifyResult>>> = vec![None; total];
+        let mut finished = vec![false; total];
+        let mut failures = Vec::new();
+        let mut waiting: Vec<usize> = (0..total).collect();
+        let mut ready = VecDeque::new();
+        let mut running = FuturesUnordered::new();
+        loop {
+            // 依赖的文件都已结束（成功或失败）的文件即可开始生成，不必等待其他正在生成的文件
+            let (now_ready, rest): (Vec<usize>, Vec<usize>) = waiting
+                .iter()
+                .partition(|&&i| dependencies[i].iter().all(|&dep| finished[dep]));
+            waiting = rest;
+            ready.extend(now_ready);
+            if ready.is_empty() && running.is_empty() {
+                if waiting.is_empty() {
+                    break;
+                }
+                self.send_warn(
+                    sender,
+                    "计划中的文件存在循环依赖，剩余文件将忽略依赖关系生成".to_string(),
+                )
+                .await?;
+                ready.extend(std::mem::take(&mut waiting));
+            }
+            while running.len() < concurrency {
+                let Some(i) = ready.pop_front() else {
+                    break;
+                };
+                let dependency_files: Vec<&FileModifyResult> = dependencies[i]
+                    .iter()
+                    .filter_map(|&dep| generated[dep].as_ref())
+                    .flatten()
+                    .collect();
+                let messages = planned_file_messages(
+                    messages,
+                    &plan_text,
+                    &plan[i],
+                    &dependency_files,
+                    redactor,
+                );
+                // 之后生成的文件会继续增加脱敏记录，该文件只需还原其消息中已有的占位符
+                let report = redactor.report.clone();
+                running.push(async move {
+                    let label = format!("[{}/{}] ", i + 1, total);
+                    let path = display_path(&plan[i].path);
+                    let outcome: Result<Vec<FileModifyResult>> = async {
+                        self.send_log(sender, &format!("{}开始生成：{}", label, path))
+                            .await?;
+                        let files = self
+                            .generate_files(sender, agent, protocol, messages, &report, &label)
+                            .await?;
+                        self.send_log(sender, &format!("{}已生成：{}", label, path))
+                            .await?;
+                        Ok(files)
+                    }
+                    .await;
+                    (i, outcome)
+                });
+            }
+            let Some((i, outcome)) = running.next().await else {
+                continue;
+            };
+            self.check_cancelled()?;
+            finished[i] = true;
+            match outcome {
+                Ok(files) => generated[i] = Some(files),
+                Err(e) => {
+                    let path = display_path(&plan[i].path);
+                    warn!("按计划生成文件失败: {}, 错误: {}", path, e);
+                    self.send_warn(
+                        sender,
+                        format!("[{}/{}] 生成失败：{}，{}", i + 1, total, path, e),
+                    )
+                    .await?;
+                    failures.push(path);
+                }
+            }
+        }
+        if failures.len() == total {
+            return Err(anyhow!("计划中的文件全部生成失败"));
+        }
+        if !failures.is_empty() {
+            self.send_warn(
+                sender,
+                format!(
+                    "{}个文件生成失败，可修改计划后重新生成：{}",
+                    failures.len(),
+                    failures.join(", ")
+                ),
+            )
+            .await?;
+        }
+        Ok(merge_files(generated.into_iter().flatten().flatten()))
+    }
+
+    async fn query_llm(
+        &self,
+        sender: &tokio::sync::mpsc::Sender<TaskLog>,
+        agent: &dyn AIAgent,
+        protocol: OutputProtocol,
+        messages: &[ChatMessage],
+        label: &str,
+    ) -> Result<String> {
+        self.send_log(sender, &format!("{}开始提交问题到LLM", label))
+            .await?;
+        let mut progress =
+            StreamProgress::new(sender.clone(), self.is_cancelled.clone(), protocol, label);
+        // 文件块格式的回复不是JSON，不能要求结构化输出
+        let schema = match protocol {
+            OutputProtocol::Json => Some(OutputSchema::file_modify_results()),
+            OutputProtocol::FileBlock => None,
+        };
+        let reply = agent
+            .generate_chat_stream(messages, schema.as_ref(), &mut |delta| progress.push(delta))
+            .await?;
+        progress.flush().await?;
+        self.send_reply_warnings(sender, &reply).await?;
+        let mut usage = reply.usage;
+        let mut res = reply.text;
+        // 部分供应商不返回结束原因，同时根据内容是否完整判断
+        let mut truncated = reply.truncated || is_unterminated(&res, protocol);
+        let mut continuations = 0;
+        while truncated {
+            if continuations >= MAX_CONTINUATIONS {
+                self.send_warn(
+                    sender,
+                    format!(
+                        "{}已请求LLM继续输出{}次，回复仍不完整",
+                        label, MAX_CONTINUATIONS
+                    ),
+                )
+                .await?;
+                break;
+            }
+            continuations += 1;
+            self.send_log(
+                sender,
+                &format!(
+                    "{}LLM回复被截断，正在请求继续输出 ({}/{})",
+                    label, continuations, MAX_CONTINUATIONS
+                ),
+            )
+            .await?;
+            let mut conversation = messages.to_vec();
+            conversation.push(ChatMessage::assistant(&res));
+            conversation.push(ChatMessage::user(CONTINUE_PROMPT, &[]));
+            // 续写的内容只是结果的一部分，不能再按schema约束
+            let reply = agent
+                .generate_chat_stream(&conversation, None, &mut |delta| progress.push(delta))
+                .await?;
+            progress.flush().await?;
+            self.send_reply_warnings(sender, &reply).await?;
+            usage.add(&reply.usage);
+            if reply.text.trim().is_empty() {
+                break;
+            }
+            res = stitch(&res, &reply.text, protocol);
+            truncated = reply.truncated || is_unterminated(&res, protocol);
+        }
+        // 供应商未返回用量时按字符数估算
+        let usage = usage.describe().unwrap_or_else(|| {
+            format!("未返回token用量，约{}个token", progress.estimated_tokens())
+        });
+        self.send_log(
+            sender,
+            &format!(
+                "{}LLM已完成回答，共接收{}个字符，{}",
+                label, progress.chars, usage
+            ),
+        )
+        .await?;
         Ok(res)
     }
 
-    async fn process_llm_response(&self, response: &str) -> Result<TaskResult> {
-        let json_str = extract_json_from_llm_response(response)
-            .ok_or_else(|| anyhow!("LLM响应格式错误：{}", response))?;
-        let file_modify_results: Vec<FileModifyResult> = from_str(&json_str).map_err(|e| {
-            error!("JSON解析详细错误: {:?}\n原始JSON字符串: {}", e, json_str);
-            anyhow!("LLM返回数据格式错误: {}, 原始内容: {}", e, json_str)
+    //解析LLM的回复，文件内容中的脱敏占位符还原为原文
+    fn parse_llm_response(
+        &self,
+        response: &str,
+        protocol: OutputProtocol,
+        redaction: &RedactionReport,
+    ) -> Result<Vec<FileModifyResult>> {
+        let files = match protocol {
+            OutputProtocol::Json => {
+                parse_llm_json::<FileList<FileModifyResult>>(response).map(FileList::into_files)
+            }
+            OutputProtocol::FileBlock => parse_file_blocks(response),
+        }
+        .and_then(|files| match files.is_empty() {
+            // 没有任何文件通常是格式错误导致只解析出了部分内容，按格式错误请求纠正
+            true => Err(anyhow!("LLM回复中没有任何文件")),
+            false => Ok(files),
+        })
+        .map_err(|e| {
+            error!("LLM回复解析失败: {:?}\n原始内容: {}", e, response);
+            e
         })?;
+        Ok(files
+            .into_iter()
+            .map(|file| FileModifyResult {
+                file_content: redaction.restore(&file.file_content),
+                ..file
+            })
+            .collect())
+    }
+
+    async fn to_task_result(
+        &self,
+        file_modify_results: Vec<FileModifyResult>,
+    ) -> Result<TaskResult> {
         let root_dir = get_config("root_source_path".to_string())
             .await?
             .unwrap_or("".to_string());
@@ -171,14 +981,21 @@ impl CodeGenTask {
             .into_iter()
             .map(|result| {
                 let file_path = PathBuf::from(result.file_path.clone());
+                let path = merge_paths(&root_dir, &result.file_path);
+                let change_type = if !result.file_path.is_empty() && path.is_file() {
+                    FileChangeType::Modified
+                } else {
+                    FileChangeType::Created
+                };
                 TaskGenFile {
                     name: file_path
                         .file_name()
                         .and_then(|n| n.to_str())
                         .unwrap_or("unknown")
                         .to_string(),
-                    path: Some(merge_paths(&root_dir, &result.file_path)),
+                    path: Some(path),
                     content: result.file_content,
+                    change_type,
                 }
             })
             .collect();
@@ -186,7 +1003,157 @@ impl CodeGenTask {
     }
 }
 
-async fn analyze_intent(user_question: &str) -> Result<Intent> {
+//按路径合并文件，同一路径以后出现的为准。会话中每轮只输出新增或修改的文件，依次合并得到当前的完整结果。
+//未提供目录结构时路径为空，无法判断是否为同一文件，全部保留
+fn merge_files(files: impl IntoIterator<Item = FileModifyResult>) -> Vec<FileModifyResult> {
+    let mut merged: Vec<FileModifyResult> = Vec::new();
+    for file in files {
+        let existing = match file.file_path.trim().is_empty() {
+            true => None,
+            false => merged.iter_mut().find(|f| f.file_path == file.file_path),
+        };
+        match existing {
+            Some(existing) => *existing = file,
+            None => merged.push(file),
+        }
+    }
+    merged
+}
+
+//计划的文本形式，每行一个文件及其用途、依赖
+fn render_plan(plan: &[PlannedFile]) -> String {
+    plan.iter()
+        .map(|file| {
+            let mut line = format!("- {}: {}", display_path(&file.path), file.purpose);
+            if !file.depends_on.is_empty() {
+                line.push_str(&format!(" (depends on: {})", file.depends_on.join(", ")));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+//按计划生成单个文件的消息：在共同的上下文之后附加计划、本次生成的文件及已生成的依赖文件
+fn planned_file_messages(
+    base: &[ChatMessage],
+    plan_text: &str,
+    file: &PlannedFile,
+    dependencies: &[&FileModifyResult],
+    redactor: &mut ContextRedactor,
+) -> Vec<ChatMessage> {
+    // 计划及用途由用户编辑，一次替换所有占位符，其中出现的占位符文本不会被再次替换
+    let path = display_path(&file.path);
+    let mut instruction = PLACEHOLDER_PATTERN
+        .replace_all(PLANNED_FILE_PROMPT, |caps: &regex::Captures| {
+            match &caps[1] {
+                "plan" => plan_text.to_string(),
+                "path" => path.clone(),
+                _ => file.purpose.clone(),
+            }
+        })
+        .into_owned();
+    for dependency in dependencies {
+        instruction.push_str(&format!(
+            "##已生成的依赖文件：{}\n```\n{}\n```\n",
+            dependency.file_path, dependency.file_content
+        ));
+    }
+    let mut messages = base.to_vec();
+    if let Some(last) = messages.last_mut() {
+        last.content.push_str(&redactor.redact(&instruction));
+    }
+    messages
+}
+
+//文件在源码目录中且未被忽略规则排除
+fn is_visible_file(root: &Path, path: &Path, excludes: &[String]) -> bool {
+    let Ok(path) = fs::canonicalize(path) else {
+        return false;
+    };
+    match is_ignored_path(root, &path, excludes) {
+        Ok(ignored) => !ignored,
+        Err(e) => {
+            warn!("检查忽略规则失败: {}", e);
+            false
+        }
+    }
+}
+
+//未提供目录结构时计划中的路径为空
+fn display_path(path: &str) -> String {
+    match path.trim().is_empty() {
+        true => "（未指定路径）".to_string(),
+        false => path.to_string(),
+    }
+}
+
+//会话中之前的对话只提交问题及生成的文件，当时的上下文不再重复提交。从最近一轮开始向前保留，
+//超过轮数或长度上限的较早对话不提交，最近一轮的文件即当前结果，总是保留。返回保留的对话及省略的轮数
+fn recent_history(
+    history: &[ChatTurn],
+    protocol: OutputProtocol,
+) -> (Vec<(String, String)>, usize) {
+    let mut recent = Vec::new();
+    let mut chars = 0;
+    for turn in history.iter().rev() {
+        let question = format!("#用户问题：\"{}\"\n", turn.question);
+        let files = render_files(&turn.files, protocol);
+        chars += question.chars().count() + files.chars().count();
+        if !recent.is_empty() && (recent.len() >= MAX_HISTORY_TURNS || chars > MAX_HISTORY_CHARS) {
+            break;
+        }
+        recent.push((question, files));
+    }
+    let omitted = history.len() - recent.len();
+    recent.reverse();
+    (recent, omitted)
+}
+
+//按本次使用的回复格式还原会话中之前的回复
+fn render_files(files: &[FileModifyResult], protocol: OutputProtocol) -> String {
+    match protocol {
+        OutputProtocol::Json => json!({ "files": files }).to_string(),
+        OutputProtocol::FileBlock => render_file_blocks(files),
+    }
+}
+
+/// 替换提交给LLM的内容中的敏感信息，同一个值在各条消息中使用相同的占位符
+struct ContextRedactor {
+    redactor: Option<Redactor>,
+    report: RedactionReport,
+}
+
+impl ContextRedactor {
+    async fn load() -> Result<Self> {
+        let options = load_redaction_options().await?;
+        let redactor = match options.enabled {
+            true => Some(Redactor::new(&options)?),
+            false => None,
+        };
+        Ok(Self {
+            redactor,
+            report: RedactionReport::default(),
+        })
+    }
+
+    fn redact(&mut self, text: &str) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.redact(text, &mut self.report),
+            None => text.to_string(),
+        }
+    }
+
+    fn restore(&self, text: &str) -> String {
+        self.report.restore(text)
+    }
+}
+
+async fn analyze_intent(
+    user_question: &str,
+    profile: Option<&str>,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<Intent> {
     let prompt = format!(
         "Analyze the user's question and determine the intent. 
         Question: \"{}\"
@@ -202,9 +1169,277 @@ async fn analyze_intent(user_question: &str) -> Result<Intent> {
         user_question
     );
 
-    let agent = get_intent_agent().await?;
+    let agent = get_intent_agent(profile, sender).await?;
     let llm_response = agent.generate_response(&prompt).await?;
     let llm_response = llm_response.trim().trim_matches('"').to_string();
     Intent::from_str(&llm_response)
         .map_err(|_| anyhow!("LLM returned invalid intent format: \"{}\"", llm_response))
 }
+
+//根据上下文预测需要生成或修改的文件路径（相对于源码根目录）
+async fn predict_target_files(
+    context: &str,
+    profile: Option<&str>,
+    sender: &tokio::sync::mpsc::Sender<TaskLog>,
+) -> Result<Vec<String>> {
+    let agent = get_target_predict_agent(profile, sender).await?;
+    let response = agent
+        .generate_json_response(context, &[], &OutputSchema::target_files())
+        .await?;
+    Ok(parse_llm_json::<FileList<String>>(&response)?.into_files())
+}
+
+/// 跟踪LLM的流式输出：定期报告已接收的内容长度，回复中出现新的文件路径时立即报告
+struct StreamProgress {
+    sender: tokio::sync::mpsc::Sender<TaskLog>,
+    is_cancelled: Arc<AtomicBool>,
+    protocol: OutputProtocol,
+    /// 日志的前缀，按计划同时生成多个文件时用于区分
+    label: String,
+    text: String,
+    chars: usize,
+    /// text中已查找过文件路径的位置
+    scanned: usize,
+    last_report: Instant,
+    /// 通道已满时暂存的文件路径日志，之后按顺序重试，回答结束时等待全部送出
+    pending: VecDeque<TaskLog>,
+}
+
+impl StreamProgress {
+    fn new(
+        sender: tokio::sync::mpsc::Sender<TaskLog>,
+        is_cancelled: Arc<AtomicBool>,
+        protocol: OutputProtocol,
+        label: &str,
+    ) -> Self {
+        Self {
+            sender,
+            is_cancelled,
+            protocol,
+            label: label.to_string(),
+            text: String::new(),
+            chars: 0,
+            scanned: 0,
+            last_report: Instant::now(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// 返回错误时停止接收，任务取消后不再等待剩余的回复
+    fn push(&mut self, delta: &str) -> Result<()> {
+        if self.is_cancelled.load(Ordering::Relaxed) {
+            return Err(anyhow!("任务已被取消"));
+        }
+        self.text.push_str(delta);
+        self.chars += delta.chars().count();
+        let pattern = match self.protocol {
+            OutputProtocol::Json => &*FILE_PATH_PATTERN,
+            OutputProtocol::FileBlock => &*FILE_HEADER_PATTERN,
+        };
+        loop {
+            // 文件块的标题行从行首开始，从行中间开始查找时先跳到下一行
+            if self.protocol == OutputProtocol::FileBlock
+                && self.scanned > 0
+                && !self.text[..self.scanned].ends_with('\n')
+            {
+                match self.text[self.scanned..].find('\n') {
+                    Some(i) => self.scanned += i + 1,
+                    None => break,
+                }
+            }
+            let Some(captures) = pattern.captures(&self.text[self.scanned..]) else {
+                self.skip_scanned();
+                break;
+            };
+            let raw = captures[1].to_string();
+            self.scanned += captures.get(0).unwrap().end();
+            // JSON中的路径需要反转义，文件块的标题行中是原样的路径
+            let path = match self.protocol {
+                OutputProtocol::Json => from_str::<String>(&format!("\"{}\"", raw)).unwrap_or(raw),
+                OutputProtocol::FileBlock => raw,
+            };
+            self.pending.push_back(TaskLog::new(
+                format!("{}正在生成文件：{}", self.label, path),
+                Info,
+            ));
+        }
+        self.try_flush();
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL && self.pending.is_empty() {
+            self.last_report = Instant::now();
+            self.log(
+                format!(
+                    "{}LLM正在回答，已接收{}个字符（约{}个token）",
+                    self.label,
+                    self.chars,
+                    self.estimated_tokens()
+                ),
+                Progress,
+            );
+        }
+        Ok(())
+    }
+
+    /// 中英文混合的代码大约每3个字符一个token，准确的用量在调用完成后由供应商返回
+    fn estimated_tokens(&self) -> usize {
+        self.chars / 3
+    }
+
+    /// 没有找到文件路径时只保留结尾部分待下次查找，避免每次收到内容都从头查找
+    fn skip_scanned(&mut self) {
+        let mut keep = self.text.len().saturating_sub(PATH_SCAN_TAIL);
+        while !self.text.is_char_boundary(keep) {
+            keep -= 1;
+        }
+        self.scanned = self.scanned.max(keep);
+    }
+
+    /// 不等待地发送暂存的文件路径日志，通道已满时保留剩余的日志
+    fn try_flush(&mut self) {
+        while let Some(log) = self.pending.pop_front() {
+            match self.sender.try_send(log) {
+                Ok(()) => {}
+                Err(tokio::sync::mpsc::error::TrySendError::Full(log)) => {
+                    self.pending.push_front(log);
+                    break;
+                }
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
+                    self.pending.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 等待暂存的文件路径日志全部送出
+    async fn flush(&mut self) -> Result<()> {
+        while let Some(log) = self.pending.pop_front() {
+            self.sender.send(log).await?;
+        }
+        Ok(())
+    }
+
+    /// 进度日志不等待发送，通道已满时直接丢弃
+    fn log(&self, message: String, level: TaskLogLevel) {
+        let _ = self.sender.try_send(TaskLog::new(message, level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, content: &str) -> FileModifyResult {
+        FileModifyResult {
+            file_path: path.to_string(),
+            file_content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn merge_keeps_files_without_path() {
+        let merged = merge_files(vec![
+            file("a.rs", "a1"),
+            file("", "x"),
+            file("", "y"),
+            file("a.rs", "a2"),
+        ]);
+        let contents: Vec<&str> = merged.iter().map(|f| f.file_content.as_str()).collect();
+        assert_eq!(contents, vec!["a2", "x", "y"]);
+    }
+
+    #[test]
+    fn session_merge_keeps_earlier_files_without_path() {
+        let previous = vec![file("", "class A {}"), file("b.rs", "b1")];
+        let current = vec![file("", "class B {}"), file("b.rs", "b2")];
+        let merged = merge_files(previous.into_iter().chain(current));
+        let contents: Vec<&str> = merged.iter().map(|f| f.file_content.as_str()).collect();
+        assert_eq!(contents, vec!["class A {}", "b2", "class B {}"]);
+    }
+
+    #[test]
+    fn ignored_targets_are_not_visible() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), ".env\n").unwrap();
+        for file in [".env", "src/main.rs", "target/out.rs"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let excludes = vec!["target".to_string()];
+        assert!(is_visible_file(&root, &root.join("src/main.rs"), &excludes));
+        assert!(!is_visible_file(&root, &root.join(".env"), &excludes));
+        assert!(!is_visible_file(
+            &root,
+            &root.join("target/out.rs"),
+            &excludes
+        ));
+        assert!(!is_visible_file(&root, &root.join("missing.rs"), &excludes));
+    }
+
+    fn progress(
+        protocol: OutputProtocol,
+        capacity: usize,
+    ) -> (StreamProgress, tokio::sync::mpsc::Receiver<TaskLog>) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(capacity);
+        let progress = StreamProgress::new(sender, Arc::new(AtomicBool::new(false)), protocol, "");
+        (progress, receiver)
+    }
+
+    fn messages(receiver: &mut tokio::sync::mpsc::Receiver<TaskLog>) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Ok(log) = receiver.try_recv() {
+            messages.push(log.message);
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn file_path_logs_are_not_dropped_when_channel_is_full() {
+        let (mut progress, mut receiver) = progress(OutputProtocol::FileBlock, 1);
+        progress
+            .push("### FILE: a.rs\n```\na\n```\n### FILE: b.rs\n```\nb\n```\n")
+            .unwrap();
+        progress.push("### FILE: c.rs\n").unwrap();
+        assert_eq!(messages(&mut receiver), vec!["正在生成文件：a.rs"]);
+        let flushing = tokio::spawn(async move { progress.flush().await });
+        let mut received = Vec::new();
+        while let Some(log) = receiver.recv().await {
+            received.push(log.message);
+        }
+        flushing.await.unwrap().unwrap();
+        assert_eq!(received, vec!["正在生成文件：b.rs", "正在生成文件：c.rs"]);
+    }
+
+    #[tokio::test]
+    async fn finds_paths_split_across_deltas_after_long_content() {
+        let (mut progress, mut receiver) = progress(OutputProtocol::Json, 16);
+        progress
+            .push("{\"files\": [{\"filePath\": \"a.rs\", \"fileContent\": \"")
+            .unwrap();
+        for _ in 0..100 {
+            progress.push(&"x".repeat(100)).unwrap();
+        }
+        // 已查找过的位置随内容推进，只保留结尾部分
+        assert!(progress.scanned >= progress.text.len() - PATH_SCAN_TAIL);
+        progress.push("\"}, {\"filePa").unwrap();
+        progress.push("th\": \"src/b.rs\"").unwrap();
+        assert_eq!(
+            messages(&mut receiver),
+            vec!["正在生成文件：a.rs", "正在生成文件：src/b.rs"]
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_file_headers_after_long_content() {
+        let (mut progress, mut receiver) = progress(OutputProtocol::FileBlock, 16);
+        progress.push("### FILE: a.rs\n```\n").unwrap();
+        progress.push(&"y".repeat(2 * PATH_SCAN_TAIL)).unwrap();
+        progress.push("print(\"FILE: fake.rs\")\n").unwrap();
+        progress.push("```\n### FILE: b.rs\n").unwrap();
+        assert_eq!(
+            messages(&mut receiver),
+            vec!["正在生成文件：a.rs", "正在生成文件：b.rs"]
+        );
+    }
+}
@@ -1,11 +1,16 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use log::warn;
 use once_cell::sync::Lazy;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Notify;
 use uuid::Uuid;
 
+use crate::llm::PlannedFile;
+
 pub mod code_gen_task;
 
 #[derive(Debug, Clone, Serialize)]
@@ -22,6 +27,8 @@ pub enum TaskLogLevel {
     Warn,
     Info,
     Error,
+    /// 进度信息，界面上连续的进度日志只显示最新的一条
+    Progress,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -36,6 +43,16 @@ pub struct TaskGenFile {
     pub name: String,
     pub path: Option<PathBuf>,
     pub content: String,
+    /// 新建文件还是修改已有文件
+    #[serde(rename = "changeType")]
+    pub change_type: FileChangeType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChangeType {
+    Created,
+    Modified,
 }
 
 impl TaskLog {
@@ -56,6 +73,8 @@ impl TaskLog {
 pub enum TaskResult {
     /// 代码生成任务的结果
     CodeGen { files: Vec<TaskGenFile> },
+    /// 生成计划，确认后按计划生成代码
+    Plan { files: Vec<PlannedFile> },
     /// 无返回值的任务
     Empty,
 }
@@ -70,27 +89,26 @@ struct TaskInfo {
     status: TaskStatus,
     logs: Vec<TaskLog>,
     task_result: TaskResult,
+    /// 取消信号，任务执行中收到信号时立即中止
+    cancel_signal: Arc<Notify>,
 }
 
-static TASKS: Lazy<tokio::sync::RwLock<HashMap<String, Box<dyn Task>>>> =
-    Lazy::new(|| tokio::sync::RwLock::new(HashMap::new()));
-
 static TASK_INFOS: Lazy<tokio::sync::RwLock<HashMap<String, TaskInfo>>> =
     Lazy::new(|| tokio::sync::RwLock::new(HashMap::new()));
 
-pub async fn execute_task(task: impl Task + 'static) -> Result<String> {
+pub async fn execute_task(mut task: impl Task + 'static) -> Result<String> {
     let task_id = Uuid::new_v4().to_string();
     // 使用异步通道并设置缓冲区大小
     let (log_sender, mut log_receiver) = tokio::sync::mpsc::channel(100);
 
+    let cancel_signal = Arc::new(Notify::new());
     let task_status = TaskInfo {
         status: TaskStatus::Pending,
         logs: Vec::new(),
         task_result: TaskResult::Empty,
+        cancel_signal: cancel_signal.clone(),
     };
 
-    //插入任务
-    TASKS.write().await.insert(task_id.clone(), Box::new(task));
     TASK_INFOS
         .write()
         .await
@@ -111,28 +129,40 @@ pub async fn execute_task(task: impl Task + 'static) -> Result<String> {
     //异步启动任务
     let task_id_clone = task_id.clone();
     tokio::spawn(async move {
-        let mut tasks = TASKS.write().await;
-        if let Some(task) = tasks.get_mut(&task_id_clone) {
-            update_task_status(&task_id_clone, TaskStatus::Running).await;
-            match task.start(log_sender.clone()).await {
-                Ok(result) => {
-                    update_task_status(&task_id_clone, TaskStatus::Completed).await;
-                    update_task_result(&task_id_clone, result).await;
-                    log_sender
-                        .send(TaskLog::new("任务执行完成", TaskLogLevel::Info))
-                        .await
-                        .unwrap();
-                }
-                Err(e) => {
-                    update_task_status(&task_id_clone, TaskStatus::Failed).await;
-                    log_sender
-                        .send(TaskLog::new(
-                            format!("任务执行失败: {}", e),
-                            TaskLogLevel::Error,
-                        ))
-                        .await
-                        .unwrap();
+        mark_task_running(&task_id_clone).await;
+        // 收到取消信号时直接丢弃执行中的任务，正在进行的LLM请求等随之中断
+        let result = tokio::select! {
+            result = task.start(log_sender.clone()) => Some(result),
+            _ = cancel_signal.notified() => None,
+        };
+        match result {
+            None => {
+                update_task_status(&task_id_clone, TaskStatus::Cancelled).await;
+                if let Err(e) = task.cancel().await {
+                    warn!("任务取消后清理失败: {}", e);
                 }
+                log_sender
+                    .send(TaskLog::new("任务已取消", TaskLogLevel::Warn))
+                    .await
+                    .unwrap();
+            }
+            Some(Ok(result)) => {
+                update_task_status(&task_id_clone, TaskStatus::Completed).await;
+                update_task_result(&task_id_clone, result).await;
+                log_sender
+                    .send(TaskLog::new("任务执行完成", TaskLogLevel::Info))
+                    .await
+                    .unwrap();
+            }
+            Some(Err(e)) => {
+                update_task_status(&task_id_clone, TaskStatus::Failed).await;
+                log_sender
+                    .send(TaskLog::new(
+                        format!("任务执行失败: {}", e),
+                        TaskLogLevel::Error,
+                    ))
+                    .await
+                    .unwrap();
             }
         }
     });
@@ -140,12 +170,14 @@ pub async fn execute_task(task: impl Task + 'static) -> Result<String> {
 }
 
 pub async fn cancel_task(task_id: String) -> Result<()> {
-    let mut tasks_mutex = TASKS.write().await;
-    let task = tasks_mutex
+    let mut task_infos = TASK_INFOS.write().await;
+    let info = task_infos
         .get_mut(&task_id)
         .ok_or_else(|| anyhow!("Task not found"))?;
-    update_task_status(&task_id, TaskStatus::Cancelled).await;
-    task.cancel().await?;
+    if matches!(info.status, TaskStatus::Pending | TaskStatus::Running) {
+        info.status = TaskStatus::Cancelled;
+        info.cancel_signal.notify_one();
+    }
     Ok(())
 }
 
@@ -196,6 +228,15 @@ async fn update_task_status(task_id: &str, status: TaskStatus) {
     }
 }
 
+// 只从等待状态转为执行中，避免覆盖启动前已被取消的状态
+async fn mark_task_running(task_id: &str) {
+    if let Some(info) = TASK_INFOS.write().await.get_mut(task_id) {
+        if matches!(info.status, TaskStatus::Pending) {
+            info.status = TaskStatus::Running;
+        }
+    }
+}
+
 async fn update_task_result(task_id: &str, result: TaskResult) {
     if let Some(info) = TASK_INFOS.write().await.get_mut(task_id) {
         info.task_result = result;
@@ -1,15 +1,38 @@
 
 
 export interface ResourceMeta {
-    resourceType: 'table' | 'file'
+    resourceType: 'table' | 'file' | 'git_diff' | 'image' | 'document'
     name: string
     data: string
+    // 文件内容在添加为资源后是否发生了变化
+    changed?: boolean
+    // 文件资源的引用方式：完整内容、大纲或指定符号
+    includeMode?: FileIncludeMode
+    symbols?: string[]
+    // 需求文档的引用范围：PDF为页码范围，DOCX及Markdown为章节标题，为空时引用全文
+    selection?: string
+}
+
+export type FileIncludeMode = 'full' | 'outline' | 'symbols'
+
+
+export interface FsChange {
+    kind: 'created' | 'removed' | 'renamed' | 'modified'
+    paths: string[]
+}
+
+export interface ResourceChange {
+    path: string
+    changed: boolean
+    removed: boolean
 }
 
 export enum TaskLogLevel {
     Warn = "Warn",
     Info = "Info",
-    Error = "Error"
+    Error = "Error",
+    // 进度信息，连续的进度日志只显示最新的一条
+    Progress = "Progress"
 }
 
 export interface TaskLog {
@@ -22,10 +45,59 @@ export interface CodeFile {
     name: string
     path: string
     content: string
+    // 新建文件还是修改已有文件
+    changeType?: 'created' | 'modified'
     applied?: boolean
 }
 
+export interface FileTreeNode {
+    id: string
+    parentId: string
+    label: string
+    isFolder: boolean
+    isLeaf: boolean
+    size: number | null
+    fileType: string
+}
+
+export interface FileTreePage {
+    items: FileTreeNode[]
+    total: number
+    hasMore: boolean
+}
+
 export interface TaskResult {
     data?: any
     type: string
-}
\ No newline at end of file
+}
+export interface DiffFileStat {
+    path: string
+    status: string
+    additions: number
+    deletions: number
+}
+
+// 对话会话，时间为秒级时间戳
+export interface ChatSession {
+    id: string
+    name: string
+    createdAt: number
+    updatedAt: number
+    turnCount: number
+}
+
+// 会话中的一轮对话及本轮生成（新增或修改）的文件
+export interface ChatTurn {
+    id: string
+    seq: number
+    question: string
+    files: { filePath: string, fileContent: string }[]
+    createdAt: number
+}
+
+// 生成计划中的一个文件，dependsOn为依赖的计划中其他文件的路径
+export interface PlannedFile {
+    path: string
+    purpose: string
+    dependsOn: string[]
+}